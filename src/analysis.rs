@@ -0,0 +1,111 @@
+use crate::state::SenderInfo;
+use std::collections::{HashMap, HashSet};
+
+/// Mail exchanged with one address: messages the user sent to it (from the
+/// Sent folder) against messages received from it (from the scan).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactBalance {
+    pub address: String,
+    pub sent: usize,
+    pub received: usize,
+}
+
+/// Two views of who the user corresponds with unevenly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplyBalance {
+    /// People the user writes to who write back less often, worst first.
+    pub unanswered: Vec<ContactBalance>,
+    /// Senders the user has never written to, most prolific first.
+    pub ignored: Vec<ContactBalance>,
+}
+
+/// Correlates scanned senders with Sent-folder recipient counts. `sent_to`
+/// on each sender must already be filled in from `sent_counts`.
+pub fn reply_balance(
+    senders: &[SenderInfo],
+    sent_counts: &HashMap<String, usize>,
+    own_address: &str,
+) -> ReplyBalance {
+    let own_address = own_address.to_lowercase();
+    let known: HashSet<&str> = senders
+        .iter()
+        .flat_map(|s| s.addresses.iter().map(String::as_str))
+        .collect();
+
+    let mut unanswered = Vec::new();
+    let mut ignored = Vec::new();
+
+    for sender in senders {
+        let balance = ContactBalance {
+            address: sender.display.clone(),
+            sent: sender.sent_to,
+            received: sender.count,
+        };
+        if sender.sent_to == 0 {
+            ignored.push(balance);
+        } else if sender.count < sender.sent_to {
+            unanswered.push(balance);
+        }
+    }
+    // Recipients who never appeared in the scanned folder haven't replied.
+    for (address, &sent) in sent_counts {
+        if !known.contains(address.as_str()) && *address != own_address {
+            unanswered.push(ContactBalance {
+                address: address.clone(),
+                sent,
+                received: 0,
+            });
+        }
+    }
+
+    // Lowest reply rate first (compared as received/sent without dividing),
+    // then whoever the user has written to most.
+    unanswered.sort_by(|a, b| {
+        (a.received * b.sent)
+            .cmp(&(b.received * a.sent))
+            .then(b.sent.cmp(&a.sent))
+            .then_with(|| a.address.cmp(&b.address))
+    });
+    ignored.sort_by(|a, b| b.received.cmp(&a.received).then_with(|| a.address.cmp(&b.address)));
+
+    ReplyBalance { unanswered, ignored }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender(email: &str, count: usize, sent_to: usize) -> SenderInfo {
+        SenderInfo {
+            email: email.to_string(),
+            display: email.to_string(),
+            addresses: vec![email.to_string()],
+            count,
+            last_seen: None,
+            sent_to,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn splits_unanswered_and_ignored_contacts() {
+        let senders = [
+            sender("friend@x.com", 10, 10),
+            sender("slow@x.com", 1, 8),
+            sender("news@x.com", 50, 0),
+        ];
+        let sent_counts = HashMap::from([
+            ("friend@x.com".to_string(), 10),
+            ("slow@x.com".to_string(), 8),
+            ("silent@x.com".to_string(), 3),
+            ("me@x.com".to_string(), 2),
+        ]);
+
+        let balance = reply_balance(&senders, &sent_counts, "Me@x.com");
+
+        let unanswered: Vec<&str> = balance.unanswered.iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(unanswered, ["silent@x.com", "slow@x.com"]);
+        assert_eq!(balance.ignored.len(), 1);
+        assert_eq!(balance.ignored[0].address, "news@x.com");
+    }
+}
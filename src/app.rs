@@ -1,76 +1,407 @@
-use crate::bridge::{BackgroundEvent, UiCommand};
-use crate::state::{AppPhase, AppState};
+use crate::bridge::{BackgroundEvent, ShutdownGuard, UiCommand};
+use crate::format::{byte_size, thousands};
+use crate::imap::sieve::SieveOutcome;
+use crate::journal::DeleteJournal;
+use crate::notification;
+use crate::state::{
+    AppPhase, AppState, BlockStatus, ConnectStatus, DeleteMode, DeleteScope, Diagnosis,
+    DisplayLimits, FolderCheck,
+};
 use crate::ui::{dashboard, sidebar};
 use tokio::sync::mpsc::UnboundedSender;
 
+/// Storage key for the persisted colour theme.
+const THEME_KEY: &str = "theme";
+
+/// Storage keys for the sender and domain allowlists.
+const PROTECTED_KEY: &str = "protected_senders";
+const PROTECTED_DOMAINS_KEY: &str = "protected_domains";
+
+/// Storage key for the notes kept on senders.
+const SENDER_NOTES_KEY: &str = "sender_notes";
+
+/// Storage key for the blocklist file and what to do with its matches.
+const BLOCKLIST_KEY: &str = "blocklist";
+
+/// Storage keys for the sign-in method and the OAuth app registration.
+const SIGN_IN_KEY: &str = "sign_in";
+const OAUTH_CLIENT_KEY: &str = "oauth_client";
+
+/// Storage key for whether the app opens in analyze-only mode.
+const ANALYZE_ONLY_KEY: &str = "analyze_only";
+
+/// Storage key for the donut and kill list sizes.
+const DISPLAY_LIMITS_KEY: &str = "display_limits";
+
+/// Storage key for the finished-job notification settings.
+const NOTIFICATIONS_KEY: &str = "notifications";
+
+/// Storage key for the last scanned folder per account.
+const LAST_FOLDERS_KEY: &str = "last_folders";
+
 pub struct EmailAssassinApp {
     state: AppState,
     cmd_tx: UnboundedSender<UiCommand>,
     event_rx: std::sync::mpsc::Receiver<BackgroundEvent>,
+    /// Logs background sessions out when the app is dropped on exit.
+    _shutdown: ShutdownGuard,
 }
 
 impl EmailAssassinApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    /// `analyze_only` comes from the command line and overrides the saved
+    /// setting for this run.
+    pub fn new(cc: &eframe::CreationContext<'_>, analyze_only: bool) -> Self {
         let bridge = crate::bridge::setup_bridge(cc.egui_ctx.clone());
+
+        let mut state = AppState::default();
+        if let Some(storage) = cc.storage {
+            if let Some(theme) = eframe::get_value(storage, THEME_KEY) {
+                state.theme = theme;
+            }
+            if let Some(protected) = eframe::get_value(storage, PROTECTED_KEY) {
+                state.protected = protected;
+            }
+            if let Some(domains) = eframe::get_value(storage, PROTECTED_DOMAINS_KEY) {
+                state.protected_domains = domains;
+            }
+            if let Some(notes) = eframe::get_value(storage, SENDER_NOTES_KEY) {
+                state.sender_notes = notes;
+            }
+            if let Some(blocklist) = eframe::get_value(storage, BLOCKLIST_KEY) {
+                state.blocklist = blocklist;
+            }
+            if let Some(sign_in) = eframe::get_value(storage, SIGN_IN_KEY) {
+                state.sign_in = sign_in;
+            }
+            if let Some(client) = eframe::get_value(storage, OAUTH_CLIENT_KEY) {
+                state.oauth_client = client;
+            }
+            if let Some(folders) = eframe::get_value(storage, LAST_FOLDERS_KEY) {
+                state.last_folders = folders;
+            }
+            if let Some(saved) = eframe::get_value(storage, ANALYZE_ONLY_KEY) {
+                state.analyze_only = saved;
+            }
+            if let Some(limits) = eframe::get_value::<DisplayLimits>(storage, DISPLAY_LIMITS_KEY) {
+                state.display_limits = limits;
+                state.kill_list_limit = limits.kill_list_rows;
+            }
+            if let Some(notifications) = eframe::get_value(storage, NOTIFICATIONS_KEY) {
+                state.notifications = notifications;
+            }
+        }
+        if analyze_only {
+            state.analyze_only = true;
+            state.analyze_only_locked = true;
+        }
+        cc.egui_ctx.set_visuals(state.theme.visuals());
+        state.interrupted_delete = DeleteJournal::load();
+
         Self {
-            state: AppState::default(),
+            state,
             cmd_tx: bridge.cmd_tx,
             event_rx: bridge.event_rx,
+            _shutdown: bridge.shutdown,
         }
     }
 
+    /// Applies everything the background thread sent since the last frame.
+    /// Progress and partial snapshots are coalesced so a burst of them costs
+    /// one update instead of one per batch.
     fn drain_events(&mut self, ctx: &egui::Context) {
+        let mut scan_progress = None;
+        let mut scan_partial = None;
+        let mut diagnostics = None;
+        let mut delete_progress = None;
+
         while let Ok(event) = self.event_rx.try_recv() {
             match event {
                 BackgroundEvent::ScanProgress { progress, status } => {
-                    self.state.scan_progress = progress;
-                    self.state.scan_status = status;
+                    scan_progress = Some((progress, status));
+                }
+                BackgroundEvent::ScanPartial { senders } => {
+                    scan_partial = Some(senders);
                 }
-                BackgroundEvent::ScanComplete {
-                    senders,
-                    total_emails,
-                } => {
-                    self.state.senders = senders;
-                    self.state.total_emails = total_emails;
-                    self.state.phase = AppPhase::ScanComplete;
-                    self.state.scan_progress = 1.0;
-                    self.state.scan_status = "Complete".to_string();
+                BackgroundEvent::Diagnostic(latest) => {
+                    diagnostics = Some(latest);
                 }
-                BackgroundEvent::ScanError(msg) => {
-                    self.state.error_message = Some(msg);
-                    self.state.phase = AppPhase::Idle;
+                BackgroundEvent::DeleteProgress { .. } => {
+                    delete_progress = Some(event);
                 }
-                BackgroundEvent::DeleteProgress { progress, status } => {
-                    self.state.delete_progress = progress;
-                    self.state.delete_status = status;
+                BackgroundEvent::DeleteThrottled(_) => {
+                    // Applied in order so the next progress update, not an
+                    // older one, replaces the notice.
+                    if let Some(pending) = delete_progress.take() {
+                        self.apply_event(pending);
+                    }
+                    self.apply_event(event);
                 }
-                BackgroundEvent::DeleteComplete {
-                    removed_senders,
-                    total_removed,
-                } => {
-                    // Optimistic update: remove deleted senders
-                    self.state
-                        .senders
-                        .retain(|s| !removed_senders.contains(&s.email));
-                    for sender in &removed_senders {
-                        self.state.sender_selected.remove(sender);
+                BackgroundEvent::ScanComplete { .. }
+                | BackgroundEvent::ScanLargeFolder { .. }
+                | BackgroundEvent::ScanError(_) => {
+                    // Anything still pending is older than the final result.
+                    scan_progress = None;
+                    scan_partial = None;
+                    let complete = matches!(event, BackgroundEvent::ScanComplete { .. });
+                    self.apply_event(event);
+                    if complete {
+                        let body = format!(
+                            "{} senders in {} emails",
+                            thousands(self.state.unique_senders()),
+                            thousands(self.state.total_emails)
+                        );
+                        self.notify_finished(ctx, "Scan complete", &body);
                     }
-                    self.state.phase = AppPhase::ScanComplete;
-                    self.state.delete_progress = 1.0;
-                    self.state.delete_status =
-                        format!("Removed {total_removed} emails");
                 }
-                BackgroundEvent::DeleteError(msg) => {
-                    self.state.error_message = Some(msg);
-                    // Don't reset phase - partial failure is tolerated
+                BackgroundEvent::DeleteComplete { .. } => {
+                    delete_progress = None;
+                    self.apply_event(event);
+                    let body = self.state.delete_status.clone();
+                    self.notify_finished(ctx, "Delete complete", &body);
+                }
+                BackgroundEvent::DeletePreview(_)
+                | BackgroundEvent::DeletePreviewError(_)
+                | BackgroundEvent::SenderRescanned { .. }
+                | BackgroundEvent::SenderRescanError { .. }
+                | BackgroundEvent::BlockComplete(_)
+                | BackgroundEvent::BlockError(_)
+                | BackgroundEvent::FoldersChecked(_)
+                | BackgroundEvent::FolderCheckError(_)
+                | BackgroundEvent::Diagnosed(_)
+                | BackgroundEvent::StaleScan(_)
+                | BackgroundEvent::AccountConnected(_)
+                | BackgroundEvent::ConnectError(_)
+                | BackgroundEvent::Link(_) => {
+                    self.apply_event(event);
                 }
             }
+        }
+
+        if let Some((progress, status)) = scan_progress {
+            self.apply_event(BackgroundEvent::ScanProgress { progress, status });
+        }
+        if let Some(senders) = scan_partial {
+            self.apply_event(BackgroundEvent::ScanPartial { senders });
+        }
+        // Kept through completion: the final counters explain a slow run.
+        if let Some(latest) = diagnostics {
+            self.apply_event(BackgroundEvent::Diagnostic(latest));
+        }
+        if let Some(pending) = delete_progress {
+            self.apply_event(pending);
+        }
+        if self.state.ease_scan_progress(ctx.input(|input| input.stable_dt)) {
             ctx.request_repaint();
         }
     }
+
+    fn apply_event(&mut self, event: BackgroundEvent) {
+        match event {
+            BackgroundEvent::ScanProgress { progress, status } => {
+                self.state.scan_progress = self.state.scan_progress.max(progress);
+                self.state.scan_status = status;
+            }
+            BackgroundEvent::ScanPartial { senders } => {
+                self.state.senders = senders;
+            }
+            BackgroundEvent::Diagnostic(diagnostics) => {
+                self.state.scan_diagnostics = Some(diagnostics);
+            }
+            event @ BackgroundEvent::ScanComplete { .. } => self.finish_scan(event),
+            BackgroundEvent::ScanLargeFolder { messages } => {
+                self.state.large_folder = Some(messages);
+                self.state.phase = AppPhase::Idle;
+            }
+            BackgroundEvent::ScanError(msg) => {
+                self.state.error_message = Some(msg);
+                self.state.phase = AppPhase::Idle;
+            }
+            BackgroundEvent::DeletePreview(check) => {
+                // Ignored if the dialog was cancelled while counting.
+                if let Some(preview) = &mut self.state.delete_preview {
+                    preview.live_counts = Some(Ok(check.counts));
+                    preview.missing_trash = check.missing_trash;
+                }
+            }
+            BackgroundEvent::DeletePreviewError(msg) => {
+                if let Some(preview) = &mut self.state.delete_preview {
+                    preview.live_counts = Some(Err(msg));
+                }
+            }
+            BackgroundEvent::DeleteProgress {
+                progress,
+                status,
+                sender_progress,
+            } => {
+                self.state.delete_progress = progress;
+                self.state.delete_status = status;
+                self.state.delete_sender_progress = sender_progress;
+            }
+            BackgroundEvent::DeleteThrottled(wait) => {
+                self.state.delete_status = format!(
+                    "Being rate-limited by server, slowing down ({}s between commands)...",
+                    wait.as_secs()
+                );
+            }
+            BackgroundEvent::DeleteComplete {
+                mode,
+                keep_newest,
+                removed_senders,
+                total_removed,
+                failed,
+            } => {
+                let freed = self.state.estimate_freed(&removed_senders, total_removed);
+                self.state.forget_deleted(&removed_senders, keep_newest);
+                self.finish_delete(mode, total_removed, freed, failed);
+            }
+            BackgroundEvent::SenderRescanned { email, count } => {
+                self.state.apply_rescan(&email, count);
+            }
+            BackgroundEvent::SenderRescanError { email, message } => {
+                self.state.rescanning.remove(&email);
+                self.state.error_message = Some(format!("Rescan of {email} failed: {message}"));
+            }
+            BackgroundEvent::BlockComplete(outcome) => {
+                self.state.block_status = Some(BlockStatus::Done(block_summary(&outcome)));
+            }
+            BackgroundEvent::BlockError(msg) => {
+                self.state.block_status = Some(BlockStatus::Failed(msg));
+            }
+            BackgroundEvent::FoldersChecked(missing) => {
+                self.state.folder_check = Some(FolderCheck::Done(missing));
+            }
+            BackgroundEvent::FolderCheckError(msg) => {
+                self.state.folder_check = Some(FolderCheck::Failed(msg));
+            }
+            BackgroundEvent::StaleScan(warning) => {
+                self.state.error_message = Some(warning);
+            }
+            BackgroundEvent::Diagnosed(steps) => {
+                self.state.diagnosis = Some(Diagnosis::Done(steps));
+            }
+            BackgroundEvent::AccountConnected(email) => {
+                self.state.connect_status =
+                    Some(ConnectStatus::Connected(format!("Connected as {email}")));
+            }
+            BackgroundEvent::ConnectError(msg) => {
+                self.state.connect_status = Some(ConnectStatus::Failed(msg));
+            }
+            BackgroundEvent::Link(status) => {
+                self.state.link_status = status;
+            }
+        }
+    }
+
+    /// Tells the user a long job is done, if they asked to be told and the
+    /// window isn't the one they're looking at.
+    fn notify_finished(&self, ctx: &egui::Context, summary: &str, body: &str) {
+        let settings = self.state.notifications;
+        if !settings.on_finish || ctx.input(|i| i.viewport().focused) == Some(true) {
+            return;
+        }
+        notification::show(summary, body, settings.sound);
+        ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+            egui::UserAttentionType::Informational,
+        ));
+    }
+
+    /// Takes in the results of a finished scan. Any other event is ignored.
+    fn finish_scan(&mut self, event: BackgroundEvent) {
+        let BackgroundEvent::ScanComplete {
+            senders,
+            total_emails,
+            failed_batches,
+            reply_balance,
+            correspondents_unchecked,
+            limited_scope,
+            sender_uids,
+            raw_headers,
+            recipients,
+            read_only,
+            uid_validity,
+        } = event
+        else {
+            return;
+        };
+        self.state.senders = senders;
+        self.state.total_emails = total_emails;
+        self.state.phase = AppPhase::ScanComplete;
+        self.state.scan_progress = 1.0;
+        self.state.scan_status = "Complete".to_string();
+        self.state.failed_batches = failed_batches;
+        self.state.reply_balance = reply_balance;
+        self.state.correspondents_unchecked = correspondents_unchecked;
+        self.state.limited_scope = limited_scope;
+        if sender_uids.is_none() {
+            self.state.delete_scope = DeleteScope::WholeFolder;
+        }
+        self.state.sender_uids = sender_uids;
+        self.state.raw_headers = raw_headers;
+        self.state.recipients = recipients;
+        self.state.folder_read_only = read_only;
+        self.state.uid_validity = uid_validity;
+        self.state.apply_blocklist();
+    }
+
+    /// Wraps up a delete run, starting a rescan if the user asked for one.
+    /// `freed` is the estimated space reclaimed, when the scan had sizes.
+    fn finish_delete(
+        &mut self,
+        mode: DeleteMode,
+        total_removed: usize,
+        freed: Option<u64>,
+        failed: Vec<(String, String)>,
+    ) {
+        self.state.delete_sender_progress = None;
+        self.state.delete_skipped = None;
+        self.state.phase = AppPhase::ScanComplete;
+        self.state.delete_progress = 1.0;
+        let freed = freed
+            .filter(|_| total_removed > 0)
+            .map(|bytes| format!(" (~{})", byte_size(bytes)))
+            .unwrap_or_default();
+        self.state.delete_status = format!("{} {total_removed} emails{freed}", mode.done_verb());
+        // Failed senders keep their selection so they can be retried
+        self.state.delete_failures = failed;
+        if self.state.rescan_after_delete && total_removed > 0 {
+            let request = self.state.start_rescan();
+            let _ = self.cmd_tx.send(UiCommand::StartScan(request));
+        }
+    }
+}
+
+fn block_summary(outcome: &SieveOutcome) -> String {
+    let mut message = format!(
+        "Blocked {} new senders on the server ({} total).",
+        outcome.added, outcome.total
+    );
+    if !outcome.active {
+        message.push_str(
+            " Another filter script is active; include \"email-assassin\" \
+             from it for the block to apply.",
+        );
+    }
+    message
 }
 
 impl eframe::App for EmailAssassinApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, THEME_KEY, &self.state.theme);
+        eframe::set_value(storage, PROTECTED_KEY, &self.state.protected);
+        eframe::set_value(storage, PROTECTED_DOMAINS_KEY, &self.state.protected_domains);
+        eframe::set_value(storage, SENDER_NOTES_KEY, &self.state.sender_notes);
+        eframe::set_value(storage, BLOCKLIST_KEY, &self.state.blocklist);
+        eframe::set_value(storage, SIGN_IN_KEY, &self.state.sign_in);
+        eframe::set_value(storage, OAUTH_CLIENT_KEY, &self.state.oauth_client);
+        eframe::set_value(storage, LAST_FOLDERS_KEY, &self.state.last_folders);
+        eframe::set_value(storage, DISPLAY_LIMITS_KEY, &self.state.display_limits);
+        eframe::set_value(storage, NOTIFICATIONS_KEY, &self.state.notifications);
+        // A forced run shouldn't change what the next normal launch does.
+        if !self.state.analyze_only_locked {
+            eframe::set_value(storage, ANALYZE_ONLY_KEY, &self.state.analyze_only);
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.drain_events(ctx);
 
@@ -0,0 +1,129 @@
+//! The user's own list of known spam senders, read from a text file and
+//! matched against every scan's senders. The opposite of the allowlist:
+//! listed senders are flagged, and can be selected for deletion up front.
+
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where the list lives and what to do with matches. Persisted across runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlocklistSettings {
+    /// Empty when no list is set.
+    pub path: String,
+    /// Check listed senders once a scan finishes.
+    pub auto_select: bool,
+}
+
+/// Parsed blocklist. Each line holds an address or a domain; `*` matches
+/// any run of characters, and a domain covers its subdomains too. Blank
+/// lines and lines starting with `#` are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Blocklist {
+    addresses: Vec<String>,
+    domains: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn parse(text: &str) -> Self {
+        let mut list = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = line.to_lowercase();
+            match entry.strip_prefix('@') {
+                Some(domain) => list.domains.push(domain.to_string()),
+                None if entry.contains('@') => list.addresses.push(entry),
+                None => list.domains.push(entry),
+            }
+        }
+        list
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            AppError::Blocklist(format!("cannot read {}: {e}", path.display()))
+        })?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Whether `address`, lowercased, is listed itself or sends from a
+    /// listed domain or one of its subdomains.
+    pub fn matches(&self, address: &str) -> bool {
+        if self.addresses.iter().any(|pattern| wildcard_match(pattern, address)) {
+            return true;
+        }
+        let Some((_, mut domain)) = address.rsplit_once('@') else {
+            return false;
+        };
+        loop {
+            if self.domains.iter().any(|pattern| wildcard_match(pattern, domain)) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` stands for any run of
+/// characters, including none.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_match_addresses_domains_and_wildcards() {
+        let list = Blocklist::parse(
+            "# known spam\n\
+             deals@shop.example\n\
+             \n\
+             Spammy.Example\n\
+             @promo.test\n\
+             news*@*.mailer.net\n",
+        );
+        assert!(list.matches("deals@shop.example"));
+        assert!(!list.matches("orders@shop.example"));
+        assert!(list.matches("anyone@spammy.example"));
+        assert!(list.matches("anyone@mail.spammy.example"));
+        assert!(!list.matches("anyone@notspammy.example"));
+        assert!(list.matches("x@promo.test"));
+        assert!(list.matches("newsletter@eu.mailer.net"));
+        assert!(!list.matches("newsletter@mailer.net"));
+        assert!(!list.matches("alerts@eu.mailer.net"));
+    }
+
+    #[test]
+    fn wildcards_need_their_fixed_parts_in_order() {
+        assert!(wildcard_match("a*b*c", "abc"));
+        assert!(wildcard_match("a*b*c", "a-b-b-c"));
+        assert!(!wildcard_match("a*b*c", "acb"));
+        assert!(!wildcard_match("*ab*ba", "aba"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("abc", "abcd"));
+    }
+}
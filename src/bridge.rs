@@ -1,23 +1,149 @@
-use crate::imap::{deleter, scanner};
-use crate::state::{DeleteMode, SenderInfo};
+use crate::analysis::{self, ReplyBalance};
+use crate::error::AppError;
+use crate::format::thousands;
+use crate::imap::deleter::{DeleteCheck, DeleteTarget, Throttle};
+use crate::imap::diagnose::{self, DiagnosticStep};
+use crate::imap::scanner::{
+    RecipientCounts, ScanDiagnostics, ScanSettings, ScanUpdate, SearchLimits, SenderGrouping,
+};
+use crate::imap::sieve::{self, SieveOutcome};
+use crate::imap::{self, deleter, scanner, ConnectionLimiter, Credentials};
+use crate::journal::DeleteJournal;
+use crate::oauth::{self, OAuthClient};
+use crate::state::{ConnectionOptions, DeleteMode, LinkStatus, ScanDirection, SenderInfo};
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use tokio::sync::mpsc as tokio_mpsc;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// Folder size above which a full scan needs the user's go-ahead.
+const LARGE_FOLDER_THRESHOLD: u32 = 100_000;
+
+/// How long running jobs get to finish their current IMAP command and log
+/// out once the app is closing.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Extra time for the runtime to wind down after the grace period, so a
+/// stuck task can't keep the process alive.
+const RUNTIME_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)] // one per scan option
+pub struct ScanRequest {
+    pub credentials: Arc<Credentials>,
+    pub folder: String,
+    pub scan_depth: u32,
+    pub scan_direction: ScanDirection,
+    /// Raw IMAP `SEARCH` criteria selecting which messages get scanned.
+    pub search_query: String,
+    pub check_correspondents: bool,
+    /// Where the correspondent check reads sent mail.
+    pub sent_folder: String,
+    /// How raw addresses are folded into senders, e.g. Gmail dot and
+    /// `+tag` variants.
+    pub grouping: SenderGrouping,
+    /// Sender headers to read and what to keep per sender, e.g. UIDs so
+    /// deletes can be scoped to the scan.
+    pub settings: ScanSettings,
+    pub search_timeout: Duration,
+    /// Skip the large-folder check; set once the user has confirmed.
+    pub allow_large_folder: bool,
+    pub connection: ConnectionOptions,
+}
+
+/// Senders the user unchecked after a delete started, shared with the
+/// running delete.
+pub type SkippedSenders = Arc<Mutex<HashSet<String>>>;
+
+#[derive(Debug, Clone)]
+pub struct DeleteRequest {
+    pub credentials: Arc<Credentials>,
+    pub folder: String,
+    pub senders: Vec<SenderInfo>,
+    /// What to delete for each sender, in the same order as `senders`.
+    pub targets: Vec<DeleteTarget>,
+    pub mode: DeleteMode,
+    /// Where Trash mode moves messages: the user's override or the
+    /// provider default.
+    pub trash_folder: String,
+    /// Most recent messages left alone per sender; 0 deletes them all.
+    pub keep_newest: usize,
+    /// Wait before each command that changes the folder.
+    pub pause: Duration,
+    /// Most messages per command that changes the folder.
+    pub chunk_size: usize,
+    /// Checked before each sender; any listed here by then are left alone.
+    pub skipped: SkippedSenders,
+    /// Search for every sender at once and purge the union in one pass,
+    /// rather than sender by sender. Never set with `keep_newest`, which
+    /// counts per sender.
+    pub combined: bool,
+    /// The folder's `UIDVALIDITY` when the scan ran, checked before
+    /// anything is deleted. `None` skips the check.
+    pub uid_validity: Option<u32>,
+    pub connection: ConnectionOptions,
+}
+
+/// Count one sender's messages in the folder again, live.
+#[derive(Debug, Clone)]
+pub struct RescanRequest {
+    pub credentials: Arc<Credentials>,
+    pub folder: String,
+    /// The sender's key in the scan results.
+    pub email: String,
+    pub addresses: Vec<String>,
+    pub connection: ConnectionOptions,
+}
+
+/// Folders to look for in the server's `LIST`.
+#[derive(Debug, Clone)]
+pub struct FolderCheckRequest {
+    pub credentials: Arc<Credentials>,
+    pub folders: Vec<String>,
+    pub connection: ConnectionOptions,
+}
+
+/// Account to run the step-by-step connection check against.
+#[derive(Debug, Clone)]
+pub struct DiagnoseRequest {
+    pub credentials: Arc<Credentials>,
+    pub connection: ConnectionOptions,
+}
+
+/// Addresses to add to the server-side Sieve blocklist.
+#[derive(Debug, Clone)]
+pub struct BlockRequest {
+    pub credentials: Arc<Credentials>,
+    pub addresses: Vec<String>,
+    pub connection: ConnectionOptions,
+}
+
+/// Run the OAuth consent flow for an account and save its refresh token.
+#[derive(Debug, Clone)]
+pub struct ConnectRequest {
+    pub email: String,
+    pub client: OAuthClient,
+    pub connection: ConnectionOptions,
+}
 
 #[derive(Debug)]
 pub enum UiCommand {
-    StartScan {
-        email: String,
-        password: String,
-        folder: String,
-        scan_depth: u32,
-    },
-    StartDelete {
-        email: String,
-        password: String,
-        folder: String,
-        senders: Vec<String>,
-        mode: DeleteMode,
-    },
+    StartScan(ScanRequest),
+    /// Count what the request would delete without touching anything.
+    PreviewDelete(DeleteRequest),
+    StartDelete(DeleteRequest),
+    /// Recount one sender with a live search instead of a full rescan.
+    RescanSender(RescanRequest),
+    BlockSenders(BlockRequest),
+    CheckFolders(FolderCheckRequest),
+    Diagnose(DiagnoseRequest),
+    ConnectAccount(ConnectRequest),
+    /// Cancel running jobs, log their sessions out and stop the loop.
+    Shutdown,
 }
 
 #[derive(Debug)]
@@ -26,37 +152,135 @@ pub enum BackgroundEvent {
         progress: f32,
         status: String,
     },
+    /// Live snapshot while the scan is still running.
+    ScanPartial {
+        senders: Vec<SenderInfo>,
+    },
+    /// Worker and batch counters for the diagnostics panel.
+    Diagnostic(ScanDiagnostics),
     ScanComplete {
         senders: Vec<SenderInfo>,
         total_emails: usize,
+        failed_batches: usize,
+        /// `None` when the Sent folder wasn't checked.
+        reply_balance: Option<ReplyBalance>,
+        /// Why the Sent folder check failed or timed out, if it was asked
+        /// for and didn't finish.
+        correspondents_unchecked: Option<String>,
+        /// Why the scan saw only part of the folder, if it did. Deletion
+        /// still searches the whole folder unless scoped to scanned UIDs.
+        limited_scope: Option<String>,
+        /// UIDs per lowercased raw address, for scan-scoped deletes.
+        /// `None` when the scan was asked not to keep them.
+        sender_uids: Option<HashMap<String, Vec<u32>>>,
+        /// Sample raw headers per lowercased raw address. `None` unless
+        /// the scan was asked to keep them.
+        raw_headers: Option<HashMap<String, Vec<String>>>,
+        /// To/Cc breakdown per lowercased raw address. `None` unless the
+        /// scan was asked for it.
+        recipients: Option<HashMap<String, RecipientCounts>>,
+        /// The folder can't be modified, so deleting from it would fail.
+        read_only: bool,
+        uid_validity: Option<u32>,
+    },
+    /// The folder is too big to scan in full without confirmation.
+    ScanLargeFolder {
+        messages: u32,
     },
     ScanError(String),
+    /// Live per-sender match counts, in the order the senders were sent,
+    /// plus a missing trash folder if Trash mode would fail.
+    DeletePreview(DeleteCheck),
+    DeletePreviewError(String),
+    /// The server asked the delete to slow down; the next command waits
+    /// this long.
+    DeleteThrottled(Duration),
+    /// The folder changed since the scan; carries the warning to show.
+    StaleScan(String),
     DeleteProgress {
         progress: f32,
         status: String,
+        /// How far through the current sender's messages, once known.
+        sender_progress: Option<f32>,
     },
     DeleteComplete {
+        mode: DeleteMode,
+        /// Carried over from the request; when non-zero the senders in
+        /// `removed_senders` still have up to this many messages.
+        keep_newest: usize,
         removed_senders: Vec<String>,
         total_removed: usize,
+        /// Senders that could not be purged, paired with the error message.
+        failed: Vec<(String, String)>,
+    },
+    /// A sender's live message count, from a `RescanSender`.
+    SenderRescanned {
+        email: String,
+        count: usize,
+    },
+    SenderRescanError {
+        email: String,
+        message: String,
     },
-    DeleteError(String),
+    BlockComplete(SieveOutcome),
+    BlockError(String),
+    /// The checked folders the server doesn't have.
+    FoldersChecked(Vec<String>),
+    FolderCheckError(String),
+    /// Every step a connection diagnosis ran, ending at the first failure.
+    Diagnosed(Vec<DiagnosticStep>),
+    /// The account's refresh token is saved; carries the address.
+    AccountConnected(String),
+    ConnectError(String),
+    /// Connectivity as seen by the latest scan or delete step.
+    Link(LinkStatus),
 }
 
 pub struct BridgeChannels {
     pub cmd_tx: tokio_mpsc::UnboundedSender<UiCommand>,
     pub event_rx: std_mpsc::Receiver<BackgroundEvent>,
+    pub shutdown: ShutdownGuard,
+}
+
+/// Stops the background thread when dropped, which eframe does once the
+/// window has closed. Running jobs are cancelled and given
+/// [`SHUTDOWN_GRACE`] to log out, so their sessions don't linger on the
+/// server and count against the next launch's connection limit.
+pub struct ShutdownGuard {
+    cmd_tx: tokio_mpsc::UnboundedSender<UiCommand>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        // Fails only if the loop has already stopped.
+        let _ = self.cmd_tx.send(UiCommand::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::warn!("background thread panicked during shutdown");
+            }
+        }
+    }
 }
 
 pub fn setup_bridge(ctx: egui::Context) -> BridgeChannels {
     let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel::<UiCommand>();
     let (event_tx, event_rx) = std_mpsc::channel::<BackgroundEvent>();
 
-    std::thread::spawn(move || {
+    let thread = std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
         rt.block_on(background_loop(cmd_rx, event_tx, ctx));
+        rt.shutdown_timeout(RUNTIME_SHUTDOWN_TIMEOUT);
     });
 
-    BridgeChannels { cmd_tx, event_rx }
+    BridgeChannels {
+        shutdown: ShutdownGuard {
+            cmd_tx: cmd_tx.clone(),
+            thread: Some(thread),
+        },
+        cmd_tx,
+        event_rx,
+    }
 }
 
 async fn background_loop(
@@ -64,154 +288,722 @@ async fn background_loop(
     event_tx: std_mpsc::Sender<BackgroundEvent>,
     ctx: egui::Context,
 ) {
+    let cancel = CancellationToken::new();
+    // Shared by every task so scans and deletes together respect the
+    // provider's connection limit.
+    let limiter = ConnectionLimiter::new(cancel.clone());
+    let tasks = TaskTracker::new();
+
     while let Some(cmd) = cmd_rx.recv().await {
+        let tx = event_tx.clone();
+        let ctx2 = ctx.clone();
+        let limiter = limiter.clone();
         match cmd {
-            UiCommand::StartScan {
-                email,
-                password,
-                folder,
-                scan_depth,
-            } => {
-                let tx = event_tx.clone();
-                let ctx2 = ctx.clone();
-                tokio::spawn(async move {
-                    handle_scan(email, password, folder, scan_depth, tx, ctx2).await;
-                });
+            UiCommand::StartScan(request) => {
+                tasks.spawn(handle_scan(request, limiter, cancel.clone(), tx, ctx2));
+            }
+            UiCommand::PreviewDelete(request) => {
+                tasks.spawn(handle_preview(request, limiter, tx, ctx2));
+            }
+            UiCommand::StartDelete(request) if request.combined => {
+                tasks.spawn(handle_combined_delete(request, limiter, cancel.clone(), tx, ctx2));
+            }
+            UiCommand::StartDelete(request) => {
+                tasks.spawn(handle_delete(request, limiter, cancel.clone(), tx, ctx2));
+            }
+            UiCommand::RescanSender(request) => {
+                tasks.spawn(handle_rescan(request, limiter, tx, ctx2));
+            }
+            UiCommand::BlockSenders(request) => {
+                tasks.spawn(handle_block(request, tx, ctx2));
+            }
+            UiCommand::CheckFolders(request) => {
+                tasks.spawn(handle_folder_check(request, limiter, tx, ctx2));
             }
-            UiCommand::StartDelete {
-                email,
-                password,
-                folder,
-                senders,
-                mode,
-            } => {
-                let tx = event_tx.clone();
-                let ctx2 = ctx.clone();
-                tokio::spawn(async move {
-                    handle_delete(email, password, folder, senders, mode, tx, ctx2).await;
-                });
+            UiCommand::Diagnose(request) => {
+                tasks.spawn(handle_diagnose(request, limiter, tx, ctx2));
             }
+            UiCommand::ConnectAccount(request) => {
+                tasks.spawn(handle_connect(request, tx, ctx2));
+            }
+            UiCommand::Shutdown => break,
         }
     }
+
+    // Also reached if the UI side went away without saying goodbye.
+    cancel.cancel();
+    tasks.close();
+    if tokio::time::timeout(SHUTDOWN_GRACE, tasks.wait()).await.is_err() {
+        tracing::warn!(running = tasks.len(), "jobs still running at exit, dropping sessions");
+    }
 }
 
 async fn handle_scan(
-    email: String,
-    password: String,
-    folder: String,
-    scan_depth: u32,
+    request: ScanRequest,
+    limiter: ConnectionLimiter,
+    cancel: CancellationToken,
     tx: std_mpsc::Sender<BackgroundEvent>,
     ctx: egui::Context,
 ) {
-    let send = |evt: BackgroundEvent| {
-        if let Err(e) = tx.send(evt) {
-            tracing::warn!(error = %e, "failed to send scan event to UI");
-        }
-        ctx.request_repaint();
-    };
+    let ScanRequest {
+        credentials,
+        folder,
+        scan_depth,
+        scan_direction,
+        search_query,
+        check_correspondents,
+        sent_folder,
+        grouping,
+        settings,
+        search_timeout,
+        allow_large_folder,
+        connection,
+    } = request;
+
+    let send = ui_sender(&tx, &ctx, "scan");
 
     send(BackgroundEvent::ScanProgress {
         progress: 0.0,
         status: "Fetching message IDs...".to_string(),
     });
 
-    let all_uids = match scanner::fetch_all_uids(&email, &password, &folder).await {
-        Ok(uids) => uids,
+    let limits = SearchLimits {
+        timeout: search_timeout,
+        // A depth limit already keeps the scan itself bounded.
+        max_messages: (!allow_large_folder && scan_depth == 0).then_some(LARGE_FOLDER_THRESHOLD),
+    };
+    let result = scanner::fetch_all_uids(
+        &credentials,
+        &folder,
+        &search_query,
+        &connection,
+        &limiter,
+        limits,
+        |attempt, e| send(search_retry_status(attempt, e)),
+    )
+    .await;
+    report_link(&send, &result);
+    let folder_uids = match result {
+        Ok(folder_uids) => folder_uids,
+        Err(AppError::LargeFolder { messages }) => {
+            send(BackgroundEvent::ScanLargeFolder { messages });
+            return;
+        }
+        Err(e) => {
+            send(BackgroundEvent::ScanError(e.to_string()));
+            return;
+        }
+    };
+
+    let read_only = folder_uids.read_only;
+    let uid_validity = folder_uids.uid_validity;
+    let total_emails = folder_uids.uids.len();
+    let uids_to_scan = limit_depth(folder_uids.uids, scan_depth, scan_direction);
+    let limited_scope = describe_scope(&search_query, uids_to_scan.len(), total_emails);
+
+    let status = format!(
+        "Found {} emails, scanning {}...",
+        thousands(total_emails),
+        thousands(uids_to_scan.len())
+    );
+    send(BackgroundEvent::ScanProgress { progress: 0.05, status });
+
+    let result = scanner::run_scan(
+        &credentials,
+        &folder,
+        &connection,
+        &limiter,
+        uids_to_scan,
+        &settings,
+        &cancel,
+        forward_scan_updates(tx.clone(), ctx.clone(), grouping.clone()),
+    )
+    .await;
+    report_link(&send, &result);
+    let scan = match result {
+        Ok(scan) => scan,
         Err(e) => {
             send(BackgroundEvent::ScanError(e.to_string()));
             return;
         }
     };
+    let mut senders = grouping.apply(scan.senders);
 
-    let total_emails = all_uids.len();
-    let uids_to_scan = if scan_depth > 0 && (scan_depth as usize) < total_emails {
-        all_uids[total_emails - scan_depth as usize..].to_vec()
+    let sent_check = SentCheck { folder: &sent_folder, timeout: search_timeout, cancel: &cancel };
+    let (reply_balance, correspondents_unchecked) = if check_correspondents {
+        check_reply_balance(&mut senders, &credentials, &sent_check, &connection, &limiter, &send)
+            .await
     } else {
-        all_uids
+        (None, None)
     };
 
-    send(BackgroundEvent::ScanProgress {
-        progress: 0.05,
-        status: format!(
-            "Found {} emails, scanning {}...",
-            total_emails,
-            uids_to_scan.len()
-        ),
+    send(BackgroundEvent::ScanComplete {
+        senders,
+        total_emails,
+        failed_batches: scan.failed_batches,
+        reply_balance,
+        correspondents_unchecked,
+        limited_scope,
+        sender_uids: scan.uids,
+        raw_headers: scan.raw_headers,
+        recipients: scan.recipients,
+        read_only,
+        uid_validity,
     });
+}
 
-    let progress_cb = {
-        let tx2 = tx.clone();
-        let ctx2 = ctx.clone();
-        move |progress: f32, status: String| {
-            if let Err(e) = tx2.send(BackgroundEvent::ScanProgress { progress, status }) {
-                tracing::warn!(error = %e, "failed to send scan progress to UI");
+/// Progress shown while a failed folder search waits to be retried.
+fn search_retry_status(attempt: u32, error: &AppError) -> BackgroundEvent {
+    BackgroundEvent::ScanProgress {
+        progress: 0.0,
+        status: format!("Folder search failed ({error}), retrying (attempt {})...", attempt + 1),
+    }
+}
+
+/// Turns scanner updates into UI events, grouping live snapshots the same
+/// way as the final result.
+fn forward_scan_updates(
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+    grouping: SenderGrouping,
+) -> impl Fn(ScanUpdate) + Send + Sync + 'static {
+    move |update: ScanUpdate| {
+        let evt = match update {
+            ScanUpdate::Progress { progress, status } => {
+                BackgroundEvent::ScanProgress { progress, status }
             }
-            ctx2.request_repaint();
+            ScanUpdate::Partial(senders) => BackgroundEvent::ScanPartial {
+                senders: grouping.apply(senders),
+            },
+            ScanUpdate::Diagnostics(diagnostics) => BackgroundEvent::Diagnostic(diagnostics),
+        };
+        if let Err(e) = tx.send(evt) {
+            tracing::warn!(error = %e, "failed to send scan progress to UI");
         }
-    };
+        ctx.request_repaint();
+    }
+}
 
-    match scanner::run_scan(&email, &password, &folder, uids_to_scan, progress_cb).await {
-        Ok(senders) => {
-            send(BackgroundEvent::ScanComplete {
-                senders,
-                total_emails,
-            });
+/// The Sent folder a scan checks for correspondents, bounded like the
+/// folder search.
+struct SentCheck<'a> {
+    folder: &'a str,
+    timeout: Duration,
+    cancel: &'a CancellationToken,
+}
+
+/// Fills in `sent_to` from the Sent folder and returns the raw
+/// per-recipient counts. A failure here is not fatal: the scan is still
+/// valid, just without the safety flag.
+async fn flag_correspondents(
+    senders: &mut [SenderInfo],
+    credentials: &Credentials,
+    check: &SentCheck<'_>,
+    connection: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Result<HashMap<String, usize>, AppError> {
+    let sent_counts = scanner::count_recipients(
+        credentials,
+        check.folder,
+        connection,
+        limiter,
+        check.timeout,
+        check.cancel,
+    )
+    .await?;
+    for sender in senders {
+        sender.sent_to = sender
+            .addresses
+            .iter()
+            .filter_map(|address| sent_counts.get(address))
+            .sum();
+    }
+    Ok(sent_counts)
+}
+
+/// Flags correspondents and compares what the user sends with what they
+/// receive. Returns the comparison, or why the Sent folder couldn't be
+/// checked.
+async fn check_reply_balance(
+    senders: &mut [SenderInfo],
+    credentials: &Credentials,
+    check: &SentCheck<'_>,
+    connection: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+    send: &impl Fn(BackgroundEvent),
+) -> (Option<ReplyBalance>, Option<String>) {
+    send(BackgroundEvent::ScanProgress {
+        progress: 1.0,
+        status: format!("Checking {} for correspondents...", check.folder),
+    });
+    match flag_correspondents(senders, credentials, check, connection, limiter).await {
+        Ok(sent_counts) => {
+            let balance = analysis::reply_balance(senders, &sent_counts, &credentials.email);
+            (Some(balance), None)
         }
         Err(e) => {
-            send(BackgroundEvent::ScanError(e.to_string()));
+            tracing::warn!(folder = check.folder, error = %e, "correspondent check failed");
+            (None, Some(format!("{} couldn't be read: {e}", check.folder)))
         }
     }
 }
 
-async fn handle_delete(
-    email: String,
-    password: String,
-    folder: String,
-    senders: Vec<String>,
-    mode: DeleteMode,
+/// Explains how the scanned messages fall short of the whole folder, or
+/// `None` if every message was scanned.
+fn describe_scope(search_query: &str, scanned: usize, matched: usize) -> Option<String> {
+    let filtered = !search_query.eq_ignore_ascii_case("ALL");
+    match (scanned < matched, filtered) {
+        (false, false) => None,
+        (true, false) => Some(format!(
+            "Only {} of {} emails were scanned.",
+            thousands(scanned),
+            thousands(matched)
+        )),
+        (false, true) => Some(format!("Only emails matching {search_query} were scanned.")),
+        (true, true) => Some(format!(
+            "Only {} of {} emails matching {search_query} were scanned.",
+            thousands(scanned),
+            thousands(matched)
+        )),
+    }
+}
+
+/// Trims the UID list to `depth` messages from the chosen end of the folder.
+/// A depth of 0 scans everything.
+fn limit_depth(mut uids: Vec<u32>, depth: u32, direction: ScanDirection) -> Vec<u32> {
+    let depth = depth as usize;
+    if depth == 0 || depth >= uids.len() {
+        return uids;
+    }
+    // UIDs are sorted ascending, so the newest messages are at the end.
+    match direction {
+        ScanDirection::Newest => uids.split_off(uids.len() - depth),
+        ScanDirection::Oldest => {
+            uids.truncate(depth);
+            uids
+        }
+    }
+}
+
+async fn handle_preview(
+    request: DeleteRequest,
+    limiter: ConnectionLimiter,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let trash_folder = (request.mode == DeleteMode::Trash).then_some(request.trash_folder.as_str());
+    let result = deleter::check_delete(
+        &request.credentials,
+        &request.folder,
+        &request.targets,
+        trash_folder,
+        &request.connection,
+        &limiter,
+    )
+    .await;
+    let evt = match result {
+        Ok(check) => BackgroundEvent::DeletePreview(check),
+        Err(e) => BackgroundEvent::DeletePreviewError(e.to_string()),
+    };
+    if let Err(e) = tx.send(evt) {
+        tracing::warn!(error = %e, "failed to send delete preview to UI");
+    }
+    ctx.request_repaint();
+}
+
+/// Counts what a delete of the sender would match, which is the same
+/// search a full rescan would have to page through the folder to total.
+async fn handle_rescan(
+    request: RescanRequest,
+    limiter: ConnectionLimiter,
     tx: std_mpsc::Sender<BackgroundEvent>,
     ctx: egui::Context,
 ) {
-    let send = |evt: BackgroundEvent| {
+    let RescanRequest {
+        credentials,
+        folder,
+        email,
+        addresses,
+        connection,
+    } = request;
+    let targets = [DeleteTarget::Addresses(addresses)];
+    let result =
+        deleter::check_delete(&credentials, &folder, &targets, None, &connection, &limiter).await;
+    let evt = match result {
+        Ok(check) => BackgroundEvent::SenderRescanned {
+            email,
+            count: check.counts.iter().sum(),
+        },
+        Err(e) => BackgroundEvent::SenderRescanError {
+            email,
+            message: e.to_string(),
+        },
+    };
+    if let Err(e) = tx.send(evt) {
+        tracing::warn!(error = %e, "failed to send sender rescan to UI");
+    }
+    ctx.request_repaint();
+}
+
+/// Paces a delete run, telling the UI whenever the server makes it back off.
+fn delete_throttle(
+    pause: Duration,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) -> Throttle {
+    Throttle::new(pause, move |wait| {
+        if tx.send(BackgroundEvent::DeleteThrottled(wait)).is_ok() {
+            ctx.request_repaint();
+        }
+    })
+}
+
+/// Sends events from a background task to the UI and wakes it up.
+fn ui_sender<'a>(
+    tx: &'a std_mpsc::Sender<BackgroundEvent>,
+    ctx: &'a egui::Context,
+    task: &'static str,
+) -> impl Fn(BackgroundEvent) + 'a {
+    move |evt| {
         if let Err(e) = tx.send(evt) {
-            tracing::warn!(error = %e, "failed to send delete event to UI");
+            tracing::warn!(task, error = %e, "failed to send event to UI");
         }
         ctx.request_repaint();
+    }
+}
+
+/// Updates the status dot from the outcome of a server round trip. A
+/// refused login still means the server answered; other failures say
+/// nothing about connectivity and leave it as it was.
+fn report_link<T>(send: &impl Fn(BackgroundEvent), result: &Result<T, AppError>) {
+    let status = match result {
+        Ok(_) | Err(AppError::Auth { .. }) => LinkStatus::Online,
+        Err(e) if e.is_connection_error() => LinkStatus::Offline(e.to_string()),
+        Err(_) => return,
     };
+    send(BackgroundEvent::Link(status));
+}
+
+/// Delete progress between senders, or while one has no count yet.
+fn run_progress(progress: f32, status: String) -> BackgroundEvent {
+    BackgroundEvent::DeleteProgress {
+        progress,
+        status,
+        sender_progress: None,
+    }
+}
+
+fn reconnect_status(sender: &str, done: usize, of: usize) -> String {
+    format!(
+        "Connection dropped after {}/{} from {sender}, reconnecting...",
+        thousands(done),
+        thousands(of)
+    )
+}
+
+async fn handle_delete(
+    request: DeleteRequest,
+    limiter: ConnectionLimiter,
+    cancel: CancellationToken,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let mut journal = DeleteJournal::from_request(&request);
+    save_journal(&journal);
+    let send = ui_sender(&tx, &ctx, "delete");
+    let stale = stale_scan(&request, &limiter, &send).await;
+    let DeleteRequest {
+        credentials,
+        folder,
+        senders,
+        targets,
+        mode,
+        trash_folder,
+        keep_newest,
+        pause,
+        chunk_size,
+        skipped,
+        combined: _,
+        uid_validity: _,
+        connection,
+    } = request;
+
+    let mut throttle = delete_throttle(pause, tx.clone(), ctx.clone());
 
     let total = senders.len();
     let mut total_removed = 0usize;
     let mut removed_senders = Vec::new();
-    let use_trash = mode == DeleteMode::Trash;
+    let mut failed = Vec::new();
 
-    for (i, sender) in senders.iter().enumerate() {
-        send(BackgroundEvent::DeleteProgress {
-            progress: i as f32 / total as f32,
-            status: format!("Purging {sender}..."),
-        });
+    for (i, (sender, target)) in senders.iter().zip(&targets).enumerate() {
+        if cancel.is_cancelled() {
+            // The journal still lists the rest, so the next launch offers
+            // to resume.
+            tracing::info!(remaining = total - i, "delete stopped by shutdown");
+            return;
+        }
+        if skipped.lock().is_ok_and(|skipped| skipped.contains(&sender.email)) {
+            tracing::info!(sender_index = i, "sender unchecked mid-run, skipping");
+            journal.complete(&sender.email);
+            save_journal(&journal);
+            continue;
+        }
+        if let (Some(error), DeleteTarget::Uids(_)) = (&stale, target) {
+            // The UIDs may name other messages now, so none are touched.
+            failed.push((sender.email.clone(), error.to_string()));
+            journal.complete(&sender.email);
+            save_journal(&journal);
+            continue;
+        }
+        send(run_progress(i as f32 / total as f32, format!("Purging {}...", sender.email)));
+        let on_chunk = |done: usize, of: usize| {
+            let fraction = if of == 0 { 1.0 } else { done as f32 / of as f32 };
+            send(BackgroundEvent::DeleteProgress {
+                progress: (i as f32 + fraction) / total as f32,
+                status: format!("Purging {}: {}/{}", sender.email, thousands(done), thousands(of)),
+                sender_progress: Some(fraction),
+            });
+        };
+
+        let on_reconnect = |done: usize, of: usize| {
+            send(run_progress(i as f32 / total as f32, reconnect_status(&sender.email, done, of)));
+        };
 
-        match deleter::nuke_sender(&email, &password, &folder, sender, use_trash).await {
+        let result = deleter::nuke_sender(
+            &credentials,
+            &folder,
+            target,
+            mode,
+            &trash_folder,
+            keep_newest,
+            chunk_size,
+            &mut throttle,
+            &on_chunk,
+            &on_reconnect,
+            &connection,
+            &limiter,
+        )
+        .await;
+        report_link(&send, &result);
+        match result {
             Ok(count) => {
                 total_removed += count;
-                removed_senders.push(sender.clone());
+                removed_senders.push(sender.email.clone());
                 tracing::info!(count, sender_index = i, "emails removed from sender");
             }
+            Err(e) if e.is_config_error() => {
+                // Every remaining sender would fail the same way, and their
+                // messages are untouched, so stop and let the user fix it.
+                tracing::warn!(sender_index = i, error = %e, "delete stopped by a settings error");
+                let message = e.to_string();
+                failed.extend(senders[i..].iter().map(|s| (s.email.clone(), message.clone())));
+                break;
+            }
             Err(e) => {
                 tracing::error!(sender_index = i, error = %e, "failed to delete emails from sender");
-                send(BackgroundEvent::DeleteError(format!(
-                    "Failed to purge {sender}: {e}"
-                )));
+                failed.push((sender.email.clone(), e.to_string()));
             }
         }
 
-        send(BackgroundEvent::DeleteProgress {
-            progress: (i + 1) as f32 / total as f32,
-            status: format!("Completed {}/{}", i + 1, total),
-        });
+        journal.complete(&sender.email);
+        save_journal(&journal);
+
+        send(run_progress((i + 1) as f32 / total as f32, format!("Completed {}/{total}", i + 1)));
     }
 
+    DeleteJournal::clear();
     send(BackgroundEvent::DeleteComplete {
+        mode,
+        keep_newest,
         removed_senders,
         total_removed,
+        failed,
     });
 }
+
+/// Checks that the folder hasn't been rebuilt since the scan recorded its
+/// UIDs, warning the UI if it has. Returns the error to fail UID targets
+/// with; searches by address still run, as they look at the folder afresh.
+/// A failed check is left for the delete's own connection to report.
+async fn stale_scan(
+    request: &DeleteRequest,
+    limiter: &ConnectionLimiter,
+    send: &impl Fn(BackgroundEvent),
+) -> Option<AppError> {
+    let scanned = request.uid_validity?;
+    let current = imap::folder_uid_validity(
+        &request.credentials,
+        &request.folder,
+        &request.connection,
+        limiter,
+    )
+    .await
+    .ok()
+    .flatten()
+    .filter(|current| *current != scanned)?;
+    let error = AppError::StaleScan {
+        folder: request.folder.clone(),
+        scanned,
+        current,
+    };
+    tracing::warn!(scanned, current, "UIDVALIDITY changed since the scan");
+    send(BackgroundEvent::StaleScan(error.to_string()));
+    Some(error)
+}
+
+/// Deletes every sender's messages with one search and one pass over the
+/// union, saving a connection and a search per sender. The run succeeds or
+/// fails as a whole.
+async fn handle_combined_delete(
+    request: DeleteRequest,
+    limiter: ConnectionLimiter,
+    cancel: CancellationToken,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    save_journal(&DeleteJournal::from_request(&request));
+    let send = ui_sender(&tx, &ctx, "delete");
+    let stale = stale_scan(&request, &limiter, &send).await;
+    let mut throttle = delete_throttle(request.pause, tx.clone(), ctx.clone());
+    let senders = request.senders.len();
+    send(run_progress(0.0, format!("Searching {senders} senders...")));
+
+    let result = match (DeleteTarget::union(&request.targets), stale) {
+        (Some(DeleteTarget::Uids(_)), Some(error)) => Err(error),
+        (Some(target), _) => {
+            let on_chunk = |done: usize, of: usize| {
+                let fraction = if of == 0 { 1.0 } else { done as f32 / of as f32 };
+                send(run_progress(
+                    fraction,
+                    format!("Purging {senders} senders: {}/{}", thousands(done), thousands(of)),
+                ));
+            };
+            let on_reconnect = |done: usize, of: usize| {
+                send(run_progress(0.0, reconnect_status("the selected senders", done, of)));
+            };
+            let purge = deleter::nuke_sender(
+                &request.credentials,
+                &request.folder,
+                &target,
+                request.mode,
+                &request.trash_folder,
+                0,
+                request.chunk_size,
+                &mut throttle,
+                &on_chunk,
+                &on_reconnect,
+                &request.connection,
+                &limiter,
+            );
+            tokio::select! {
+                result = purge => result,
+                () = cancel.cancelled() => {
+                    // The journal is kept, so the next launch offers to
+                    // finish what the single pass didn't reach.
+                    tracing::info!(senders, "combined delete stopped by shutdown");
+                    return;
+                }
+            }
+        }
+        (None, _) => Err(AppError::Imap(
+            "Senders scoped to scanned messages can't be combined with others".to_string(),
+        )),
+    };
+    report_link(&send, &result);
+
+    let emails = request.senders.iter().map(|s| s.email.clone());
+    let (removed_senders, total_removed, failed) = match result {
+        Ok(count) => (emails.collect(), count, Vec::new()),
+        Err(e) => {
+            tracing::error!(senders, error = %e, "combined delete failed");
+            let message = e.to_string();
+            (Vec::new(), 0, emails.map(|email| (email, message.clone())).collect())
+        }
+    };
+    DeleteJournal::clear();
+    send(BackgroundEvent::DeleteComplete {
+        mode: request.mode,
+        keep_newest: 0,
+        removed_senders,
+        total_removed,
+        failed,
+    });
+}
+
+async fn handle_block(
+    request: BlockRequest,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let result =
+        sieve::block_senders(&request.credentials, &request.addresses, &request.connection).await;
+    let evt = match result {
+        Ok(outcome) => BackgroundEvent::BlockComplete(outcome),
+        Err(e) => BackgroundEvent::BlockError(e.to_string()),
+    };
+    if let Err(e) = tx.send(evt) {
+        tracing::warn!(error = %e, "failed to send block result to UI");
+    }
+    ctx.request_repaint();
+}
+
+async fn handle_folder_check(
+    request: FolderCheckRequest,
+    limiter: ConnectionLimiter,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let result = imap::missing_folders(
+        &request.credentials,
+        &request.folders,
+        &request.connection,
+        &limiter,
+    )
+    .await;
+    let evt = match result {
+        Ok(missing) => BackgroundEvent::FoldersChecked(missing),
+        Err(e) => BackgroundEvent::FolderCheckError(e.to_string()),
+    };
+    if let Err(e) = tx.send(evt) {
+        tracing::warn!(error = %e, "failed to send folder check to UI");
+    }
+    ctx.request_repaint();
+}
+
+async fn handle_diagnose(
+    request: DiagnoseRequest,
+    limiter: ConnectionLimiter,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let steps = diagnose::diagnose(&request.credentials, &request.connection, &limiter).await;
+    if let Err(e) = tx.send(BackgroundEvent::Diagnosed(steps)) {
+        tracing::warn!(error = %e, "failed to send diagnosis to UI");
+    }
+    ctx.request_repaint();
+}
+
+async fn handle_connect(
+    request: ConnectRequest,
+    tx: std_mpsc::Sender<BackgroundEvent>,
+    ctx: egui::Context,
+) {
+    let result =
+        oauth::connect_account(&request.email, &request.client, &request.connection).await;
+    let evt = match result {
+        Ok(()) => BackgroundEvent::AccountConnected(request.email),
+        Err(e) => BackgroundEvent::ConnectError(e.to_string()),
+    };
+    if let Err(e) = tx.send(evt) {
+        tracing::warn!(error = %e, "failed to send connect result to UI");
+    }
+    ctx.request_repaint();
+}
+
+/// A journal that can't be written only costs the ability to resume, so
+/// the delete carries on.
+fn save_journal(journal: &DeleteJournal) {
+    if let Err(e) = journal.save() {
+        tracing::warn!(error = %e, "failed to write delete journal");
+    }
+}
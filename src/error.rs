@@ -1,3 +1,4 @@
+use crate::imap::provider::ProviderKind;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,21 +9,176 @@ pub enum AppError {
     #[error("TLS error: {0}")]
     Tls(String),
 
-    #[error("Authentication failed: {0}")]
-    Auth(String),
+    #[error(
+        "Server certificate not trusted: {0}\nIf the server uses a private CA, add its \
+         certificate under TLS settings."
+    )]
+    Certificate(String),
+
+    #[error(
+        "TLS handshake failed: {0}\nCheck that the server accepts TLS on this port and that \
+         nothing on the network is intercepting the connection."
+    )]
+    Handshake(String),
+
+    #[error("Authentication failed: {message}\n{}", provider.auth_hint())]
+    Auth {
+        message: String,
+        provider: ProviderKind,
+    },
 
     #[error("Connection failed: {0}")]
     Connection(String),
+
+    #[error("Connection to the server was lost: {0}")]
+    Disconnected(String),
+
+    #[error(
+        "Connection lost after {done} of {total} messages and could not be restored \
+         ({reason}). Run the delete again for the rest."
+    )]
+    Interrupted {
+        done: usize,
+        total: usize,
+        reason: String,
+    },
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("Scan incomplete: {failed} of {total} batches failed (last error: {last_error})")]
+    IncompleteScan {
+        failed: usize,
+        total: usize,
+        last_error: String,
+    },
+
+    #[error("Folder has {messages} messages; confirm before scanning all of them")]
+    LargeFolder { messages: u32 },
+
+    #[error("Export failed: {0}")]
+    Export(String),
+
+    #[error("Blocklist: {0}")]
+    Blocklist(String),
+
+    #[error("OAuth sign-in: {0}")]
+    OAuth(String),
+
+    #[error("Server filter: {0}")]
+    Sieve(String),
+
+    #[error("Delete journal: {0}")]
+    Journal(String),
+
+    #[error("Trash folder '{folder}' not found — check the Trash folder setting")]
+    TrashNotFound { folder: String },
+
+    #[error("Folder '{folder}' is read-only on the server; nothing can be deleted from it")]
+    ReadOnlyFolder { folder: String },
+
+    #[error(
+        "Folder '{folder}' was rebuilt since the scan (UIDVALIDITY {scanned}, now {current}): \
+         scanned message IDs no longer apply and counts may be out of date. Scan again."
+    )]
+    StaleScan {
+        folder: String,
+        scanned: u32,
+        current: u32,
+    },
+}
+
+impl AppError {
+    /// The server couldn't be reached or stopped answering, as opposed to
+    /// answering with a refusal.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            AppError::Connection(_)
+                | AppError::Disconnected(_)
+                | AppError::Interrupted { .. }
+                | AppError::Tls(_)
+                | AppError::Certificate(_)
+                | AppError::Handshake(_)
+        )
+    }
+
+    /// A failure that may clear up on its own, like a dropped or refused
+    /// connection. TLS and certificate errors won't, so they don't count.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, AppError::Connection(_) | AppError::Disconnected(_))
+    }
+
+    /// A settings problem rather than a server or network failure. Retrying
+    /// won't help until the user changes something, and every other sender
+    /// in the same run would hit it too.
+    pub fn is_config_error(&self) -> bool {
+        matches!(
+            self,
+            AppError::TrashNotFound { .. } | AppError::ReadOnlyFolder { .. }
+        )
+    }
 }
 
 impl From<async_imap::error::Error> for AppError {
     fn from(e: async_imap::error::Error) -> Self {
-        AppError::Imap(e.to_string())
+        match e {
+            // A `BYE` ends the response stream, which surfaces as a lost
+            // connection.
+            async_imap::error::Error::ConnectionLost | async_imap::error::Error::Io(_) => {
+                AppError::Disconnected(e.to_string())
+            }
+            _ => AppError::Imap(e.to_string()),
+        }
     }
 }
 
+/// Phrases the TLS backends (OpenSSL, Secure Transport, `SChannel`) use when
+/// the handshake got as far as the certificate and rejected it.
+const CERTIFICATE_HINTS: [&str; 8] = [
+    "certificate",
+    "self signed",
+    "self-signed",
+    "issuer",
+    "not trusted",
+    "trust policy",
+    "hostname mismatch",
+    "expired",
+];
+
 impl From<async_native_tls::Error> for AppError {
     fn from(e: async_native_tls::Error) -> Self {
-        AppError::Tls(e.to_string())
+        tls_failure(e.to_string())
+    }
+}
+
+/// The TLS backend errors only carry a message, so certificate rejections
+/// are told apart from other handshake failures by wording.
+fn tls_failure(message: String) -> AppError {
+    let lower = message.to_lowercase();
+    if CERTIFICATE_HINTS.iter().any(|hint| lower.contains(hint)) {
+        AppError::Certificate(message)
+    } else {
+        AppError::Handshake(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tls_errors_split_into_certificate_and_handshake() {
+        for message in [
+            "error:0A000086:SSL routines:tls_post_process_server_certificate:\
+             certificate verify failed (self-signed certificate in certificate chain)",
+            "The trust policy was not trusted. (-2147408896)",
+            "The certificate chain was issued by an authority that is not trusted.",
+        ] {
+            let error = tls_failure(message.to_string());
+            assert!(matches!(error, AppError::Certificate(_)), "{message}");
+        }
+        let error = tls_failure("error:0A00010B:SSL routines::wrong version number".to_string());
+        assert!(matches!(error, AppError::Handshake(_)));
     }
 }
@@ -0,0 +1,232 @@
+use crate::error::AppError;
+use crate::imap::scanner::SenderHeaders;
+use crate::state::SenderInfo;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+/// A finished scan saved to disk, so it can be reviewed later without
+/// connecting. Holds no credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanSnapshot {
+    pub email: String,
+    pub folder: String,
+    pub total_emails: usize,
+    /// Headers the senders were keyed on. Missing from older files, which
+    /// only ever used `From:`.
+    #[serde(default)]
+    pub headers: SenderHeaders,
+    pub senders: Vec<SenderInfo>,
+}
+
+/// Asks where to save and writes `snapshot` there as JSON. Returns `None` if
+/// the user cancelled the dialog.
+pub fn save_scan(snapshot: &ScanSnapshot) -> Result<Option<PathBuf>, AppError> {
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name("scan.json")
+        .add_filter("Email Assassin scan (JSON)", &["json"])
+        .save_file()
+    else {
+        return Ok(None);
+    };
+
+    let json = serde_json::to_string_pretty(snapshot)
+        .map_err(|e| AppError::Export(format!("cannot serialize scan: {e}")))?;
+    std::fs::write(&path, json)
+        .map_err(|e| AppError::Export(format!("cannot write {}: {e}", path.display())))?;
+    Ok(Some(path))
+}
+
+/// Asks for a file saved by [`save_scan`] and reads it back. Returns `None`
+/// if the user cancelled the dialog.
+pub fn load_scan() -> Result<Option<(PathBuf, ScanSnapshot)>, AppError> {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Email Assassin scan (JSON)", &["json"])
+        .pick_file()
+    else {
+        return Ok(None);
+    };
+
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| AppError::Export(format!("cannot read {}: {e}", path.display())))?;
+    let snapshot = serde_json::from_str(&json)
+        .map_err(|e| AppError::Export(format!("{} isn't a saved scan: {e}", path.display())))?;
+    Ok(Some((path, snapshot)))
+}
+
+/// Asks where to save and writes `image` there as a PNG. Returns `None` if
+/// the user cancelled the dialog.
+pub fn save_png(image: &egui::ColorImage) -> Result<Option<PathBuf>, AppError> {
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name("inbox-composition.png")
+        .add_filter("PNG image", &["png"])
+        .save_file()
+    else {
+        return Ok(None);
+    };
+
+    let [width, height] = image.size;
+    image::save_buffer_with_format(
+        &path,
+        image.as_raw(),
+        width as u32,
+        height as u32,
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| AppError::Export(format!("cannot write {}: {e}", path.display())))?;
+    Ok(Some(path))
+}
+
+/// Mail-client filter formats the selected senders can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterFormat {
+    /// Atom feed accepted by Gmail's Settings > Filters > Import filters.
+    GmailXml,
+    /// Rules to paste into a Thunderbird profile's `msgFilterRules.dat`.
+    Thunderbird,
+}
+
+impl FilterFormat {
+    pub const ALL: [FilterFormat; 2] = [FilterFormat::GmailXml, FilterFormat::Thunderbird];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterFormat::GmailXml => "Gmail filters (XML)",
+            FilterFormat::Thunderbird => "Thunderbird rules (msgFilterRules.dat)",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            FilterFormat::GmailXml => "mailFilters.xml",
+            FilterFormat::Thunderbird => "msgFilterRules.dat",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            FilterFormat::GmailXml => "xml",
+            FilterFormat::Thunderbird => "dat",
+        }
+    }
+}
+
+/// Renders one delete-on-arrival filter per sender. Output is deterministic
+/// for a given sender list so repeated exports diff cleanly.
+pub fn render_filters(format: FilterFormat, senders: &[&str]) -> String {
+    match format {
+        FilterFormat::GmailXml => render_gmail(senders),
+        FilterFormat::Thunderbird => render_thunderbird(senders),
+    }
+}
+
+/// Asks where to save and writes the filters there. Returns `None` if the
+/// user cancelled the dialog.
+pub fn save_filters(format: FilterFormat, senders: &[&str]) -> Result<Option<PathBuf>, AppError> {
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name(format.file_name())
+        .add_filter(format.label(), &[format.extension()])
+        .save_file()
+    else {
+        return Ok(None);
+    };
+
+    std::fs::write(&path, render_filters(format, senders))
+        .map_err(|e| AppError::Export(format!("cannot write {}: {e}", path.display())))?;
+    Ok(Some(path))
+}
+
+fn render_gmail(senders: &[&str]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version='1.0' encoding='UTF-8'?>\n");
+    out.push_str(
+        "<feed xmlns='http://www.w3.org/2005/Atom' \
+         xmlns:apps='http://schemas.google.com/apps/2006'>\n",
+    );
+    out.push_str("  <title>Mail Filters</title>\n");
+    for sender in senders {
+        out.push_str("  <entry>\n");
+        out.push_str("    <category term='filter'></category>\n");
+        out.push_str("    <title>Mail Filter</title>\n");
+        out.push_str("    <content></content>\n");
+        let _ = writeln!(out, "    <apps:property name='from' value='{}'/>", xml_escape(sender));
+        out.push_str("    <apps:property name='shouldTrash' value='true'/>\n");
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn render_thunderbird(senders: &[&str]) -> String {
+    let mut out = String::new();
+    for sender in senders {
+        let sender = sender.replace('\\', "\\\\").replace('"', "\\\"");
+        // type 17 = run on incoming mail and when run manually.
+        let _ = write!(
+            out,
+            "name=\"Email Assassin: {sender}\"\n\
+             enabled=\"yes\"\n\
+             type=\"17\"\n\
+             action=\"Delete\"\n\
+             condition=\"AND (from,contains,{sender})\"\n"
+        );
+    }
+    out
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\'' => out.push_str("&apos;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_scans_round_trip() {
+        let snapshot = ScanSnapshot {
+            email: "me@gmail.com".to_string(),
+            folder: "INBOX".to_string(),
+            total_emails: 12,
+            headers: SenderHeaders::default(),
+            senders: vec![SenderInfo {
+                email: "news@acme.com".to_string(),
+                display: "News@Acme.com".to_string(),
+                addresses: vec!["news@acme.com".to_string()],
+                count: 7,
+                last_seen: chrono::DateTime::from_timestamp(1_700_000_000, 0),
+                sent_to: 0,
+                bytes: 0,
+            }],
+        };
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<ScanSnapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn gmail_filters_escape_addresses() {
+        let xml = render_filters(FilterFormat::GmailXml, &["o'brien&co@example.com"]);
+        assert!(xml.contains("value='o&apos;brien&amp;co@example.com'"));
+        assert!(xml.contains("name='shouldTrash' value='true'"));
+        assert_eq!(xml.matches("<entry>").count(), 1);
+    }
+
+    #[test]
+    fn thunderbird_rules_have_one_block_per_sender() {
+        let rules = render_filters(FilterFormat::Thunderbird, &["a@x.com", "b@y.com"]);
+        assert_eq!(rules.matches("action=\"Delete\"").count(), 2);
+        assert!(rules.contains("condition=\"AND (from,contains,b@y.com)\""));
+    }
+}
@@ -0,0 +1,51 @@
+use async_imap::types::{Capabilities, Capability};
+use std::collections::HashSet;
+
+/// Capability atoms the server advertised right after login, normalised to
+/// upper case so lookups don't depend on how the server spells them.
+#[derive(Debug, Clone, Default)]
+pub struct ServerCapabilities {
+    atoms: HashSet<String>,
+}
+
+impl ServerCapabilities {
+    pub fn from_response(caps: &Capabilities) -> Self {
+        let atoms = caps
+            .iter()
+            .map(|cap| match cap {
+                Capability::Imap4rev1 => "IMAP4REV1".to_string(),
+                Capability::Auth(mech) => format!("AUTH={}", mech.to_uppercase()),
+                Capability::Atom(atom) => atom.to_uppercase(),
+            })
+            .collect();
+        Self { atoms }
+    }
+
+    #[cfg(test)]
+    pub fn from_atoms(atoms: &[&str]) -> Self {
+        Self {
+            atoms: atoms.iter().map(|atom| atom.to_uppercase()).collect(),
+        }
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.atoms.contains(&name.to_uppercase())
+    }
+
+    /// RFC 6851: atomic `UID MOVE`. Without it a move has to be done as
+    /// copy, flag and expunge.
+    pub fn supports_move(&self) -> bool {
+        self.has("MOVE")
+    }
+
+    /// RFC 4315: enables `UID EXPUNGE`, which only removes the given UIDs
+    /// instead of everything flagged `\Deleted` in the mailbox.
+    pub fn supports_uidplus(&self) -> bool {
+        self.has("UIDPLUS")
+    }
+
+    /// Gmail's IMAP extensions, including `X-GM-LABELS`.
+    pub fn supports_gmail_labels(&self) -> bool {
+        self.has("X-GM-EXT-1")
+    }
+}
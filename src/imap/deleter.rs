@@ -1,72 +1,952 @@
 use crate::error::AppError;
-use crate::imap::provider::ImapProvider;
+use crate::state::{ConnectionOptions, DeleteMode};
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-use super::connect_imap;
+use super::ops::ImapOps;
+use super::{
+    connect_imap, uid_chunk_len, uid_chunks, uid_list, ConnectionLimiter, Credentials,
+    MAX_UID_SET_LEN,
+    ImapConnection,
+};
 
-/// Maximum UIDs per IMAP command. Keeps individual commands under typical
-/// server command-length limits and avoids long-running single operations.
-const DELETE_CHUNK_SIZE: usize = 1000;
+/// Maximum UIDs per `SEARCH` or `FETCH` while looking up which messages a
+/// delete covers. How many go into each command that changes the folder
+/// is set per delete instead.
+const LOOKUP_CHUNK_SIZE: usize = 1000;
 
+/// Adds `\Deleted` without touching the other flags. A bare `FLAGS` store
+/// would replace them and clear `\Seen` on anything that survives.
+const DELETED_FLAG_STORE: &str = "+FLAGS (\\Deleted)";
+
+/// Phrases servers use in a `NO` reply when a COPY or MOVE target doesn't
+/// exist. `TRYCREATE` is the RFC 3501 response code; the rest cover servers
+/// that only say it in prose.
+const MISSING_MAILBOX_HINTS: &[&str] = &[
+    "TRYCREATE",
+    "NO FOLDER",
+    "NO SUCH MAILBOX",
+    "NO SUCH FOLDER",
+    "UNKNOWN MAILBOX",
+    "DOESN'T EXIST",
+    "DOES NOT EXIST",
+    "NONEXISTENT",
+];
+
+/// Phrases in a `NO`/`BAD` reply that mean "slow down" rather than a real
+/// failure: Gmail's `[THROTTLED]`, the `[LIMIT]` and `[UNAVAILABLE]` codes
+/// from RFC 5530, and prose from servers that only describe it.
+const THROTTLE_HINTS: &[&str] = &[
+    "THROTTLED",
+    "[LIMIT]",
+    "[UNAVAILABLE]",
+    "TOO MANY",
+    "RATE LIMIT",
+    "TRY AGAIN LATER",
+    "SERVER UNAVAILABLE",
+];
+
+/// First wait after a throttling reply. Doubles on each further one.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Longest wait between retries of a throttled command.
+const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+/// Throttling replies tolerated for one command before giving up on it.
+const MAX_THROTTLE_RETRIES: u32 = 5;
+
+/// Times a single sender's purge reconnects after the server drops the
+/// connection before giving up on it.
+const MAX_RECONNECTS: u32 = 3;
+
+/// Drops Gmail's `\Inbox` label. The message leaves INBOX but stays in
+/// All Mail under its other labels.
+const INBOX_LABEL_REMOVE: &str = "-X-GM-LABELS (\\Inbox)";
+
+/// Which of a sender's messages a delete acts on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeleteTarget {
+    /// Everything in the folder from any of these addresses.
+    Addresses(Vec<String>),
+    /// Exactly these messages, as recorded by the scan.
+    Uids(Vec<u32>),
+}
+
+impl DeleteTarget {
+    /// One target covering everything in `targets`, for deleting several
+    /// senders in a single pass. Targets of different kinds can't share a
+    /// search, so those give `None`.
+    pub fn union(targets: &[DeleteTarget]) -> Option<DeleteTarget> {
+        let mut addresses = Vec::new();
+        let mut uids = Vec::new();
+        for target in targets {
+            match target {
+                DeleteTarget::Addresses(more) => addresses.extend(more.iter().cloned()),
+                DeleteTarget::Uids(more) => uids.extend(more),
+            }
+        }
+        match (addresses.is_empty(), uids.is_empty()) {
+            (false, false) => None,
+            (true, false) => {
+                uids.sort_unstable();
+                uids.dedup();
+                Some(DeleteTarget::Uids(uids))
+            }
+            _ => Some(DeleteTarget::Addresses(addresses)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            DeleteTarget::Addresses(addresses) => addresses.is_empty(),
+            DeleteTarget::Uids(uids) => uids.is_empty(),
+        }
+    }
+}
+
+/// Paces the commands that change a mailbox and backs off when the server
+/// says it is being hit too hard. One is shared by every sender in a run,
+/// so a backoff carries over to the next sender.
+pub struct Throttle {
+    /// Wait before every command; the user's setting.
+    pause: Duration,
+    /// Extra wait while the server is pushing back. Halves after each
+    /// accepted command.
+    backoff: Duration,
+    initial_backoff: Duration,
+    /// Throttling replies to the command currently being retried.
+    retries: u32,
+    /// Told how long the next wait is whenever a throttling reply comes in.
+    on_backoff: Box<dyn Fn(Duration) + Send + Sync>,
+}
+
+impl Throttle {
+    pub fn new(pause: Duration, on_backoff: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        Self {
+            pause,
+            backoff: Duration::ZERO,
+            initial_backoff: INITIAL_BACKOFF,
+            retries: 0,
+            on_backoff: Box::new(on_backoff),
+        }
+    }
+
+    async fn wait(&self) {
+        let delay = self.pause + self.backoff;
+        if !delay.is_zero() {
+            async_std::task::sleep(delay).await;
+        }
+    }
+
+    /// Records a command's reply and says whether to send it again: only
+    /// after a throttling reply, and at most [`MAX_THROTTLE_RETRIES`] times.
+    fn retry<T>(&mut self, result: &async_imap::error::Result<T>) -> bool {
+        match result {
+            Err(e) if is_throttled(e) && self.retries < MAX_THROTTLE_RETRIES => {
+                self.retries += 1;
+                self.backoff = (self.backoff * 2).clamp(self.initial_backoff, MAX_BACKOFF);
+                tracing::warn!(error = %e, retries = self.retries, "server is throttling");
+                (self.on_backoff)(self.pause + self.backoff);
+                true
+            }
+            Ok(_) => {
+                self.retries = 0;
+                self.backoff /= 2;
+                if self.backoff < self.initial_backoff {
+                    self.backoff = Duration::ZERO;
+                }
+                false
+            }
+            Err(_) => {
+                self.retries = 0;
+                false
+            }
+        }
+    }
+}
+
+/// Called by [`nuke_sender`] with the messages done so far and the total.
+pub type ChunkProgress<'a> = dyn Fn(usize, usize) + Sync + 'a;
+
+/// Sends a command that changes the mailbox under `throttle`: waits out the
+/// current pause first and repeats the command while the server answers
+/// with a throttling reply. Only for commands that are safe to repeat after
+/// a refusal.
+macro_rules! paced {
+    ($throttle:expr, $command:expr) => {
+        loop {
+            $throttle.wait().await;
+            let result = $command.await;
+            if !$throttle.retry(&result) {
+                break result;
+            }
+        }
+    };
+}
+
+fn is_throttled(error: &async_imap::error::Error) -> bool {
+    let (async_imap::error::Error::No(reply) | async_imap::error::Error::Bad(reply)) = error else {
+        return false;
+    };
+    let reply = reply.to_uppercase();
+    THROTTLE_HINTS.iter().any(|hint| reply.contains(hint))
+}
+
+/// Applies `mode` to `target`'s messages in `folder`, sparing the
+/// `keep_newest` most recent of them, at most `chunk_size` per command.
+/// `on_chunk` is called with the messages
+/// done so far and the total after each chunk, and `on_reconnect` the same
+/// way when the server drops the connection partway and the purge resumes
+/// on a new one. Returns how many were acted on.
+#[allow(clippy::too_many_arguments)] // connection settings plus what to delete and how
 pub async fn nuke_sender(
-    email: &str,
-    password: &str,
+    credentials: &Credentials,
     folder: &str,
-    sender: &str,
-    use_trash: bool,
+    target: &DeleteTarget,
+    mode: DeleteMode,
+    trash_folder: &str,
+    keep_newest: usize,
+    chunk_size: usize,
+    throttle: &mut Throttle,
+    on_chunk: &ChunkProgress<'_>,
+    on_reconnect: &ChunkProgress<'_>,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
 ) -> Result<usize, AppError> {
-    let mut session = connect_imap(email, password, folder).await?;
-    let provider = ImapProvider::from_email(email);
-
-    // Sanitize sender to prevent malformed IMAP search queries
-    let sanitized_sender = sender.replace('"', "");
-    let search_query = format!("FROM \"{sanitized_sender}\"");
-    let uids = session
-        .uid_search(&search_query)
+    if target.is_empty() {
+        return Ok(0);
+    }
+    let mut session = connect_imap(credentials, folder, options, limiter).await?;
+    let result = if session.is_read_only() {
+        Err(AppError::ReadOnlyFolder {
+            folder: folder.to_string(),
+        })
+    } else {
+        purge(
+            &mut session,
+            target,
+            mode,
+            trash_folder,
+            keep_newest,
+            chunk_size,
+            throttle,
+            on_chunk,
+            &mut Redial {
+                credentials,
+                folder,
+                options,
+                limiter,
+                on_reconnect,
+            },
+        )
         .await
-        .map_err(|e| AppError::Imap(e.to_string()))?;
+    };
+
+    if let Err(e) = session.logout().await {
+        tracing::warn!(error = %e, "logout failed after deletion");
+    }
+    result
+}
 
-    let uid_vec: Vec<u32> = uids.into_iter().collect();
+/// Replaces a session whose connection dropped partway through a purge.
+trait Reconnect<S> {
+    /// `done` of `total` messages were confirmed before the drop.
+    async fn reconnect(&mut self, session: &mut S, done: usize, total: usize)
+        -> Result<(), AppError>;
+}
+
+/// Logs in again with the settings of the original connection.
+struct Redial<'a> {
+    credentials: &'a Credentials,
+    folder: &'a str,
+    options: &'a ConnectionOptions,
+    limiter: &'a ConnectionLimiter,
+    on_reconnect: &'a ChunkProgress<'a>,
+}
+
+impl Reconnect<ImapConnection> for Redial<'_> {
+    async fn reconnect(
+        &mut self,
+        session: &mut ImapConnection,
+        done: usize,
+        total: usize,
+    ) -> Result<(), AppError> {
+        (self.on_reconnect)(done, total);
+        // The old connection is dead; dropping it frees its slot.
+        *session = connect_imap(self.credentials, self.folder, self.options, self.limiter).await?;
+        Ok(())
+    }
+}
+
+/// Applies `mode` to everything `target` covers in the selected folder
+/// except the `keep_newest` most recent, `chunk_size` messages at a time,
+/// fewer if their UID set would be too long for one command.
+/// If the connection drops between or during chunks, `reconnect` is given
+/// the session to replace along with the progress so far, and the purge
+/// picks up with whatever is still in the folder. Returns how many messages
+/// it acted on.
+#[allow(clippy::too_many_arguments)] // the delete's inputs plus its callbacks
+async fn purge<S: ImapOps>(
+    session: &mut S,
+    target: &DeleteTarget,
+    mode: DeleteMode,
+    trash_folder: &str,
+    keep_newest: usize,
+    chunk_size: usize,
+    throttle: &mut Throttle,
+    on_chunk: &ChunkProgress<'_>,
+    reconnect: &mut impl Reconnect<S>,
+) -> Result<usize, AppError> {
+    if mode == DeleteMode::Archive && !session.capabilities().supports_gmail_labels() {
+        return Err(AppError::Imap(
+            "Server doesn't support Gmail labels; archiving only works on Gmail".to_string(),
+        ));
+    }
+
+    let mut uid_vec = resolve_uids(session, target).await?;
+    if keep_newest > 0 {
+        uid_vec = all_but_newest(session, uid_vec, keep_newest).await?;
+    }
     let total = uid_vec.len();
+    on_chunk(0, total);
+    let mut done = 0;
+    let mut reconnects = 0;
+    // A chunk already copied into the trash when the connection dropped.
+    let mut copied = Vec::new();
+    while !uid_vec.is_empty() || !copied.is_empty() {
+        let (chunk, mut stage) = if copied.is_empty() {
+            let rest = uid_vec.split_off(uid_chunk_len(&uid_vec, chunk_size));
+            (std::mem::replace(&mut uid_vec, rest), ChunkStage::Pending)
+        } else {
+            (std::mem::take(&mut copied), ChunkStage::CopyDone)
+        };
+        let uid_str = uid_list(&chunk);
+        match purge_chunk(session, &uid_str, mode, trash_folder, throttle, &mut stage).await {
+            Ok(()) => {
+                done += chunk.len();
+                on_chunk(done, total);
+            }
+            Err(AppError::Disconnected(reason)) => {
+                if reconnects == MAX_RECONNECTS {
+                    return Err(AppError::Interrupted { done, total, reason });
+                }
+                reconnects += 1;
+                tracing::warn!(done, total, reason, "connection dropped mid-delete, reconnecting");
+                let interrupted = |e: AppError| AppError::Interrupted {
+                    done,
+                    total,
+                    reason: e.to_string(),
+                };
+                reconnect.reconnect(session, done, total).await.map_err(interrupted)?;
+                // The interrupted chunk may have gone through before the
+                // connection dropped, so only what is still there is redone.
+                // Once it is in the trash, that means removing it from here.
+                let before = chunk.len() + uid_vec.len();
+                let mut pending = chunk;
+                if stage == ChunkStage::CopyDone {
+                    copied = resolve_uids(session, &DeleteTarget::Uids(pending))
+                        .await
+                        .map_err(interrupted)?;
+                    pending = Vec::new();
+                }
+                pending.append(&mut uid_vec);
+                uid_vec = resolve_uids(session, &DeleteTarget::Uids(pending))
+                    .await
+                    .map_err(interrupted)?;
+                done += before - copied.len() - uid_vec.len();
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
 
-    if total == 0 {
-        if let Err(e) = session.logout().await {
-            tracing::warn!(error = %e, "logout failed after empty search");
+/// How far a chunk got, so a retry after a dropped connection doesn't copy
+/// it into the trash twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkStage {
+    Pending,
+    /// Copied into the trash; the originals still need removing.
+    CopyDone,
+}
+
+/// Applies `mode` to the messages in `uid_str`, moving `stage` on as steps
+/// complete.
+async fn purge_chunk<S: ImapOps>(
+    session: &mut S,
+    uid_str: &str,
+    mode: DeleteMode,
+    trash_folder: &str,
+    throttle: &mut Throttle,
+    stage: &mut ChunkStage,
+) -> Result<(), AppError> {
+    match mode {
+        DeleteMode::Archive => {
+            paced!(throttle, session.uid_store(uid_str, INBOX_LABEL_REMOVE))?;
         }
-        return Ok(0);
+        DeleteMode::Trash if session.capabilities().supports_move() => {
+            paced!(throttle, session.uid_mv(uid_str, trash_folder))
+                .map_err(|e| trash_error(e, trash_folder))?;
+        }
+        DeleteMode::Trash => {
+            // No MOVE extension: copy into the trash, then remove the
+            // originals the same way permanent deletion does.
+            if *stage == ChunkStage::Pending {
+                paced!(throttle, session.uid_copy(uid_str, trash_folder))
+                    .map_err(|e| trash_error(e, trash_folder))?;
+                *stage = ChunkStage::CopyDone;
+            }
+            flag_and_expunge(session, uid_str, throttle).await?;
+        }
+        DeleteMode::Permanent => flag_and_expunge(session, uid_str, throttle).await?,
     }
+    Ok(())
+}
 
-    for chunk in uid_vec.chunks(DELETE_CHUNK_SIZE) {
-        let uid_str = chunk
-            .iter()
-            .map(std::string::ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
+/// What a delete would do, checked against the server before it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteCheck {
+    /// Live match count per target, in the order the targets were given.
+    pub counts: Vec<usize>,
+    /// The trash folder, if it was checked and `LIST` doesn't return it.
+    pub missing_trash: Option<String>,
+}
 
-        if use_trash {
-            session
-                .uid_mv(&uid_str, provider.trash_folder)
-                .await
-                .map_err(|e| AppError::Imap(e.to_string()))?;
-        } else {
+/// Counts what deleting each target would remove right now, on a single
+/// connection, and makes sure `trash_folder` exists when one is given.
+/// Scan counts can be stale or cover only part of the folder.
+pub async fn check_delete(
+    credentials: &Credentials,
+    folder: &str,
+    targets: &[DeleteTarget],
+    trash_folder: Option<&str>,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Result<DeleteCheck, AppError> {
+    let mut session = connect_imap(credentials, folder, options, limiter).await?;
+
+    let mut counts = Vec::with_capacity(targets.len());
+    for target in targets {
+        counts.push(resolve_uids(&mut session, target).await?.len());
+    }
+
+    let missing_trash = match trash_folder {
+        Some(name) if !mailbox_exists(&mut session, name).await? => Some(name.to_string()),
+        _ => None,
+    };
+
+    if let Err(e) = session.logout().await {
+        tracing::warn!(error = %e, "logout failed after counting matches");
+    }
+    Ok(DeleteCheck {
+        counts,
+        missing_trash,
+    })
+}
+
+/// Whether `LIST` returns a mailbox named exactly `name`.
+async fn mailbox_exists(session: &mut ImapConnection, name: &str) -> Result<bool, AppError> {
+    let names: Vec<_> = session
+        .list(Some(""), Some(name))
+        .await
+        .map_err(|e| AppError::Imap(e.to_string()))?
+        .collect()
+        .await;
+    Ok(names
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|mailbox| mailbox.name() == name))
+}
+
+/// UIDs in the selected folder that `target` currently covers. Scanned UIDs
+/// are searched too, so messages already gone from the server are dropped.
+async fn resolve_uids<S: ImapOps>(
+    session: &mut S,
+    target: &DeleteTarget,
+) -> Result<Vec<u32>, AppError> {
+    let queries = match target {
+        DeleteTarget::Addresses(addresses) => from_queries(addresses),
+        DeleteTarget::Uids(uids) => uid_chunks(uids, LOOKUP_CHUNK_SIZE)
+            .map(|chunk| format!("UID {}", uid_list(chunk)))
+            .collect(),
+    };
+
+    let mut found = Vec::new();
+    for query in queries {
+        found.extend(
             session
-                .uid_store(&uid_str, "+FLAGS (\\Deleted)")
+                .uid_search(&query)
                 .await
-                .map_err(|e| AppError::Imap(e.to_string()))?
-                .collect::<Vec<_>>()
-                .await;
+                .map_err(|e| AppError::Imap(e.to_string()))?,
+        );
+    }
+    found.sort_unstable();
+    found.dedup();
+    Ok(found)
+}
 
+/// `uids` minus the `keep` with the latest `INTERNALDATE`. Messages the
+/// server gives no date for are left out of the result too: their age is
+/// unknown, so they are kept.
+async fn all_but_newest<S: ImapOps>(
+    session: &mut S,
+    uids: Vec<u32>,
+    keep: usize,
+) -> Result<Vec<u32>, AppError> {
+    if uids.len() <= keep {
+        return Ok(Vec::new());
+    }
+    let mut dated = Vec::with_capacity(uids.len());
+    for chunk in uid_chunks(&uids, LOOKUP_CHUNK_SIZE) {
+        dated.extend(
             session
-                .expunge()
+                .uid_fetch_dates(&uid_list(chunk))
                 .await
-                .map_err(|e| AppError::Imap(e.to_string()))?
-                .collect::<Vec<_>>()
-                .await;
+                .map_err(|e| AppError::Imap(e.to_string()))?,
+        );
+    }
+    // Newest first. UIDs grow with arrival, so they break ties.
+    dated.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    let mut older: Vec<u32> = dated.into_iter().skip(keep).map(|(uid, _)| uid).collect();
+    older.sort_unstable();
+    Ok(older)
+}
+
+/// Maps a failed COPY or MOVE into the trash, singling out a missing trash
+/// folder so the user is pointed at the setting instead of a raw reply.
+fn trash_error(error: async_imap::error::Error, trash_folder: &str) -> AppError {
+    match error {
+        async_imap::error::Error::No(reply) if is_missing_mailbox(&reply) => {
+            AppError::TrashNotFound {
+                folder: trash_folder.to_string(),
+            }
         }
+        _ => AppError::from(error),
     }
+}
 
-    if let Err(e) = session.logout().await {
-        tracing::warn!(error = %e, "logout failed after deletion");
+fn is_missing_mailbox(reply: &str) -> bool {
+    let reply = reply.to_uppercase();
+    MISSING_MAILBOX_HINTS.iter().any(|hint| reply.contains(hint))
+}
+
+/// `FROM` search matching any of `addresses`. IMAP's `OR` takes exactly two
+/// keys, so each extra address wraps the query in another `OR`.
+fn from_query(addresses: &[String]) -> String {
+    // Quotes are stripped to prevent malformed IMAP search queries
+    let mut keys = addresses
+        .iter()
+        .map(|address| format!("FROM \"{}\"", address.replace('"', "")));
+    let first = keys.next().unwrap_or_default();
+    keys.fold(first, |query, key| format!("OR {query} {key}"))
+}
+
+/// [`from_query`] searches that together match any of `addresses`, split
+/// so none gets longer than [`MAX_UID_SET_LEN`].
+fn from_queries(addresses: &[String]) -> Vec<String> {
+    let mut queries = Vec::new();
+    let mut start = 0;
+    let mut len = 0;
+    for (i, address) in addresses.iter().enumerate() {
+        // `OR FROM ""` around each address.
+        let key_len = address.len() + 11;
+        if i > start && len + key_len > MAX_UID_SET_LEN {
+            queries.push(from_query(&addresses[start..i]));
+            start = i;
+            len = 0;
+        }
+        len += key_len;
+    }
+    if start < addresses.len() {
+        queries.push(from_query(&addresses[start..]));
+    }
+    queries
+}
+
+/// Flags the messages in `uid_str` as `\Deleted` and expunges them.
+async fn flag_and_expunge<S: ImapOps>(
+    session: &mut S,
+    uid_str: &str,
+    throttle: &mut Throttle,
+) -> Result<(), AppError> {
+    paced!(throttle, session.uid_store(uid_str, DELETED_FLAG_STORE))?;
+
+    // Prefer UID EXPUNGE so messages the user flagged \Deleted elsewhere in
+    // the folder aren't swept up with ours.
+    if session.capabilities().supports_uidplus() {
+        paced!(throttle, session.uid_expunge(uid_str))?;
+    } else {
+        paced!(throttle, session.expunge())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap::ops::mock::MockSession;
+    use chrono::DateTime;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn purge_now(
+        session: &mut MockSession,
+        target: &DeleteTarget,
+        mode: DeleteMode,
+        keep_newest: usize,
+    ) -> Result<usize, AppError> {
+        let mut throttle = Throttle::new(Duration::ZERO, |_| {});
+        let no_progress = |_, _| {};
+        block_on(purge(
+            session,
+            target,
+            mode,
+            "Trash",
+            keep_newest,
+            1000,
+            &mut throttle,
+            &no_progress,
+            &mut Refused,
+        ))
+    }
+
+    /// Trashes `target` `chunk_size` messages per command, reporting each
+    /// chunk to `on_chunk` and resuming through `reconnect`.
+    fn trash_in_chunks(
+        session: &mut MockSession,
+        target: &DeleteTarget,
+        chunk_size: usize,
+        on_chunk: &ChunkProgress<'_>,
+        reconnect: &mut impl Reconnect<MockSession>,
+    ) -> Result<usize, AppError> {
+        let mut throttle = Throttle::new(Duration::ZERO, |_| {});
+        block_on(purge(
+            session,
+            target,
+            DeleteMode::Trash,
+            "Trash",
+            0,
+            chunk_size,
+            &mut throttle,
+            on_chunk,
+            reconnect,
+        ))
+    }
+
+    struct Refused;
+
+    impl Reconnect<MockSession> for Refused {
+        async fn reconnect(
+            &mut self,
+            _: &mut MockSession,
+            _: usize,
+            _: usize,
+        ) -> Result<(), AppError> {
+            Err(AppError::Connection("refused".to_string()))
+        }
+    }
+
+    /// Reconnects instantly, recording the progress it was called with.
+    #[derive(Default)]
+    struct Recorded(Vec<(usize, usize)>);
+
+    impl Reconnect<MockSession> for Recorded {
+        async fn reconnect(
+            &mut self,
+            _: &mut MockSession,
+            done: usize,
+            total: usize,
+        ) -> Result<(), AppError> {
+            self.0.push((done, total));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn archive_only_removes_the_inbox_label() {
+        let mut session = MockSession::new(&["X-GM-EXT-1"])
+            .with_messages("news@acme.com", 3)
+            .with_messages("friend@x.com", 1);
+        session.set_flag(2, "\\Seen");
+        let before = session.flags.clone();
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        let archived = purge_now(&mut session, &target, DeleteMode::Archive, 0);
+        assert_eq!(archived.unwrap(), 3);
+        let stores: Vec<_> = session.log.iter().filter(|c| c.starts_with("UID STORE")).collect();
+        assert_eq!(stores, ["UID STORE 1:3 -X-GM-LABELS (\\Inbox)"]);
+        assert_eq!(session.commands("UID EXPUNGE") + session.commands("EXPUNGE"), 0);
+        assert_eq!(session.flags, before);
+        assert!(session.deleted.is_empty());
+        assert_eq!(session.messages.len(), 4);
+    }
+
+    #[test]
+    fn missing_trash_is_told_apart_from_other_failures() {
+        let gmail = async_imap::error::Error::No("[TRYCREATE] No folder [Gmail]/Bin".into());
+        assert!(matches!(
+            trash_error(gmail, "[Gmail]/Bin"),
+            AppError::TrashNotFound { folder } if folder == "[Gmail]/Bin"
+        ));
+
+        let dovecot = async_imap::error::Error::No("Mailbox doesn't exist: Papierkorb".into());
+        assert!(trash_error(dovecot, "Papierkorb").is_config_error());
+
+        let quota = async_imap::error::Error::No("[OVERQUOTA] Quota exceeded".into());
+        assert!(matches!(trash_error(quota, "Trash"), AppError::Imap(_)));
+    }
+
+    #[test]
+    fn trash_moves_in_chunks_when_move_is_supported() {
+        let mut session = MockSession::new(&["MOVE", "UIDPLUS"])
+            .with_messages("news@acme.com", 2500)
+            .with_messages("friend@x.com", 1);
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        let reported = std::sync::Mutex::new(Vec::new());
+        let on_chunk = |done, total| reported.lock().unwrap().push((done, total));
+        let removed = trash_in_chunks(&mut session, &target, 1000, &on_chunk, &mut Refused);
+        assert_eq!(removed.unwrap(), 2500);
+        assert_eq!(session.commands("UID MOVE"), 3);
+        assert_eq!(
+            reported.into_inner().unwrap(),
+            [(0, 2500), (1000, 2500), (2000, 2500), (2500, 2500)]
+        );
+        assert_eq!(session.commands("UID STORE"), 0);
+        assert_eq!(session.messages.len(), 1);
+    }
+
+    #[test]
+    fn trash_without_move_copies_then_expunges_only_its_uids() {
+        let mut session = MockSession::new(&["UIDPLUS"])
+            .with_messages("news@acme.com", 3)
+            .with_messages("friend@x.com", 1);
+        // Flagged by another client; must survive our expunge.
+        session.deleted.insert(4);
+        let target = DeleteTarget::Uids(vec![1, 2, 3, 99]);
+
+        let removed = purge_now(&mut session, &target, DeleteMode::Trash, 0).unwrap();
+        assert_eq!(removed, 3);
+        let commands: Vec<_> = session.log.iter().skip(1).map(String::as_str).collect();
+        assert_eq!(
+            commands,
+            [
+                "UID COPY 1:3 Trash",
+                "UID STORE 1:3 +FLAGS (\\Deleted)",
+                "UID EXPUNGE 1:3",
+            ]
+        );
+        assert_eq!(session.messages.keys().copied().collect::<Vec<_>>(), [4]);
+    }
+
+    #[test]
+    fn permanent_delete_without_uidplus_falls_back_to_expunge() {
+        let mut session = MockSession::new(&[]).with_messages("news@acme.com", 2);
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        purge_now(&mut session, &target, DeleteMode::Permanent, 0).unwrap();
+        assert_eq!(session.commands("EXPUNGE"), 1);
+        assert!(session.messages.is_empty());
+    }
+
+    #[test]
+    fn missing_trash_stops_before_flagging_anything() {
+        let mut session = MockSession::new(&[]).with_messages("news@acme.com", 2);
+        session.copy_error = Some("[TRYCREATE] No such mailbox".to_string());
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        let result = purge_now(&mut session, &target, DeleteMode::Trash, 0);
+        assert!(matches!(result, Err(AppError::TrashNotFound { .. })));
+        assert!(session.deleted.is_empty());
+        assert_eq!(session.messages.len(), 2);
+    }
+
+    #[test]
+    fn keep_newest_spares_the_latest_by_date() {
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 5);
+        // Re-sent long after the others despite its low UID.
+        session.dates.insert(1, DateTime::from_timestamp(10_000_000, 0).unwrap());
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        let removed = purge_now(&mut session, &target, DeleteMode::Trash, 2);
+        assert_eq!(removed.unwrap(), 3);
+        assert_eq!(session.messages.keys().copied().collect::<Vec<_>>(), [1, 5]);
+
+        let removed = purge_now(&mut session, &target, DeleteMode::Trash, 2);
+        assert_eq!(removed.unwrap(), 0);
+        assert_eq!(session.commands("UID MOVE"), 1);
+    }
+
+    #[test]
+    fn purge_leaves_what_it_keeps_unread() {
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+        for (capabilities, mode) in [
+            (&["MOVE"][..], DeleteMode::Trash),
+            (&["UIDPLUS"], DeleteMode::Trash),
+            (&[], DeleteMode::Permanent),
+            (&["X-GM-EXT-1"], DeleteMode::Archive),
+        ] {
+            let mut session = MockSession::new(capabilities).with_messages("news@acme.com", 5);
+            session.set_flag(3, "\\Seen");
+            session.set_flag(4, "\\Flagged");
+            let before = session.flags.clone();
+
+            purge_now(&mut session, &target, mode, 3).unwrap();
+            assert_eq!(session.flags, before, "{mode:?} with {capabilities:?}");
+        }
+    }
+
+    #[test]
+    fn throttled_commands_are_retried_after_a_backoff() {
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 2);
+        session.throttled_replies = 2;
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+        let backoffs = Arc::new(AtomicUsize::new(0));
+        let mut throttle = Throttle::new(Duration::ZERO, {
+            let backoffs = Arc::clone(&backoffs);
+            move |_| {
+                backoffs.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        throttle.initial_backoff = Duration::ZERO;
+
+        let mode = DeleteMode::Trash;
+        let removed = block_on(purge(
+            &mut session,
+            &target,
+            mode,
+            "Trash",
+            0,
+            1000,
+            &mut throttle,
+            &|_, _| {},
+            &mut Refused,
+        ));
+        assert_eq!(removed.unwrap(), 2);
+        assert_eq!(session.commands("UID MOVE"), 3);
+        assert_eq!(backoffs.load(Ordering::Relaxed), 2);
+
+        session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 1);
+        session.throttled_replies = usize::MAX;
+        let result = block_on(purge(
+            &mut session,
+            &target,
+            mode,
+            "Trash",
+            0,
+            1000,
+            &mut throttle,
+            &|_, _| {},
+            &mut Refused,
+        ));
+        assert!(result.is_err());
+        assert_eq!(session.commands("UID MOVE") as u32, MAX_THROTTLE_RETRIES + 1);
+    }
+
+    #[test]
+    fn chunk_size_sets_messages_per_command() {
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 1200);
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+        let removed = trash_in_chunks(&mut session, &target, 500, &|_, _| {}, &mut Refused);
+        assert_eq!(removed.unwrap(), 1200);
+        assert_eq!(session.commands("UID MOVE"), 3);
+    }
+
+    #[test]
+    fn dropped_connection_resumes_with_what_is_left() {
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 2500);
+        // The first chunk is moved, but its reply never arrives.
+        session.dropped_replies = 1;
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+        let mut reconnects = Recorded::default();
+
+        let purged = trash_in_chunks(&mut session, &target, 1000, &|_, _| {}, &mut reconnects);
+        assert_eq!(purged.unwrap(), 2500);
+        assert_eq!(reconnects.0, [(0, 2500)]);
+        assert_eq!(session.commands("UID MOVE"), 3);
+        assert!(session.messages.is_empty());
+
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 1500);
+        session.dropped_replies = 2;
+        let result = purge_now(&mut session, &target, DeleteMode::Trash, 0);
+        assert!(matches!(
+            result,
+            Err(AppError::Interrupted { done: 0, total: 1500, .. })
+        ));
+    }
+
+    #[test]
+    fn dropped_store_after_copy_only_redoes_the_removal() {
+        let mut session = MockSession::new(&["UIDPLUS"]).with_messages("news@acme.com", 1500);
+        // The first chunk is copied and flagged, but the flag reply is lost.
+        session.dropped_store_replies = 1;
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+        let mut reconnects = Recorded::default();
+
+        let purged = trash_in_chunks(&mut session, &target, 1000, &|_, _| {}, &mut reconnects);
+        assert_eq!(purged.unwrap(), 1500);
+        assert_eq!(reconnects.0, [(0, 1500)]);
+        assert_eq!(session.commands("UID COPY"), 2);
+        assert_eq!(session.copied, (1..=1500).collect::<Vec<_>>());
+        assert!(session.messages.is_empty());
+    }
+
+    #[test]
+    fn throttling_is_told_apart_from_refusals() {
+        for reply in ["[THROTTLED] Account exceeded command limit", "[LIMIT] Too many commands"] {
+            assert!(is_throttled(&async_imap::error::Error::No(reply.into())));
+        }
+        assert!(!is_throttled(&async_imap::error::Error::No("[TRYCREATE] No folder".into())));
+    }
+
+    #[test]
+    fn archive_needs_gmail_labels() {
+        let mut session = MockSession::new(&["MOVE"]).with_messages("news@acme.com", 1);
+        let target = DeleteTarget::Addresses(vec!["news@acme.com".to_string()]);
+
+        let result = purge_now(&mut session, &target, DeleteMode::Archive, 0);
+        assert!(result.is_err());
+        assert!(session.log.is_empty());
+    }
+
+    #[test]
+    fn combined_senders_share_one_search() {
+        let mut session = MockSession::new(&["MOVE"])
+            .with_messages("a@x.com", 3)
+            .with_messages("b@y.com", 2)
+            .with_messages("keep@z.com", 1);
+        let targets = [
+            DeleteTarget::Addresses(vec!["a@x.com".to_string()]),
+            DeleteTarget::Addresses(vec!["b@y.com".to_string()]),
+        ];
+        let union = DeleteTarget::union(&targets).unwrap();
+        assert_eq!(purge_now(&mut session, &union, DeleteMode::Trash, 0).unwrap(), 5);
+        assert_eq!(session.commands("UID SEARCH"), 1);
+        assert_eq!(session.commands("UID MOVE"), 1);
+        assert_eq!(session.messages.len(), 1);
+
+        let many: Vec<String> = (0..500).map(|i| format!("sender{i}@example.com")).collect();
+        let queries = from_queries(&many);
+        assert!(queries.len() > 1);
+        assert!(queries.iter().all(|query| query.len() <= MAX_UID_SET_LEN));
+        let keys: usize = queries.iter().map(|query| query.matches("FROM").count()).sum();
+        assert_eq!(keys, 500);
+
+        let mixed = [DeleteTarget::Uids(vec![1]), targets[0].clone()];
+        assert_eq!(DeleteTarget::union(&mixed), None);
+    }
+
+    #[test]
+    fn from_query_nests_or_for_aliases() {
+        let one = vec!["a@x.com".to_string()];
+        assert_eq!(from_query(&one), "FROM \"a@x.com\"");
+
+        let three = vec!["a@x.com".to_string(), "b@x.com".to_string(), "c\"@x.com".to_string()];
+        assert_eq!(
+            from_query(&three),
+            "OR OR FROM \"a@x.com\" FROM \"b@x.com\" FROM \"c@x.com\""
+        );
     }
-    Ok(total)
 }
@@ -0,0 +1,143 @@
+//! Step-by-step connection check for bug reports. Goes through the same
+//! phases as [`connect_imap`](super::connect_imap), but reports each one
+//! separately instead of collapsing them into one error.
+
+use super::provider::ImapProvider;
+use super::{login, open_tcp, tls_connector, ConnectionLimiter, Credentials, CONNECT_TIMEOUT};
+use crate::error::AppError;
+use crate::state::ConnectionOptions;
+use async_std::net::ToSocketAddrs;
+use std::fmt::Write;
+use std::future::Future;
+
+/// One phase of a diagnosis and how it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticStep {
+    pub name: &'static str,
+    /// What was found on success, or why the step failed.
+    pub outcome: Result<String, String>,
+}
+
+impl DiagnosticStep {
+    fn new(name: &'static str, outcome: Result<String, String>) -> Self {
+        Self { name, outcome }
+    }
+}
+
+/// Detects the provider, then resolves, connects to, handshakes with and
+/// signs in to its server. Stops at the first failed step, which is the
+/// last one returned. Waits for a free connection slot like any session.
+pub async fn diagnose(
+    credentials: &Credentials,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Vec<DiagnosticStep> {
+    let provider = ImapProvider::from_email(&credentials.email);
+    let server = format!("{}:{}", provider.host, provider.port);
+    let detected = if ImapProvider::is_fallback(&credentials.email) {
+        format!("unknown provider, trying {server}")
+    } else {
+        format!("{:?} at {server}", provider.kind)
+    };
+    let mut steps = vec![DiagnosticStep::new("Provider", Ok(detected))];
+
+    let lookup = match &options.proxy {
+        Some(proxy) => Ok(format!("left to proxy {}:{}", proxy.host, proxy.port)),
+        None => resolve(provider.host, provider.port).await,
+    };
+    if !push(&mut steps, DiagnosticStep::new("DNS lookup", lookup)) {
+        return steps;
+    }
+
+    let _permit = limiter.acquire(&credentials.email, &provider).await;
+    let tcp = timed(open_tcp(provider.host, provider.port, options.proxy.as_ref())).await;
+    let connected = tcp.as_ref().map(|_| format!("connected to {server}")).map_err(Clone::clone);
+    if !push(&mut steps, DiagnosticStep::new("TCP connect", connected)) {
+        return steps;
+    }
+    let Ok(tcp) = tcp else { return steps };
+
+    let tls = match tls_connector(&options.tls) {
+        Ok(tls) => timed(async { Ok(tls.connect(provider.host, tcp).await?) }).await,
+        Err(e) => Err(e.to_string()),
+    };
+    let handshake = tls.as_ref().map(|_| "certificate accepted".to_string()).map_err(Clone::clone);
+    if !push(&mut steps, DiagnosticStep::new("TLS handshake", handshake)) {
+        return steps;
+    }
+    let Ok(tls_stream) = tls else { return steps };
+
+    let session = timed(login(credentials, options, &provider, tls_stream)).await;
+    let signed_in = match session {
+        Ok(mut session) => {
+            if let Err(e) = session.logout().await {
+                tracing::warn!(error = %e, "logout failed after diagnosis");
+            }
+            Ok(format!("signed in as {}", credentials.email))
+        }
+        Err(e) => Err(e),
+    };
+    steps.push(DiagnosticStep::new("Login", signed_in));
+    steps
+}
+
+/// Plain-text version of `steps`, for pasting into a bug report.
+pub fn report(steps: &[DiagnosticStep]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        let _ = match &step.outcome {
+            Ok(detail) => writeln!(out, "PASS {}: {detail}", step.name),
+            Err(reason) => writeln!(out, "FAIL {}: {reason}", step.name),
+        };
+    }
+    out
+}
+
+/// Adds `step`, returning whether it passed and the next one should run.
+fn push(steps: &mut Vec<DiagnosticStep>, step: DiagnosticStep) -> bool {
+    let passed = step.outcome.is_ok();
+    steps.push(step);
+    passed
+}
+
+async fn resolve(host: &str, port: u16) -> Result<String, String> {
+    let addresses: Vec<String> = timed(async {
+        (host, port)
+            .to_socket_addrs()
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))
+    })
+    .await?
+    .map(|address| address.ip().to_string())
+    .collect();
+    if addresses.is_empty() {
+        return Err(format!("{host} has no addresses"));
+    }
+    Ok(format!("{host} is {}", addresses.join(", ")))
+}
+
+/// Runs one step within [`CONNECT_TIMEOUT`], so a server that stops
+/// answering fails the step instead of hanging the diagnosis.
+async fn timed<T>(step: impl Future<Output = Result<T, AppError>>) -> Result<T, String> {
+    match async_std::future::timeout(CONNECT_TIMEOUT, step).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("timed out after {}s", CONNECT_TIMEOUT.as_secs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_marks_each_step() {
+        let steps = [
+            DiagnosticStep::new("Provider", Ok("Gmail at imap.gmail.com:993".to_string())),
+            DiagnosticStep::new("DNS lookup", Err("no such host".to_string())),
+        ];
+        assert_eq!(
+            report(&steps),
+            "PASS Provider: Gmail at imap.gmail.com:993\nFAIL DNS lookup: no such host\n"
+        );
+    }
+}
@@ -1,46 +1,577 @@
+pub mod capabilities;
 pub mod deleter;
+pub mod diagnose;
+pub mod ops;
 pub mod provider;
 pub mod scanner;
+pub mod sieve;
 
 use crate::error::AppError;
+use crate::oauth::{OAuthLogin, XOAuth2};
+use capabilities::ServerCapabilities;
+use crate::state::{ConnectionOptions, ProxyConfig, TlsConfig};
+use async_imap::imap_proto::{MailboxDatum, Response, ResponseCode, Status};
+use async_std::net::TcpStream;
+use futures::StreamExt;
 use provider::ImapProvider;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use zeroize::Zeroizing;
 
 /// TCP connect timeout. 30s is generous enough for high-latency networks
 /// while still failing fast on unreachable hosts.
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Limit on the whole of opening a session once a connection slot is free:
+/// TCP, TLS, login and `SELECT`. A server that accepts the connection and
+/// then stops answering would otherwise hang the job.
+const SETUP_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// Longest UID set or search put in one command. RFC 7162 asks clients to
+/// keep command lines under 8192 octets, and some servers reject anything
+/// longer; this leaves room for the rest of the command.
+pub const MAX_UID_SET_LEN: usize = 4000;
+
+/// Login for one account, shared by reference between every connection a
+/// job opens rather than copied into each. The password is wiped on drop.
+#[derive(Clone)]
+pub struct Credentials {
+    pub email: String,
+    pub password: Zeroizing<String>,
+    /// Sign in with `XOAUTH2` instead, ignoring `password`.
+    pub oauth: Option<Arc<OAuthLogin>>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("email", &self.email)
+            .field("password", &"<redacted>")
+            .field("oauth", &self.oauth.is_some())
+            .finish()
+    }
+}
+
+pub type ImapSession = async_imap::Session<async_native_tls::TlsStream<TcpStream>>;
+
+/// Caps simultaneous IMAP sessions per account across every scan and delete
+/// task, so overlapping jobs can't exceed the provider's connection limit.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimiter {
+    accounts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    /// Fires when the app shuts down. Connections still being set up are
+    /// abandoned rather than left to time out.
+    closing: CancellationToken,
+}
+
+impl ConnectionLimiter {
+    pub fn new(closing: CancellationToken) -> Self {
+        Self {
+            accounts: Arc::default(),
+            closing,
+        }
+    }
+
+    async fn acquire(&self, email: &str, provider: &ImapProvider) -> OwnedSemaphorePermit {
+        let semaphore = {
+            let mut accounts = self
+                .accounts
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            accounts
+                .entry(email.to_lowercase())
+                .or_insert_with(|| Arc::new(Semaphore::new(provider.max_connections)))
+                .clone()
+        };
+        // The semaphore is never closed, so acquiring can't fail.
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("connection semaphore closed")
+    }
+}
+
+/// A logged-in session that holds one of the account's connection slots
+/// until it is dropped.
+pub struct ImapConnection {
+    session: ImapSession,
+    capabilities: ServerCapabilities,
+    exists: u32,
+    read_only: bool,
+    uid_validity: Option<u32>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ImapConnection {
+    /// What the server advertised at connect time. Cached, so this doesn't
+    /// issue another `CAPABILITY` command.
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+
+    /// Messages in the selected folder, from the `SELECT` response's
+    /// `EXISTS`. Same figure as `STATUS (MESSAGES)`, without another command.
+    pub fn message_count(&self) -> u32 {
+        self.exists
+    }
+
+    /// Whether the server opened the folder with `[READ-ONLY]`, in which
+    /// case flag changes, moves and expunges will all be refused.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The folder's `UIDVALIDITY` from the `SELECT` response. UIDs only
+    /// name the same messages while this stays the same.
+    pub fn uid_validity(&self) -> Option<u32> {
+        self.uid_validity
+    }
+}
+
+impl Deref for ImapConnection {
+    type Target = ImapSession;
+
+    fn deref(&self) -> &ImapSession {
+        &self.session
+    }
+}
+
+impl DerefMut for ImapConnection {
+    fn deref_mut(&mut self) -> &mut ImapSession {
+        &mut self.session
+    }
+}
+
+/// Opens a session on `folder` once one of the account's connection slots
+/// is free. Setting it up is bounded by [`SETUP_TIMEOUT`], and waiting for
+/// the slot or the server stops as soon as the limiter is closed.
 pub async fn connect_imap(
-    email: &str,
-    password: &str,
+    credentials: &Credentials,
     folder: &str,
-) -> Result<async_imap::Session<async_native_tls::TlsStream<async_std::net::TcpStream>>, AppError>
-{
-    let provider = ImapProvider::from_email(email);
-    let tls = async_native_tls::TlsConnector::new();
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Result<ImapConnection, AppError> {
+    let provider = ImapProvider::from_email(&credentials.email);
+    let permit = tokio::select! {
+        permit = limiter.acquire(&credentials.email, &provider) => permit,
+        () = limiter.closing.cancelled() => return Err(AppError::Cancelled),
+    };
+    let setup = async_std::future::timeout(
+        SETUP_TIMEOUT,
+        open_session(credentials, folder, options, &provider, permit),
+    );
+    tokio::select! {
+        result = setup => result.map_err(|_| {
+            AppError::Connection(format!(
+                "Connecting timed out after {}s",
+                SETUP_TIMEOUT.as_secs()
+            ))
+        })?,
+        () = limiter.closing.cancelled() => Err(AppError::Cancelled),
+    }
+}
+
+/// The steps of [`connect_imap`] after a slot is granted. `permit` moves
+/// into the connection, or is released if this fails or is dropped.
+async fn open_session(
+    credentials: &Credentials,
+    folder: &str,
+    options: &ConnectionOptions,
+    provider: &ImapProvider,
+    permit: OwnedSemaphorePermit,
+) -> Result<ImapConnection, AppError> {
+    let tls = tls_connector(&options.tls)?;
     let tcp = async_std::future::timeout(
         CONNECT_TIMEOUT,
-        async_std::net::TcpStream::connect((provider.host, provider.port)),
+        open_tcp(provider.host, provider.port, options.proxy.as_ref()),
     )
     .await
-    .map_err(|_| AppError::Connection("TCP connect timed out after 30s".to_string()))?
-    .map_err(|e| AppError::Connection(e.to_string()))?;
+    .map_err(|_| AppError::Connection("TCP connect timed out after 30s".to_string()))??;
 
     let tls_stream = tls
         .connect(provider.host, tcp)
         .await
-        .map_err(|e| AppError::Tls(e.to_string()))?;
+        .map_err(AppError::from)?;
+
+    let mut session = login(credentials, options, provider, tls_stream).await?;
+
+    // Servers usually send capabilities with the login response, but that
+    // isn't guaranteed, so ask explicitly. Without an answer, assume a
+    // minimal server and use only base IMAP4rev1 commands.
+    let capabilities = match session.capabilities().await {
+        Ok(caps) => ServerCapabilities::from_response(&caps),
+        Err(e) => {
+            tracing::warn!(error = %e, "CAPABILITY failed, assuming a minimal server");
+            ServerCapabilities::default()
+        }
+    };
+    tracing::debug!(?capabilities, "server capabilities");
 
+    let selected = match select_folder(&mut session, folder).await {
+        Err(AppError::Imap(reason)) => select_recased(&mut session, folder, reason).await?,
+        result => result?,
+    };
+
+    Ok(ImapConnection {
+        session,
+        capabilities,
+        exists: selected.exists,
+        read_only: selected.read_only,
+        uid_validity: selected.uid_validity,
+        _permit: permit,
+    })
+}
+
+/// Signs in over an established TLS stream, with the app password or
+/// `XOAUTH2`.
+async fn login(
+    credentials: &Credentials,
+    options: &ConnectionOptions,
+    provider: &ImapProvider,
+    tls_stream: async_native_tls::TlsStream<TcpStream>,
+) -> Result<ImapSession, AppError> {
+    let Credentials {
+        email,
+        password,
+        oauth,
+    } = credentials;
     let client = async_imap::Client::new(tls_stream);
-    let mut session = client
-        .login(email, password)
+    let login = match oauth {
+        Some(oauth) => {
+            let token = oauth.access_token(email, options).await?;
+            client.authenticate("XOAUTH2", XOAuth2::new(email, &token)).await
+        }
+        None => client.login(email, password.as_str()).await,
+    };
+    login.map_err(|(e, _)| AppError::Auth {
+        message: e.to_string(),
+        provider: provider.kind,
+    })
+}
+
+/// What `SELECT` reported about the folder.
+struct SelectedFolder {
+    exists: u32,
+    read_only: bool,
+    uid_validity: Option<u32>,
+}
+
+/// Selects `folder`, reading the responses here rather than through
+/// async-imap's `select`, which drops the tagged response code and with it
+/// `[READ-ONLY]`.
+async fn select_folder(
+    session: &mut ImapSession,
+    folder: &str,
+) -> Result<SelectedFolder, AppError> {
+    if folder.contains(['\r', '\n']) {
+        return Err(AppError::Imap("Folder name can't contain line breaks".to_string()));
+    }
+    let quoted = format!("\"{}\"", folder.replace('\\', "\\\\").replace('"', "\\\""));
+    let id = session.run_command(format!("SELECT {quoted}")).await?;
+
+    let mut exists = 0;
+    let mut uid_validity = None;
+    while let Some(response) = session.read_response().await {
+        let response = response.map_err(|e| AppError::Connection(e.to_string()))?;
+        match response.parsed() {
+            Response::MailboxData(MailboxDatum::Exists(count)) => exists = *count,
+            Response::Data {
+                code: Some(ResponseCode::UidValidity(value)),
+                ..
+            } => uid_validity = Some(*value),
+            Response::Done {
+                tag,
+                status,
+                code,
+                information,
+            } if *tag == id => {
+                return match status {
+                    Status::Ok => Ok(SelectedFolder {
+                        exists,
+                        read_only: matches!(code, Some(ResponseCode::ReadOnly)),
+                        uid_validity,
+                    }),
+                    _ => Err(AppError::Imap(format!(
+                        "Cannot open folder {quoted}: {}",
+                        information.as_deref().unwrap_or("no reason given")
+                    ))),
+                };
+            }
+            _ => {}
+        }
+    }
+    Err(AppError::Connection("Connection closed while opening the folder".to_string()))
+}
+
+/// Retries a refused `SELECT` with the casing `LIST` reports for `folder`,
+/// since most servers treat names like "spam" and "Spam" as different
+/// folders. When several folders differ only in case, none is guessed and
+/// the error names them instead.
+async fn select_recased(
+    session: &mut ImapSession,
+    folder: &str,
+    reason: String,
+) -> Result<SelectedFolder, AppError> {
+    match recased_folders(session, folder).await.as_slice() {
+        [] => Err(AppError::Imap(reason)),
+        [name] => {
+            tracing::info!(requested = folder, actual = %name, "retrying SELECT as listed");
+            select_folder(session, name).await
+        }
+        names => Err(AppError::Imap(format!(
+            "{reason}. Did you mean \"{}\"?",
+            names.join("\" or \"")
+        ))),
+    }
+}
+
+/// Folders whose names match `folder` ignoring case, but not exactly. A
+/// failed `LIST` just means there's nothing to suggest.
+async fn recased_folders(session: &mut ImapSession, folder: &str) -> Vec<String> {
+    let Ok(names) = list_folders(session).await else {
+        return Vec::new();
+    };
+    names
+        .into_iter()
+        .filter(|name| name != folder && name.eq_ignore_ascii_case(folder))
+        .collect()
+}
+
+/// Every folder `LIST` returns for the account.
+async fn list_folders(session: &mut ImapSession) -> Result<Vec<String>, AppError> {
+    let mailboxes: Vec<_> = session
+        .list(Some(""), Some("*"))
+        .await
+        .map_err(|e| AppError::Imap(e.to_string()))?
+        .collect()
+        .await;
+    Ok(mailboxes
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|mailbox| mailbox.name().to_string())
+        .collect())
+}
+
+/// Which of `folders` the server doesn't list, for checking the special
+/// folder settings before a scan or delete depends on them.
+pub async fn missing_folders(
+    credentials: &Credentials,
+    folders: &[String],
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Result<Vec<String>, AppError> {
+    let mut session = connect_imap(credentials, "INBOX", options, limiter).await?;
+    let listed = list_folders(&mut session).await;
+    if let Err(e) = session.logout().await {
+        tracing::warn!(error = %e, "logout failed after listing folders");
+    }
+    let listed = listed?;
+    Ok(folders
+        .iter()
+        .filter(|folder| !listed.contains(folder))
+        .cloned()
+        .collect())
+}
+
+/// The folder's current `UIDVALIDITY`, for checking that UIDs recorded
+/// earlier still name the same messages. `None` if the server didn't say.
+pub async fn folder_uid_validity(
+    credentials: &Credentials,
+    folder: &str,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+) -> Result<Option<u32>, AppError> {
+    let mut session = connect_imap(credentials, folder, options, limiter).await?;
+    let uid_validity = session.uid_validity();
+    if let Err(e) = session.logout().await {
+        tracing::warn!(error = %e, "logout failed after checking UIDVALIDITY");
+    }
+    Ok(uid_validity)
+}
+
+/// Formats UIDs as an IMAP sequence set, writing consecutive runs as
+/// `first:last` ranges, e.g. `1:3,7`.
+pub fn uid_list(uids: &[u32]) -> String {
+    let mut out = String::new();
+    for (start, end) in uid_runs(uids) {
+        if !out.is_empty() {
+            out.push(',');
+        }
+        let _ = write!(out, "{start}");
+        if end != start {
+            let _ = write!(out, ":{end}");
+        }
+    }
+    out
+}
+
+/// Consecutive runs in `uids`, in order, as `(first, last)`.
+fn uid_runs(uids: &[u32]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    let mut rest = uids;
+    std::iter::from_fn(move || {
+        let (&start, _) = rest.split_first()?;
+        let len = rest
+            .iter()
+            .zip(u64::from(start)..)
+            .take_while(|(uid, expected)| u64::from(**uid) == *expected)
+            .count();
+        let end = rest[len - 1];
+        rest = &rest[len..];
+        Some((start, end))
+    })
+}
+
+/// Splits `uids` into pieces of at most `max` whose [`uid_list`] stays
+/// within [`MAX_UID_SET_LEN`], so no command line gets too long for the
+/// server however sparse the UIDs are.
+pub fn uid_chunks(uids: &[u32], max: usize) -> impl Iterator<Item = &[u32]> {
+    let mut rest = uids;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (chunk, tail) = rest.split_at(uid_chunk_len(rest, max));
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// Length of the first piece [`uid_chunks`] would cut from `uids`. Never
+/// zero for a non-empty list.
+pub fn uid_chunk_len(uids: &[u32], max: usize) -> usize {
+    let digits = |uid: u32| uid.checked_ilog10().unwrap_or(0) as usize + 1;
+    let run_len = |start: u32, end: u32| {
+        if start == end { digits(start) } else { digits(start) + 1 + digits(end) }
+    };
+    // Length of the runs before the current one, separators included.
+    let mut closed = 0;
+    let mut run: Option<(u32, u32)> = None;
+    for (i, &uid) in uids.iter().enumerate().take(max) {
+        let (start, end) = match run {
+            Some((start, end)) if end.checked_add(1) == Some(uid) => (start, uid),
+            Some((start, end)) => {
+                closed += run_len(start, end) + 1;
+                (uid, uid)
+            }
+            None => (uid, uid),
+        };
+        if i > 0 && closed + run_len(start, end) > MAX_UID_SET_LEN {
+            return i;
+        }
+        run = Some((start, end));
+    }
+    uids.len().min(max)
+}
+
+/// Builds the TLS connector, layering any user-supplied trust settings on
+/// top of the platform defaults.
+pub(crate) fn tls_connector(
+    config: &TlsConfig,
+) -> Result<async_native_tls::TlsConnector, AppError> {
+    let mut tls = async_native_tls::TlsConnector::new();
+
+    let ca_path = config.ca_cert_path.trim();
+    if !ca_path.is_empty() {
+        let pem = std::fs::read(ca_path)
+            .map_err(|e| AppError::Tls(format!("cannot read CA certificate {ca_path}: {e}")))?;
+        let cert = async_native_tls::Certificate::from_pem(&pem)
+            .map_err(|e| AppError::Tls(format!("invalid CA certificate {ca_path}: {e}")))?;
+        tls = tls.add_root_certificate(cert);
+    }
+
+    if let Some(identity) = client_identity(config)? {
+        tls = tls.identity(identity);
+    }
+
+    if config.accept_invalid_certs {
+        tracing::warn!("TLS certificate validation is disabled");
+        tls = tls.danger_accept_invalid_certs(true);
+    }
+
+    Ok(tls)
+}
+
+/// Loads the client certificate, if one is configured. Without a separate
+/// key file the certificate is read as a PKCS#12 bundle holding both.
+fn client_identity(config: &TlsConfig) -> Result<Option<async_native_tls::Identity>, AppError> {
+    let cert_path = config.client_cert_path.trim();
+    if cert_path.is_empty() {
+        return Ok(None);
+    }
+    let read = |path: &str, what: &str| {
+        std::fs::read(path).map_err(|e| AppError::Tls(format!("cannot read {what} {path}: {e}")))
+    };
+    let cert = read(cert_path, "client certificate")?;
+    let key_path = config.client_key_path.trim();
+    let identity = if key_path.is_empty() {
+        async_native_tls::Identity::from_pkcs12(&cert, &config.client_cert_password)
+    } else {
+        async_native_tls::Identity::from_pkcs8(&cert, &read(key_path, "client key")?)
+    };
+    identity
+        .map(Some)
+        .map_err(|e| AppError::Tls(format!("invalid client certificate {cert_path}: {e}")))
+}
+
+/// Opens the TCP stream to `host`, tunnelling through a SOCKS5 proxy when
+/// one is configured. The returned stream is ready for the TLS handshake.
+pub(crate) async fn open_tcp(
+    host: &str,
+    port: u16,
+    proxy: Option<&ProxyConfig>,
+) -> Result<TcpStream, AppError> {
+    let Some(proxy) = proxy else {
+        return TcpStream::connect((host, port))
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()));
+    };
+
+    let stream = TcpStream::connect((proxy.host.as_str(), proxy.port))
         .await
-        .map_err(|(e, _)| AppError::Auth(e.to_string()))?;
+        .map_err(|e| {
+            AppError::Connection(format!("proxy {}:{} unreachable: {e}", proxy.host, proxy.port))
+        })?;
 
-    session
-        .select(folder)
+    let auth = (!proxy.username.is_empty())
+        .then(|| async_socks5::Auth::new(proxy.username.as_str(), proxy.password.as_str()));
+
+    // async-socks5 speaks tokio's IO traits; the compat wrapper is unwrapped
+    // again once the handshake is done so the rest of the stack is unchanged.
+    let mut compat = stream.compat();
+    async_socks5::connect(&mut compat, (host, port), auth)
         .await
-        .map_err(|e| AppError::Imap(e.to_string()))?;
+        .map_err(|e| AppError::Connection(format!("SOCKS5 proxy handshake failed: {e}")))?;
+
+    Ok(compat.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uid_sets_use_ranges_and_stay_short() {
+        assert_eq!(uid_list(&[1, 2, 3, 7, 9, 10]), "1:3,7,9:10");
+        assert_eq!(uid_list(&[5]), "5");
+        assert_eq!(uid_list(&[]), "");
+
+        // Contiguous UIDs compress to one range, so only `max` splits them.
+        let dense: Vec<u32> = (1..=5000).collect();
+        assert_eq!(uid_chunks(&dense, 1000).count(), 5);
 
-    Ok(session)
+        // Sparse ten-digit UIDs overflow the line length well before `max`.
+        let sparse: Vec<u32> = (0..1000).map(|i| 4_000_000_000 + i * 2).collect();
+        let chunks: Vec<&[u32]> = uid_chunks(&sparse, 1000).collect();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(|c| c.len()).sum::<usize>(), sparse.len());
+        for chunk in chunks {
+            assert!(uid_list(chunk).len() <= MAX_UID_SET_LEN);
+        }
+    }
 }
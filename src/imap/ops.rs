@@ -0,0 +1,372 @@
+//! The handful of IMAP commands the scanner and deleter issue, behind a
+//! trait so their logic can run against canned responses in tests.
+
+use async_imap::error::Result as ImapResult;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+
+use super::capabilities::ServerCapabilities;
+use super::ImapConnection;
+
+/// One message from a `UID FETCH` of header fields.
+#[derive(Debug, Clone)]
+pub struct FetchedHeader {
+    pub uid: Option<u32>,
+    pub header: Vec<u8>,
+    pub internal_date: Option<DateTime<Utc>>,
+    /// `RFC822.SIZE`, when it was requested.
+    pub size: Option<u32>,
+}
+
+/// Commands on a session with a folder selected. Replies that only echo
+/// flags or expunged sequence numbers are drained and dropped.
+pub trait ImapOps {
+    fn capabilities(&self) -> &ServerCapabilities;
+
+    async fn uid_search(&mut self, query: &str) -> ImapResult<Vec<u32>>;
+
+    /// Messages in `uid_set` that came back with a header section.
+    async fn uid_fetch(&mut self, uid_set: &str, query: &str) -> ImapResult<Vec<FetchedHeader>>;
+
+    /// `INTERNALDATE` of each message in `uid_set` that reported one.
+    async fn uid_fetch_dates(&mut self, uid_set: &str) -> ImapResult<Vec<(u32, DateTime<Utc>)>>;
+
+    async fn uid_mv(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()>;
+
+    async fn uid_copy(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()>;
+
+    async fn uid_store(&mut self, uid_set: &str, query: &str) -> ImapResult<()>;
+
+    async fn uid_expunge(&mut self, uid_set: &str) -> ImapResult<()>;
+
+    async fn expunge(&mut self) -> ImapResult<()>;
+}
+
+impl ImapOps for ImapConnection {
+    fn capabilities(&self) -> &ServerCapabilities {
+        ImapConnection::capabilities(self)
+    }
+
+    async fn uid_search(&mut self, query: &str) -> ImapResult<Vec<u32>> {
+        Ok(self.session.uid_search(query).await?.into_iter().collect())
+    }
+
+    async fn uid_fetch(&mut self, uid_set: &str, query: &str) -> ImapResult<Vec<FetchedHeader>> {
+        let mut stream = self.session.uid_fetch(uid_set, query).await?;
+        let mut fetched = Vec::new();
+        while let Some(fetch_result) = stream.next().await {
+            // A single unparsable response shouldn't cost the whole batch.
+            if let Ok(fetch) = fetch_result {
+                if let Some(header) = fetch.header() {
+                    fetched.push(FetchedHeader {
+                        uid: fetch.uid,
+                        header: header.to_vec(),
+                        internal_date: fetch.internal_date().map(|d| d.with_timezone(&Utc)),
+                        size: fetch.size,
+                    });
+                }
+            }
+        }
+        Ok(fetched)
+    }
+
+    async fn uid_fetch_dates(&mut self, uid_set: &str) -> ImapResult<Vec<(u32, DateTime<Utc>)>> {
+        let mut stream = self.session.uid_fetch(uid_set, "INTERNALDATE").await?;
+        let mut dates = Vec::new();
+        while let Some(fetch_result) = stream.next().await {
+            if let Ok(fetch) = fetch_result {
+                if let (Some(uid), Some(date)) = (fetch.uid, fetch.internal_date()) {
+                    dates.push((uid, date.with_timezone(&Utc)));
+                }
+            }
+        }
+        Ok(dates)
+    }
+
+    async fn uid_mv(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()> {
+        self.session.uid_mv(uid_set, mailbox).await
+    }
+
+    async fn uid_copy(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()> {
+        self.session.uid_copy(uid_set, mailbox).await
+    }
+
+    async fn uid_store(&mut self, uid_set: &str, query: &str) -> ImapResult<()> {
+        self.session
+            .uid_store(uid_set, query)
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        Ok(())
+    }
+
+    async fn uid_expunge(&mut self, uid_set: &str) -> ImapResult<()> {
+        self.session
+            .uid_expunge(uid_set)
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        Ok(())
+    }
+
+    async fn expunge(&mut self) -> ImapResult<()> {
+        self.session.expunge().await?.collect::<Vec<_>>().await;
+        Ok(())
+    }
+}
+
+/// In-memory folder for tests. Understands just enough of the commands the
+/// app sends to keep its message list consistent.
+#[cfg(test)]
+pub mod mock {
+    use super::{FetchedHeader, ImapOps, ImapResult};
+    use crate::imap::capabilities::ServerCapabilities;
+    use chrono::{DateTime, Utc};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    pub struct MockSession {
+        pub capabilities: ServerCapabilities,
+        /// Raw `From:` header block per UID.
+        pub messages: BTreeMap<u32, Vec<u8>>,
+        /// `INTERNALDATE` overrides. Other messages are dated one day per
+        /// UID, so higher UIDs are newer.
+        pub dates: BTreeMap<u32, DateTime<Utc>>,
+        pub deleted: BTreeSet<u32>,
+        /// Other flags per UID, such as `\Seen`. Set by STORE and by
+        /// fetches that mark messages read, as a real server would.
+        pub flags: BTreeMap<u32, BTreeSet<String>>,
+        /// Every command received, e.g. `UID MOVE 1,2 Trash`.
+        pub log: Vec<String>,
+        /// When set, COPY and MOVE fail with this `NO` reply.
+        pub copy_error: Option<String>,
+        /// COPY and MOVE commands to refuse with `[THROTTLED]` before
+        /// accepting any.
+        pub throttled_replies: usize,
+        /// COPY and MOVE commands that take effect but lose the connection
+        /// before the reply arrives.
+        pub dropped_replies: usize,
+        /// The same for STORE commands.
+        pub dropped_store_replies: usize,
+        /// UIDs copied or moved out, once for each command that took effect.
+        pub copied: Vec<u32>,
+    }
+
+    impl MockSession {
+        pub fn new(capabilities: &[&str]) -> Self {
+            Self {
+                capabilities: ServerCapabilities::from_atoms(capabilities),
+                messages: BTreeMap::new(),
+                dates: BTreeMap::new(),
+                deleted: BTreeSet::new(),
+                flags: BTreeMap::new(),
+                log: Vec::new(),
+                copy_error: None,
+                throttled_replies: 0,
+                dropped_replies: 0,
+                dropped_store_replies: 0,
+                copied: Vec::new(),
+            }
+        }
+
+        /// Adds `count` messages from `from`, numbered after the last UID.
+        pub fn with_messages(mut self, from: &str, count: usize) -> Self {
+            let first = self.messages.keys().next_back().map_or(1, |uid| uid + 1);
+            for uid in (first..).take(count) {
+                self.messages.insert(uid, format!("From: {from}\r\n\r\n").into_bytes());
+            }
+            self
+        }
+
+        /// Commands whose name starts with `prefix`.
+        pub fn commands(&self, prefix: &str) -> usize {
+            self.log.iter().filter(|line| line.starts_with(prefix)).count()
+        }
+
+        fn existing(&self, uid_set: &str) -> Vec<u32> {
+            uid_set
+                .split(',')
+                .filter_map(|part| match part.split_once(':') {
+                    Some((start, end)) => Some(start.parse().ok()?..=end.parse().ok()?),
+                    None => part.parse().ok().map(|uid| uid..=uid),
+                })
+                .flatten()
+                .filter(|uid| self.messages.contains_key(uid))
+                .collect()
+        }
+
+        /// `UID <set>` or nested `FROM "..."` keys, matched as substrings.
+        fn matches(&self, query: &str) -> Vec<u32> {
+            if let Some(uid_set) = query.strip_prefix("UID ") {
+                return self.existing(uid_set);
+            }
+            let needles: Vec<String> = query
+                .split('"')
+                .skip(1)
+                .step_by(2)
+                .map(str::to_lowercase)
+                .collect();
+            self.messages
+                .iter()
+                .filter(|(_, header)| {
+                    let header = String::from_utf8_lossy(header).to_lowercase();
+                    needles.is_empty() || needles.iter().any(|n| header.contains(n.as_str()))
+                })
+                .map(|(uid, _)| *uid)
+                .collect()
+        }
+
+        /// Sets `flag` on `uid`, e.g. to mark a message read beforehand.
+        pub fn set_flag(&mut self, uid: u32, flag: &str) {
+            self.flags.entry(uid).or_default().insert(flag.to_string());
+        }
+
+        fn remove(&mut self, uid: u32) {
+            self.messages.remove(&uid);
+            self.flags.remove(&uid);
+        }
+
+        fn copy(&mut self, command: &str, uid_set: &str, mailbox: &str) -> ImapResult<()> {
+            self.log.push(format!("{command} {uid_set} {mailbox}"));
+            if self.throttled_replies > 0 {
+                self.throttled_replies -= 1;
+                return Err(async_imap::error::Error::No("[THROTTLED] Slow down".to_string()));
+            }
+            if let Some(reply) = &self.copy_error {
+                return Err(async_imap::error::Error::No(reply.clone()));
+            }
+            self.copied.extend(self.existing(uid_set));
+            Ok(())
+        }
+
+        fn reply(&mut self) -> ImapResult<()> {
+            if self.dropped_replies > 0 {
+                self.dropped_replies -= 1;
+                return Err(async_imap::error::Error::ConnectionLost);
+            }
+            Ok(())
+        }
+    }
+
+    impl ImapOps for MockSession {
+        fn capabilities(&self) -> &ServerCapabilities {
+            &self.capabilities
+        }
+
+        async fn uid_search(&mut self, query: &str) -> ImapResult<Vec<u32>> {
+            self.log.push(format!("UID SEARCH {query}"));
+            Ok(self.matches(query))
+        }
+
+        async fn uid_fetch(&mut self, uid_set: &str, query: &str) -> ImapResult<Vec<FetchedHeader>> {
+            self.log.push(format!("UID FETCH {uid_set} {query}"));
+            let uids = self.existing(uid_set);
+            if sets_seen(query) {
+                for &uid in &uids {
+                    self.set_flag(uid, "\\Seen");
+                }
+            }
+            Ok(uids
+                .into_iter()
+                .map(|uid| FetchedHeader {
+                    uid: Some(uid),
+                    header: self.messages[&uid].clone(),
+                    internal_date: None,
+                    size: Some(self.messages[&uid].len() as u32),
+                })
+                .collect())
+        }
+
+        async fn uid_fetch_dates(
+            &mut self,
+            uid_set: &str,
+        ) -> ImapResult<Vec<(u32, DateTime<Utc>)>> {
+            self.log.push(format!("UID FETCH {uid_set} INTERNALDATE"));
+            Ok(self
+                .existing(uid_set)
+                .into_iter()
+                .map(|uid| {
+                    let date = self.dates.get(&uid).copied().unwrap_or_else(|| {
+                        DateTime::from_timestamp(i64::from(uid) * 86_400, 0).unwrap_or_default()
+                    });
+                    (uid, date)
+                })
+                .collect())
+        }
+
+        async fn uid_mv(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()> {
+            self.copy("UID MOVE", uid_set, mailbox)?;
+            for uid in self.existing(uid_set) {
+                self.remove(uid);
+            }
+            self.reply()
+        }
+
+        async fn uid_copy(&mut self, uid_set: &str, mailbox: &str) -> ImapResult<()> {
+            self.copy("UID COPY", uid_set, mailbox)?;
+            self.reply()
+        }
+
+        async fn uid_store(&mut self, uid_set: &str, query: &str) -> ImapResult<()> {
+            self.log.push(format!("UID STORE {uid_set} {query}"));
+            let Some((item, list)) = query.split_once(' ') else {
+                return Ok(());
+            };
+            let adding = match item.trim_end_matches(".SILENT") {
+                "+FLAGS" => true,
+                "-FLAGS" => false,
+                // Gmail labels aren't flags.
+                _ => return Ok(()),
+            };
+            for uid in self.existing(uid_set) {
+                for flag in list.trim_matches(['(', ')']).split_whitespace() {
+                    match (flag, adding) {
+                        ("\\Deleted", true) => {
+                            self.deleted.insert(uid);
+                        }
+                        ("\\Deleted", false) => {
+                            self.deleted.remove(&uid);
+                        }
+                        (_, true) => self.set_flag(uid, flag),
+                        (_, false) => {
+                            self.flags.entry(uid).or_default().remove(flag);
+                        }
+                    }
+                }
+            }
+            if self.dropped_store_replies > 0 {
+                self.dropped_store_replies -= 1;
+                return Err(async_imap::error::Error::ConnectionLost);
+            }
+            Ok(())
+        }
+
+        async fn uid_expunge(&mut self, uid_set: &str) -> ImapResult<()> {
+            self.log.push(format!("UID EXPUNGE {uid_set}"));
+            for uid in self.existing(uid_set) {
+                if self.deleted.remove(&uid) {
+                    self.remove(uid);
+                }
+            }
+            Ok(())
+        }
+
+        async fn expunge(&mut self) -> ImapResult<()> {
+            self.log.push("EXPUNGE".to_string());
+            for uid in std::mem::take(&mut self.deleted) {
+                self.remove(uid);
+            }
+            Ok(())
+        }
+    }
+
+    /// Whether a FETCH item list would implicitly set `\Seen` (RFC 3501
+    /// 6.4.5): non-PEEK `BODY[...]`/`BINARY[...]`, `RFC822` and `RFC822.TEXT`.
+    fn sets_seen(items: &str) -> bool {
+        let items = items.to_uppercase();
+        items.contains("BODY[")
+            || items.contains("BINARY[")
+            || items
+                .split([' ', '(', ')'])
+                .any(|item| item == "RFC822" || item == "RFC822.TEXT")
+    }
+}
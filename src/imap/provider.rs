@@ -1,44 +1,117 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    Gmail,
+    Outlook,
+    Yahoo,
+    ICloud,
+}
+
+impl ProviderKind {
+    /// What to tell the user when login is rejected. All supported providers
+    /// refuse the normal account password over IMAP.
+    pub fn auth_hint(self) -> &'static str {
+        match self {
+            ProviderKind::Gmail => {
+                "Gmail requires an app password, not your login password. \
+                 Enable 2-Step Verification, then go to myaccount.google.com > Security > \
+                 App passwords, generate one for Mail and paste it here."
+            }
+            ProviderKind::Outlook => {
+                "Outlook requires an app password. Create one at account.live.com > \
+                 Security > Advanced security options > App passwords."
+            }
+            ProviderKind::Yahoo => {
+                "Yahoo requires an app password. Create one at \
+                 login.yahoo.com/account/security > Generate app password."
+            }
+            ProviderKind::ICloud => {
+                "iCloud requires an app-specific password. Create one at \
+                 appleid.apple.com > Sign-In and Security > App-Specific Passwords."
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ImapProvider {
+    pub kind: ProviderKind,
     pub host: &'static str,
     pub port: u16,
     pub trash_folder: &'static str,
+    pub sent_folder: &'static str,
+    /// Where the provider files what its spam filter catches.
+    pub junk_folder: &'static str,
+    /// Simultaneous IMAP sessions allowed per account. Providers don't all
+    /// publish this, so the unknown ones are kept conservative.
+    pub max_connections: usize,
+    /// UIDs per delete command. Bigger chunks mean fewer round trips, but
+    /// some servers time out or refuse large sets, so those get less.
+    pub delete_chunk_size: usize,
 }
 
 impl ImapProvider {
     pub fn from_email(email: &str) -> Self {
-        let domain = email
-            .rsplit('@')
-            .next()
-            .unwrap_or("")
-            .to_lowercase();
+        let domain = domain_of(email);
 
         if domain.contains("outlook") || domain.contains("hotmail") || domain.contains("live.com")
         {
             Self {
+                kind: ProviderKind::Outlook,
                 host: "imap-mail.outlook.com",
                 port: 993,
                 trash_folder: "Deleted",
+                sent_folder: "Sent",
+                junk_folder: "Junk",
+                max_connections: 10,
+                delete_chunk_size: 500,
             }
         } else if domain.contains("yahoo") {
             Self {
+                kind: ProviderKind::Yahoo,
                 host: "imap.mail.yahoo.com",
                 port: 993,
                 trash_folder: "Trash",
+                sent_folder: "Sent",
+                junk_folder: "Bulk Mail",
+                max_connections: 5,
+                delete_chunk_size: 500,
             }
         } else if domain.contains("icloud") || domain.contains("me.com") || domain.contains("mac.com") {
             Self {
+                kind: ProviderKind::ICloud,
                 host: "imap.mail.me.com",
                 port: 993,
                 trash_folder: "Deleted Messages",
+                sent_folder: "Sent Messages",
+                junk_folder: "Junk",
+                max_connections: 10,
+                delete_chunk_size: 1000,
             }
         } else {
             // Default: Gmail
             Self {
+                kind: ProviderKind::Gmail,
                 host: "imap.gmail.com",
                 port: 993,
                 trash_folder: "[Gmail]/Trash",
+                sent_folder: "[Gmail]/Sent Mail",
+                junk_folder: "[Gmail]/Spam",
+                max_connections: 15,
+                delete_chunk_size: 2000,
             }
         }
     }
+
+    /// True when `email` matched no known provider and got the Gmail
+    /// settings only as a default.
+    pub fn is_fallback(email: &str) -> bool {
+        let domain = domain_of(email);
+        Self::from_email(email).kind == ProviderKind::Gmail
+            && !domain.contains("gmail")
+            && !domain.contains("googlemail")
+    }
+}
+
+fn domain_of(email: &str) -> String {
+    email.rsplit('@').next().unwrap_or("").to_lowercase()
 }
@@ -1,53 +1,451 @@
 use crate::error::AppError;
-use crate::state::SenderInfo;
-use futures::StreamExt;
+use crate::format::thousands;
+use crate::state::{ConnectionOptions, SenderInfo};
+use chrono::{DateTime, Utc};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::sync::LazyLock;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-use super::connect_imap;
+use super::ops::ImapOps;
+use super::provider::ImapProvider;
+use super::{connect_imap, uid_chunks, uid_list, ConnectionLimiter, Credentials, ImapConnection};
 
 static FROM_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"(?i)From:\s*(.*)").unwrap());
+    LazyLock::new(|| Regex::new(r"(?im)^From:\s*(.*)").unwrap());
+static SENDER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^Sender:\s*(.*)").unwrap());
+static LIST_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^List-Id:\s*(.*)").unwrap());
+static TO_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^To:\s*(.*)").unwrap());
+static CC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^Cc:\s*(.*)").unwrap());
 static EMAIL_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"<([^>]+)>").unwrap());
+    LazyLock::new(|| Regex::new(r"<([^<>]*)>").unwrap());
+/// Line break plus the whitespace that continues a folded header
+/// (RFC 5322 2.2.3).
+static FOLD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\r?\n[ \t]+").unwrap());
+/// Quoted display name, which may itself contain `<`, `>` or `@`.
+static QUOTED_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap());
+/// Mozilla's Public Suffix List, bundled so grouping works offline. Parsed
+/// on first use.
+static SUFFIX_LIST: LazyLock<Option<publicsuffix::List>> = LazyLock::new(|| {
+    include_str!("../../assets/public_suffix_list.dat")
+        .parse()
+        .inspect_err(|e| tracing::warn!(error = %e, "public suffix list failed to parse"))
+        .ok()
+});
+static ADDRESS_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[A-Za-z0-9._%+'-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)+").unwrap());
 
 /// Number of persistent IMAP connections used for parallel scanning.
 /// Balances throughput against server-side connection limits (most
 /// providers allow 10-15 simultaneous sessions).
 const MAX_CONCURRENT: usize = 10;
 
+/// Fetch items for the Sent-folder pass that finds who the user writes to.
+const RECIPIENT_FETCH_QUERY: &str = "BODY.PEEK[HEADER.FIELDS (TO CC)]";
+
+/// UIDs per fetch when walking the Sent folder on a single connection.
+const RECIPIENT_CHUNK_SIZE: usize = 1000;
+
+/// Most UIDs in one scan batch. Bounds the size of each FETCH response and
+/// how much is lost when a single batch fails.
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// UIDs queued ahead of the workers. Batches are cut from the UID list as
+/// workers free up rather than all up front, so on huge folders only about
+/// this many UIDs are copied out at once.
+const SCAN_WINDOW: usize = 20_000;
+
+/// Share of batches allowed to fail before the scan is rejected as
+/// incomplete. Below this the results are kept but reported as partial.
+const MAX_FAILED_BATCH_FRACTION: f32 = 0.25;
+
+/// Minimum gap between live result snapshots sent to the UI. Each snapshot
+/// clones the whole sender list, so this bounds the cost on big inboxes.
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Domains whose mailboxes ignore dots and `+tag` suffixes in the local part.
+const GMAIL_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// Default local-part pattern for rotating ESP addresses: a word and a
+/// separator, then a token with at least one digit (`bounce-123`,
+/// `msg.a8f3e9`). The first capture group is kept in the merged key.
+pub const DEFAULT_ROTATION_PATTERN: &str = r"^([a-z]+[-_.+])[0-9a-z]*[0-9][0-9a-z]*$";
+
+/// Attempts at the initial folder search before the scan gives up.
+const SEARCH_ATTEMPTS: u32 = 3;
+
+/// Wait before retrying the folder search, doubled after each failure.
+const SEARCH_RETRY_DELAY: Duration = Duration::from_secs(2);
+
 /// Initial progress percentage reserved for the UID-fetch phase before
 /// batch scanning begins.
 const INITIAL_PROGRESS: f32 = 0.05;
 
-fn parse_sender(raw: &[u8]) -> String {
-    let text = String::from_utf8_lossy(raw);
-    if let Some(m) = FROM_RE.captures(&text) {
-        let raw_from = m.get(1).map_or("", |m| m.as_str().trim());
-        if let Some(email_match) = EMAIL_RE.captures(raw_from) {
-            return email_match
-                .get(1).map_or_else(|| "unknown".to_string(), |m| m.as_str().to_lowercase());
+/// Batch errors kept for the diagnostics panel; older ones are dropped.
+const MAX_RECORDED_ERRORS: usize = 20;
+
+/// Raw headers kept from messages whose sender couldn't be parsed.
+const MAX_UNPARSED_SAMPLES: usize = 5;
+
+/// Raw headers kept per sender when a scan is asked to keep samples.
+pub const MAX_RAW_SAMPLES: usize = 3;
+
+/// Header fields that identify a message's sender, tried in the order
+/// From, Sender, List-Id. The first enabled one with a usable value wins,
+/// so turning From off keys mail relayed through one address by its list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderHeaders {
+    pub from: bool,
+    pub sender: bool,
+    pub list_id: bool,
+}
+
+impl Default for SenderHeaders {
+    fn default() -> Self {
+        Self {
+            from: true,
+            sender: false,
+            list_id: false,
         }
-        if !raw_from.is_empty() {
-            return raw_from.to_lowercase();
+    }
+}
+
+impl SenderHeaders {
+    /// Keys are plain `From:` addresses, so a `FROM` search finds the same
+    /// messages again.
+    pub fn is_from_only(self) -> bool {
+        self == Self::default()
+    }
+
+    pub fn is_empty(self) -> bool {
+        !(self.from || self.sender || self.list_id)
+    }
+
+    fn fields(self) -> impl Iterator<Item = (&'static str, &'static Regex)> {
+        [
+            (self.from, "FROM", &*FROM_RE),
+            (self.sender, "SENDER", &*SENDER_RE),
+            (self.list_id, "LIST-ID", &*LIST_ID_RE),
+        ]
+        .into_iter()
+        .filter(|(enabled, _, _)| *enabled)
+        .map(|(_, name, pattern)| (name, pattern))
+    }
+}
+
+/// What a scan reads from each message beyond its sender, and what it
+/// keeps once the counts are tallied.
+#[derive(Debug, Clone, Default)]
+pub struct ScanSettings {
+    pub headers: SenderHeaders,
+    /// Keep each sender's UIDs.
+    pub retain_uids: bool,
+    /// Keep up to [`MAX_RAW_SAMPLES`] raw header blocks per sender.
+    pub raw_samples: bool,
+    /// The account's own address, lowercased and Gmail-normalised. When
+    /// set, `To:` and `Cc:` are fetched too and each message is counted by
+    /// which of them it appears in.
+    pub own_address: Option<String>,
+}
+
+impl ScanSettings {
+    /// Fetch items requested per message. `BODY.PEEK` keeps the `\Seen`
+    /// flag untouched, `INTERNALDATE` feeds the per-sender "last seen"
+    /// date and `RFC822.SIZE` the space each sender takes up.
+    fn fetch_query(&self) -> String {
+        let mut names: Vec<&str> = self.headers.fields().map(|(name, _)| name).collect();
+        if self.own_address.is_some() {
+            names.extend(["TO", "CC"]);
         }
+        format!("(INTERNALDATE RFC822.SIZE BODY.PEEK[HEADER.FIELDS ({})])", names.join(" "))
     }
-    "unknown".to_string()
 }
 
-pub async fn fetch_all_uids(
-    email: &str,
-    password: &str,
+/// Where the account's own address appeared on a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recipient {
+    To,
+    Cc,
+    /// In neither field, so it arrived through a list alias or Bcc.
+    Neither,
+}
+
+/// Messages from one address, split by where the account was addressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecipientCounts {
+    pub to: usize,
+    pub cc: usize,
+    pub neither: usize,
+}
+
+impl RecipientCounts {
+    pub fn total(&self) -> usize {
+        self.to + self.cc + self.neither
+    }
+
+    fn record(&mut self, recipient: Recipient) {
+        match recipient {
+            Recipient::To => self.to += 1,
+            Recipient::Cc => self.cc += 1,
+            Recipient::Neither => self.neither += 1,
+        }
+    }
+}
+
+impl std::ops::AddAssign for RecipientCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.to += other.to;
+        self.cc += other.cc;
+        self.neither += other.neither;
+    }
+}
+
+/// Where `own`, already normalised like [`ScanSettings::own_address`],
+/// appears in a header block. `To:` wins when it is in both.
+fn classify_recipient(raw: &[u8], own: &str) -> Recipient {
+    let text = String::from_utf8_lossy(raw);
+    let text = FOLD_RE.replace_all(&text, " ");
+    let addressed = |pattern: &Regex| {
+        pattern.captures_iter(&text).any(|value| {
+            ADDRESS_RE
+                .find_iter(&value[1])
+                .any(|m| normalize_gmail_address(&m.as_str().to_lowercase()) == own)
+        })
+    };
+    if addressed(&TO_RE) {
+        Recipient::To
+    } else if addressed(&CC_RE) {
+        Recipient::Cc
+    } else {
+        Recipient::Neither
+    }
+}
+
+/// Extracts the sender as written in the first of `headers` present: an
+/// address, or a list's identifier for `List-Id:`. Callers lowercase it
+/// for aggregation but keep this form for display.
+fn parse_sender(raw: &[u8], headers: SenderHeaders) -> String {
+    let text = String::from_utf8_lossy(raw);
+    let text = FOLD_RE.replace_all(&text, " ");
+    headers
+        .fields()
+        .find_map(|(_, pattern)| {
+            let value = pattern.captures(&text)?.get(1)?;
+            parse_address(value.as_str().trim())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The address in one header value, or `None` if there isn't one.
+fn parse_address(raw_from: &str) -> Option<String> {
+
+    // Quoted names are dropped first so a `<` inside one isn't mistaken
+    // for the start of the address.
+    let unquoted = QUOTED_RE.replace_all(raw_from, "");
+    let sender = match EMAIL_RE.captures_iter(&unquoted).last() {
+        Some(email_match) => email_match.get(1).map_or("", |m| m.as_str().trim()),
+        // No brackets: a bare address, possibly followed by a comment, or
+        // just a name.
+        None => ADDRESS_RE
+            .find(&unquoted)
+            .map_or_else(|| raw_from.trim_matches('"').trim(), |m| m.as_str()),
+    };
+    (!sender.is_empty()).then(|| sender.to_string())
+}
+
+/// Extracts every address from a `To:`/`Cc:` header block, lowercased.
+fn parse_recipients(raw: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(raw);
+    ADDRESS_RE
+        .find_iter(&text)
+        .map(|m| m.as_str().to_lowercase())
+        .collect()
+}
+
+/// Counts how many messages in `folder` were addressed (To or Cc) to each
+/// recipient. Used against the Sent folder to spot real correspondents.
+/// Gives up after `timeout`, or with [`AppError::Cancelled`] when `cancel`
+/// fires, so a slow Sent folder can't hold up the scan's results.
+pub async fn count_recipients(
+    credentials: &Credentials,
     folder: &str,
-) -> Result<Vec<u32>, AppError> {
-    let mut session = connect_imap(email, password, folder).await?;
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+    timeout: Duration,
+    cancel: &CancellationToken,
+) -> Result<HashMap<String, usize>, AppError> {
+    let count = async {
+        let mut session = connect_imap(credentials, folder, options, limiter).await?;
+        let counts = tally_recipients(&mut session).await?;
+        if let Err(e) = session.logout().await {
+            tracing::warn!(error = %e, "logout failed after recipient scan");
+        }
+        Ok(counts)
+    };
+    bounded(timeout, cancel, Box::pin(count)).await
+}
 
-    let uids = session
+/// Runs `work` until it finishes, `timeout` passes or `cancel` fires.
+async fn bounded<T>(
+    timeout: Duration,
+    cancel: &CancellationToken,
+    work: impl Future<Output = Result<T, AppError>>,
+) -> Result<T, AppError> {
+    tokio::select! {
+        result = async_std::future::timeout(timeout, work) => result.unwrap_or_else(|_| {
+            Err(AppError::Imap(format!("timed out after {}s", timeout.as_secs())))
+        }),
+        () = cancel.cancelled() => Err(AppError::Cancelled),
+    }
+}
+
+async fn tally_recipients<S: ImapOps>(session: &mut S) -> Result<HashMap<String, usize>, AppError> {
+    let mut uids = session
         .uid_search("ALL")
         .await
         .map_err(|e| AppError::Imap(e.to_string()))?;
+    uids.sort_unstable();
+
+    let mut counts = HashMap::new();
+    for chunk in uid_chunks(&uids, RECIPIENT_CHUNK_SIZE) {
+        let fetched = session
+            .uid_fetch(&uid_list(chunk), RECIPIENT_FETCH_QUERY)
+            .await
+            .map_err(|e| AppError::Imap(e.to_string()))?;
+        for message in fetched {
+            for address in parse_recipients(&message.header) {
+                *counts.entry(address).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Cheap sanity check for a user-supplied `UID SEARCH` query. It can't prove
+/// the server will accept it, but catches input that would break the command
+/// line or obviously can't parse.
+pub fn validate_search_query(query: &str) -> Result<(), String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err("Search query is empty (use ALL to scan everything)".to_string());
+    }
+    if query.contains(['\r', '\n']) {
+        return Err("Search query can't contain line breaks".to_string());
+    }
+    let mut depth = 0i32;
+    let mut in_quotes = false;
+    for c in query.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err("Unbalanced parentheses in search query".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    if in_quotes {
+        return Err("Unbalanced quotes in search query".to_string());
+    }
+    if depth != 0 {
+        return Err("Unbalanced parentheses in search query".to_string());
+    }
+    Ok(())
+}
+
+/// Guards around the initial `UID SEARCH`.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    /// Give up on a server that doesn't answer the search in this time.
+    pub timeout: Duration,
+    /// Refuse with [`AppError::LargeFolder`] instead of searching a folder
+    /// holding more messages than this.
+    pub max_messages: Option<u32>,
+}
+
+/// Result of [`fetch_all_uids`].
+#[derive(Debug)]
+pub struct FolderUids {
+    /// Matching UIDs, ascending.
+    pub uids: Vec<u32>,
+    /// The folder was opened `[READ-ONLY]`.
+    pub read_only: bool,
+    /// The folder's `UIDVALIDITY`, which `uids` are only good under.
+    pub uid_validity: Option<u32>,
+}
+
+/// Searches `folder` for the UIDs to scan. A dropped or refused connection
+/// is retried with a growing delay, reporting each retry to `on_retry`;
+/// anything else, including a rejected login, fails straight away.
+pub async fn fetch_all_uids(
+    credentials: &Credentials,
+    folder: &str,
+    query: &str,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+    limits: SearchLimits,
+    on_retry: impl Fn(u32, &AppError),
+) -> Result<FolderUids, AppError> {
+    validate_search_query(query).map_err(AppError::Imap)?;
+    let mut delay = SEARCH_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match search_folder(credentials, folder, query, options, limiter, limits).await {
+            Err(e) if e.is_transient() && attempt < SEARCH_ATTEMPTS => {
+                tracing::warn!(attempt, error = %e, "folder search failed, retrying");
+                on_retry(attempt, &e);
+                async_std::task::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// One connect-and-search pass for [`fetch_all_uids`].
+async fn search_folder(
+    credentials: &Credentials,
+    folder: &str,
+    query: &str,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
+    limits: SearchLimits,
+) -> Result<FolderUids, AppError> {
+    let mut session = connect_imap(credentials, folder, options, limiter).await?;
+
+    let messages = session.message_count();
+    if limits.max_messages.is_some_and(|max| messages > max) {
+        if let Err(e) = session.logout().await {
+            tracing::warn!(error = %e, "logout failed after size check");
+        }
+        return Err(AppError::LargeFolder { messages });
+    }
+
+    let uids = async_std::future::timeout(limits.timeout, session.uid_search(query.trim()))
+        .await
+        .map_err(|_| {
+            AppError::Imap(format!(
+                "UID SEARCH timed out after {}s",
+                limits.timeout.as_secs()
+            ))
+        })?
+        .map_err(AppError::from)?;
 
     if let Err(e) = session.logout().await {
         tracing::warn!(error = %e, "logout failed after UID fetch");
@@ -55,22 +453,59 @@ pub async fn fetch_all_uids(
 
     let mut uid_vec: Vec<u32> = uids.into_iter().collect();
     uid_vec.sort_unstable();
-    Ok(uid_vec)
+    Ok(FolderUids {
+        uids: uid_vec,
+        read_only: session.is_read_only(),
+        uid_validity: session.uid_validity(),
+    })
+}
+
+/// What a batch fetch extracts from a single message.
+struct ScannedMessage {
+    uid: Option<u32>,
+    /// Lowercased address, the aggregation key.
+    sender: String,
+    /// Address with its original casing.
+    display: String,
+    date: Option<DateTime<Utc>>,
+    size: Option<u32>,
+    /// The header block as received, when the scan keeps samples.
+    raw: Option<String>,
+    /// Set when the scan breaks messages down by recipient field.
+    recipient: Option<Recipient>,
+}
+
+/// Output of one batch fetch.
+#[derive(Default)]
+struct ScannedBatch {
+    messages: Vec<ScannedMessage>,
+    /// Header blocks of messages whose sender couldn't be parsed.
+    unparsed: Vec<String>,
 }
 
 struct ScanWorker {
-    email: String,
-    password: String,
+    credentials: Arc<Credentials>,
     folder: String,
-    session: Option<async_imap::Session<async_native_tls::TlsStream<async_std::net::TcpStream>>>,
+    settings: ScanSettings,
+    options: ConnectionOptions,
+    limiter: ConnectionLimiter,
+    session: Option<ImapConnection>,
 }
 
 impl ScanWorker {
-    fn new(email: String, password: String, folder: String) -> Self {
+    fn new(
+        credentials: Arc<Credentials>,
+        folder: String,
+        settings: ScanSettings,
+        options: ConnectionOptions,
+        limiter: ConnectionLimiter,
+    ) -> Self {
         Self {
-            email,
-            password,
+            credentials,
             folder,
+            settings,
+            options,
+            limiter,
             session: None,
         }
     }
@@ -79,140 +514,738 @@ impl ScanWorker {
         if self.session.is_some() {
             return Ok(());
         }
-        let session = connect_imap(&self.email, &self.password, &self.folder).await?;
+        let session = connect_imap(
+            &self.credentials,
+            &self.folder,
+            &self.options,
+            &self.limiter,
+        )
+        .await?;
         self.session = Some(session);
         Ok(())
     }
 
-    async fn scan_batch(&mut self, uids: &[u32]) -> Result<Vec<String>, AppError> {
+    async fn scan_batch(&mut self, uids: &[u32]) -> Result<ScannedBatch, AppError> {
         if uids.is_empty() {
-            return Ok(Vec::new());
+            return Ok(ScannedBatch::default());
         }
 
         self.ensure_connected().await?;
         let mut session = self.session.take().unwrap();
 
-        let uid_str = uids
-            .iter()
-            .map(std::string::ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
+        // On failure the session is dropped and the next batch reconnects.
+        let batch = fetch_senders(&mut session, uids, &self.settings).await?;
 
-        let fetches_result = session
-            .uid_fetch(&uid_str, "BODY.PEEK[HEADER.FIELDS (FROM)]")
-            .await;
+        // Success — return the session to the worker for reuse
+        self.session = Some(session);
+        Ok(batch)
+    }
+}
 
-        if let Err(e) = fetches_result {
+/// Fetches the sender headers of each message in `uids`. Messages whose
+/// sender can't be parsed are set aside with their raw header; the rest
+/// carry it only when `settings` asks for samples.
+async fn fetch_senders<S: ImapOps>(
+    session: &mut S,
+    uids: &[u32],
+    settings: &ScanSettings,
+) -> Result<ScannedBatch, AppError> {
+    let query = settings.fetch_query();
+    let mut fetched = Vec::with_capacity(uids.len());
+    // A batch of sparse UIDs can be too long for one command line.
+    for chunk in uid_chunks(uids, uids.len()) {
+        fetched.extend(session.uid_fetch(&uid_list(chunk), &query).await.map_err(|e| {
             tracing::warn!(error = %e, "IMAP fetch failed, dropping session");
-            return Err(AppError::Imap(e.to_string()));
+            AppError::Imap(e.to_string())
+        })?);
+    }
+
+    let mut batch = ScannedBatch::default();
+    for message in fetched {
+        let display = parse_sender(&message.header, settings.headers);
+        if display == "unknown" {
+            let header = String::from_utf8_lossy(&message.header);
+            batch.unparsed.push(header.trim_end().to_string());
+            continue;
         }
+        batch.messages.push(ScannedMessage {
+            uid: message.uid,
+            sender: display.to_lowercase(),
+            display,
+            date: message.internal_date,
+            size: message.size,
+            raw: settings
+                .raw_samples
+                .then(|| String::from_utf8_lossy(&message.header).trim_end().to_string()),
+            recipient: settings
+                .own_address
+                .as_deref()
+                .map(|own| classify_recipient(&message.header, own)),
+        });
+    }
+    Ok(batch)
+}
 
-        let mut stream = fetches_result.unwrap();
-        let mut senders = Vec::new();
+/// Incremental feedback emitted by [`run_scan`] while batches complete.
+#[derive(Debug)]
+pub enum ScanUpdate {
+    Progress { progress: f32, status: String },
+    /// Snapshot of the senders aggregated so far, sorted by count.
+    Partial(Vec<SenderInfo>),
+    Diagnostics(ScanDiagnostics),
+}
 
-        while let Some(fetch_result) = stream.next().await {
-            if let Ok(fetch) = fetch_result {
-                if let Some(body) = fetch.header() {
-                    let sender = parse_sender(body);
-                    if sender != "unknown" {
-                        senders.push(sender);
-                    }
-                }
-            }
-        }
+/// Worker and batch counters for troubleshooting slow or throttled scans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanDiagnostics {
+    pub workers: usize,
+    /// Workers that haven't finished yet, including any still waiting for
+    /// a connection slot.
+    pub active_workers: usize,
+    pub batches_total: usize,
+    pub batches_completed: usize,
+    pub batches_failed: usize,
+    /// Most recent batch errors, oldest first.
+    pub errors: Vec<String>,
+    /// Messages left out because none of their sender headers could be
+    /// parsed.
+    pub unparsed: usize,
+    /// The first few of those headers, as received.
+    pub unparsed_samples: Vec<String>,
+}
 
-        // Explicitly drop the stream to release the borrow on session
-        drop(stream);
+impl ScanDiagnostics {
+    pub fn batches_queued(&self) -> usize {
+        self.batches_total - self.batches_completed
+    }
 
-        // Success — return the session to the worker for reuse
-        self.session = Some(session);
-        Ok(senders)
+    fn record_batch(&mut self, completed: usize, failed: usize, active: &AtomicUsize) {
+        self.batches_completed = completed;
+        self.batches_failed = failed;
+        self.active_workers = active.load(Ordering::Relaxed);
+        if self.errors.len() > MAX_RECORDED_ERRORS {
+            self.errors.remove(0);
+        }
+    }
+
+    fn record_unparsed(&mut self, headers: Vec<String>) {
+        self.unparsed += headers.len();
+        let room = MAX_UNPARSED_SAMPLES.saturating_sub(self.unparsed_samples.len());
+        self.unparsed_samples.extend(headers.into_iter().take(room));
     }
 }
 
+/// Aggregated output of [`run_scan`].
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    pub senders: Vec<SenderInfo>,
+    /// Batches whose fetch failed; their messages are missing from `senders`.
+    pub failed_batches: usize,
+    /// UIDs of the scanned messages, keyed by lowercased raw address.
+    /// `None` unless requested, since it holds one entry per message.
+    pub uids: Option<HashMap<String, Vec<u32>>>,
+    /// Up to [`MAX_RAW_SAMPLES`] raw header blocks per lowercased raw
+    /// address. `None` unless requested.
+    pub raw_headers: Option<HashMap<String, Vec<String>>>,
+    /// Where the account was addressed, per lowercased raw address. `None`
+    /// unless requested.
+    pub recipients: Option<HashMap<String, RecipientCounts>>,
+}
+
+/// Scans `uids` with a pool of workers. When `cancel` fires, or a login is
+/// refused, queued batches are dropped and this returns once every worker
+/// has finished its current batch and logged out.
+#[allow(clippy::too_many_arguments)] // connection settings plus the scan's own inputs
 pub async fn run_scan<F>(
-    email: &str,
-    password: &str,
+    credentials: &Arc<Credentials>,
     folder: &str,
+    options: &ConnectionOptions,
+    limiter: &ConnectionLimiter,
     uids: Vec<u32>,
-    progress_cb: F,
-) -> Result<Vec<SenderInfo>, AppError>
+    settings: &ScanSettings,
+    cancel: &CancellationToken,
+    on_update: F,
+) -> Result<ScanResult, AppError>
 where
-    F: Fn(f32, String) + Send + Sync + 'static,
+    F: Fn(ScanUpdate) + Send + Sync + 'static,
 {
     let total = uids.len();
     if total == 0 {
-        return Ok(Vec::new());
-    }
-
-    let chunk_size = (total / MAX_CONCURRENT).max(1);
-    let chunks: Vec<Vec<u32>> = uids.chunks(chunk_size).map(<[u32]>::to_vec).collect();
-    let num_chunks = chunks.len();
-
-    let (job_tx, job_rx) = async_channel::bounded(num_chunks);
-    let (result_tx, mut result_rx) = mpsc::channel(num_chunks + 10);
-
-    for chunk in chunks {
-        if let Err(e) = job_tx.send(chunk).await {
-            tracing::error!(error = %e, "failed to enqueue scan job");
-        }
-    }
-    job_tx.close();
-
-    let mut handles = Vec::new();
-    for worker_id in 0..MAX_CONCURRENT {
-        let job_rx = job_rx.clone();
-        let result_tx = result_tx.clone();
-        let email = email.to_string();
-        let password = password.to_string();
-        let folder = folder.to_string();
-
-        handles.push(tokio::spawn(async move {
-            let mut worker = ScanWorker::new(email, password, folder);
-            while let Ok(chunk) = job_rx.recv().await {
-                match worker.scan_batch(&chunk).await {
-                    Ok(senders) => {
-                        if let Err(e) = result_tx.send(senders).await {
-                            tracing::error!(worker = worker_id, error = %e, "failed to send scan result");
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!(worker = worker_id, error = %e, "batch scan failed");
-                        // Send empty result to keep progress moving
-                        if let Err(e) = result_tx.send(Vec::new()).await {
-                            tracing::error!(worker = worker_id, error = %e, "failed to send error fallback");
-                        }
-                    }
-                }
-            }
-            if let Some(mut session) = worker.session {
-                if let Err(e) = session.logout().await {
-                    tracing::warn!(worker = worker_id, error = %e, "logout failed after scan");
-                }
-            }
-        }));
+        return Ok(ScanResult::default());
+    }
+
+    // No point spawning workers that would only queue for a connection slot.
+    let workers = MAX_CONCURRENT.min(ImapProvider::from_email(&credentials.email).max_connections);
+    let chunk_size = (total / workers).clamp(1, MAX_BATCH_SIZE);
+    let num_chunks = total.div_ceil(chunk_size);
+    let window = (SCAN_WINDOW / chunk_size).max(workers);
+
+    let (job_tx, job_rx) = async_channel::bounded(window);
+    let (result_tx, mut result_rx) = mpsc::channel(window);
+
+    spawn_feeder(uids, chunk_size, job_tx);
+
+    let active_workers = Arc::new(AtomicUsize::new(workers));
+    for worker_id in 0..workers {
+        let worker = ScanWorker::new(
+            Arc::clone(credentials),
+            folder.to_string(),
+            settings.clone(),
+            options.clone(),
+            limiter.clone(),
+        );
+        spawn_worker(
+            worker_id,
+            worker,
+            job_rx.clone(),
+            result_tx.clone(),
+            Arc::clone(&active_workers),
+        );
     }
 
     drop(result_tx);
 
-    let mut sender_map = HashMap::new();
+    let mut diagnostics = ScanDiagnostics {
+        workers,
+        active_workers: workers,
+        batches_total: num_chunks,
+        ..ScanDiagnostics::default()
+    };
+    on_update(ScanUpdate::Diagnostics(diagnostics.clone()));
+
+    let mut tally = Tally::new(settings);
     let mut completed_batches = 0;
+    let mut scanned_emails = 0;
+    let mut failed_batches = 0;
+    let mut last_partial = Instant::now();
+    let mut last_error = None;
 
-    while let Some(senders) = result_rx.recv().await {
-        for s in senders {
-            *sender_map.entry(s).or_insert(0) += 1;
+    loop {
+        let received = tokio::select! {
+            received = result_rx.recv() => received,
+            () = cancel.cancelled() => {
+                wind_down(&job_rx, &mut result_rx).await;
+                return Err(AppError::Cancelled);
+            }
+        };
+        let Some((batch_len, result)) = received else {
+            break;
+        };
+        if let Err(e @ AppError::Auth { .. }) = result {
+            // Every worker logs in with the same credentials, so the rest
+            // would only fail the same way.
+            wind_down(&job_rx, &mut result_rx).await;
+            return Err(e);
         }
+        let batch = result.unwrap_or_else(|e| {
+            failed_batches += 1;
+            diagnostics.errors.push(e.to_string());
+            last_error = Some(e);
+            ScannedBatch::default()
+        });
+        diagnostics.record_unparsed(batch.unparsed);
+        tally.add(batch.messages);
 
         completed_batches += 1;
-        let progress = INITIAL_PROGRESS + (1.0 - INITIAL_PROGRESS) * (completed_batches as f32 / num_chunks as f32);
-        progress_cb(progress, format!("Scanned batch {completed_batches}/{num_chunks}"));
+        scanned_emails += batch_len;
+        diagnostics.record_batch(completed_batches, failed_batches, &active_workers);
+        on_update(ScanUpdate::Diagnostics(diagnostics.clone()));
+        if completed_batches < num_chunks && last_partial.elapsed() >= PARTIAL_INTERVAL {
+            on_update(ScanUpdate::Partial(sorted_senders(&tally.senders)));
+            last_partial = Instant::now();
+        }
+
+        on_update(scan_progress(scanned_emails, total));
+        if completed_batches == num_chunks {
+            // Workers log out before closing their result channels, which
+            // can take a moment on slow links.
+            on_update(ScanUpdate::Progress {
+                progress: 1.0,
+                status: "Finalizing connections...".to_string(),
+            });
+        }
     }
 
-    let mut senders: Vec<SenderInfo> = sender_map
-        .into_iter()
-        .map(|(email, count)| SenderInfo { email, count })
-        .collect();
+    if failed_batches as f32 / num_chunks as f32 > MAX_FAILED_BATCH_FRACTION {
+        return Err(AppError::IncompleteScan {
+            failed: failed_batches,
+            total: num_chunks,
+            last_error: last_error.map_or_else(String::new, |e| e.to_string()),
+        });
+    }
+
+    Ok(ScanResult {
+        senders: sorted_senders(&tally.senders),
+        failed_batches,
+        uids: tally.uids,
+        raw_headers: tally.raw_headers,
+        recipients: tally.recipients,
+    })
+}
+
+fn scan_progress(scanned: usize, total: usize) -> ScanUpdate {
+    ScanUpdate::Progress {
+        progress: INITIAL_PROGRESS + (1.0 - INITIAL_PROGRESS) * (scanned as f32 / total as f32),
+        status: format!("Scanned {} / {} emails", thousands(scanned), thousands(total)),
+    }
+}
+
+/// Drops the batches still queued and waits for the workers to finish the
+/// ones in hand and log out.
+async fn wind_down(
+    job_rx: &async_channel::Receiver<Vec<u32>>,
+    result_rx: &mut mpsc::Receiver<(usize, Result<ScannedBatch, AppError>)>,
+) {
+    job_rx.close();
+    while job_rx.try_recv().is_ok() {}
+    while result_rx.recv().await.is_some() {}
+}
+
+/// Cuts `uids` into batches as the queue makes room for them.
+fn spawn_feeder(uids: Vec<u32>, chunk_size: usize, job_tx: async_channel::Sender<Vec<u32>>) {
+    tokio::spawn(async move {
+        for chunk in uids.chunks(chunk_size) {
+            if job_tx.send(chunk.to_vec()).await.is_err() {
+                tracing::debug!("scan aborted, no more batches queued");
+                break;
+            }
+        }
+        job_tx.close();
+    });
+}
+
+/// Runs `worker` until the job queue is drained, counting itself out of
+/// `active` when done. A rejected login ends the worker early: trying the
+/// same credentials on the next batch can't succeed.
+fn spawn_worker(
+    worker_id: usize,
+    mut worker: ScanWorker,
+    job_rx: async_channel::Receiver<Vec<u32>>,
+    result_tx: mpsc::Sender<(usize, Result<ScannedBatch, AppError>)>,
+    active: Arc<AtomicUsize>,
+) {
+    tokio::spawn(async move {
+        while let Ok(chunk) = job_rx.recv().await {
+            let result = worker.scan_batch(&chunk).await;
+            let auth_failed = matches!(result, Err(AppError::Auth { .. }));
+            if let Err(e) = &result {
+                tracing::error!(worker = worker_id, error = %e, "batch scan failed");
+            }
+            // Failures are forwarded too so progress keeps moving and
+            // the collector can tell a partial scan from a complete one.
+            if result_tx.send((chunk.len(), result)).await.is_err() {
+                tracing::debug!(worker = worker_id, "scan aborted, worker stopping");
+                break;
+            }
+            if auth_failed {
+                break;
+            }
+        }
+        active.fetch_sub(1, Ordering::Relaxed);
+        if let Some(mut session) = worker.session {
+            if let Err(e) = session.logout().await {
+                tracing::warn!(worker = worker_id, error = %e, "logout failed after scan");
+            }
+        }
+    });
+}
+
+/// Per-sender totals built up as batches arrive, plus whatever else the
+/// scan was asked to keep, all keyed by lowercased raw address.
+struct Tally {
+    senders: HashMap<String, SenderInfo>,
+    uids: Option<HashMap<String, Vec<u32>>>,
+    raw_headers: Option<HashMap<String, Vec<String>>>,
+    recipients: Option<HashMap<String, RecipientCounts>>,
+}
+
+impl Tally {
+    fn new(settings: &ScanSettings) -> Self {
+        Self {
+            senders: HashMap::new(),
+            uids: settings.retain_uids.then(HashMap::new),
+            raw_headers: settings.raw_samples.then(HashMap::new),
+            recipients: settings.own_address.is_some().then(HashMap::new),
+        }
+    }
+
+    /// Folds one batch of scanned messages into the totals.
+    fn add(&mut self, messages: Vec<ScannedMessage>) {
+        for msg in messages {
+            self.add_message(msg);
+        }
+    }
+
+    fn add_message(&mut self, msg: ScannedMessage) {
+        if let (Some(uids), Some(uid)) = (&mut self.uids, msg.uid) {
+            uids.entry(msg.sender.clone()).or_default().push(uid);
+        }
+        if let (Some(samples), Some(raw)) = (&mut self.raw_headers, msg.raw) {
+            let kept = samples.entry(msg.sender.clone()).or_default();
+            if kept.len() < MAX_RAW_SAMPLES {
+                kept.push(raw);
+            }
+        }
+        if let (Some(recipients), Some(recipient)) = (&mut self.recipients, msg.recipient) {
+            recipients.entry(msg.sender.clone()).or_default().record(recipient);
+        }
+        let info = self
+            .senders
+            .entry(msg.sender)
+            .or_insert_with_key(|email| SenderInfo {
+                email: email.clone(),
+                display: msg.display,
+                addresses: vec![email.clone()],
+                count: 0,
+                last_seen: None,
+                sent_to: 0,
+                bytes: 0,
+            });
+        info.count += 1;
+        info.last_seen = info.last_seen.max(msg.date);
+        info.bytes += u64::from(msg.size.unwrap_or(0));
+    }
+}
+
+/// Canonical mailbox for a Gmail address: dots and any `+tag` dropped from
+/// the local part, `googlemail.com` folded into `gmail.com`. Other addresses
+/// are returned unchanged, since most providers treat dots as significant.
+pub fn normalize_gmail_address(address: &str) -> String {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return address.to_string();
+    };
+    if !GMAIL_DOMAINS.contains(&domain) {
+        return address.to_string();
+    }
+    let local = local.split('+').next().unwrap_or(local).replace('.', "");
+    format!("{local}@gmail.com")
+}
+
+/// Folds Gmail aliases of the same mailbox into one entry keyed by the
+/// normalised address, keeping every original address for deletion.
+pub fn merge_gmail_aliases(senders: Vec<SenderInfo>) -> Vec<SenderInfo> {
+    merge_by(senders, normalize_gmail_address)
+}
+
+/// Key shared by rotating addresses on one domain: the local part is
+/// replaced by the pattern's first capture (if any) and `*`, so
+/// `bounce-123@mail.acme.com` becomes `bounce-*@mail.acme.com`. Addresses
+/// the pattern doesn't match are returned unchanged.
+pub fn rotation_key(address: &str, pattern: &Regex) -> String {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return address.to_string();
+    };
+    match pattern.captures(local) {
+        Some(caps) => {
+            let stem = caps.get(1).map_or("", |m| m.as_str());
+            format!("{stem}*@{domain}")
+        }
+        None => address.to_string(),
+    }
+}
+
+/// Key shared by every address under one registrable domain (eTLD+1), so
+/// `news@mail.shop.co.uk` and `offers@shop.co.uk` both become
+/// `*@shop.co.uk`. Addresses whose domain isn't under a known suffix are
+/// returned unchanged.
+pub fn registrable_domain_key(address: &str) -> String {
+    use publicsuffix::Psl;
 
-    senders.sort_by(|a, b| b.count.cmp(&a.count));
-    Ok(senders)
+    let Some((_, domain)) = address.rsplit_once('@') else {
+        return address.to_string();
+    };
+    let registrable = SUFFIX_LIST
+        .as_ref()
+        .and_then(|list| list.domain(domain.as_bytes()))
+        .filter(|d| d.suffix().is_known())
+        .and_then(|d| std::str::from_utf8(d.as_bytes()).ok().map(str::to_string));
+    match registrable {
+        Some(registrable) => format!("*@{registrable}"),
+        None => address.to_string(),
+    }
+}
+
+/// Optional steps that fold several raw addresses into one sender, applied
+/// to partial and final scan results alike.
+#[derive(Debug, Clone, Default)]
+pub struct SenderGrouping {
+    pub gmail_aliases: bool,
+    /// Local-part pattern for rotating ESP addresses; see [`rotation_key`].
+    pub rotation: Option<Regex>,
+    /// One sender per registrable domain; see [`registrable_domain_key`].
+    pub registrable_domains: bool,
+}
+
+impl SenderGrouping {
+    pub fn apply(&self, mut senders: Vec<SenderInfo>) -> Vec<SenderInfo> {
+        if self.gmail_aliases {
+            senders = merge_gmail_aliases(senders);
+        }
+        if let Some(pattern) = &self.rotation {
+            senders = merge_by(senders, |address| rotation_key(address, pattern));
+        }
+        if self.registrable_domains {
+            senders = merge_by(senders, registrable_domain_key);
+        }
+        senders
+    }
+}
+
+/// Merges senders whose keys map to the same `key(email)`, summing counts
+/// and keeping every raw address so deletion can still match them all.
+fn merge_by(senders: Vec<SenderInfo>, key: impl Fn(&str) -> String) -> Vec<SenderInfo> {
+    let mut merged: HashMap<String, SenderInfo> = HashMap::with_capacity(senders.len());
+    for sender in senders {
+        let key = key(&sender.email);
+        match merged.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let info = entry.get_mut();
+                info.count += sender.count;
+                info.last_seen = info.last_seen.max(sender.last_seen);
+                info.sent_to += sender.sent_to;
+                info.bytes += sender.bytes;
+                info.addresses.extend(sender.addresses);
+            }
+            Entry::Vacant(entry) => {
+                let email = entry.key().clone();
+                // A wildcard key is clearer to show than one member's name.
+                let display = if email.contains('*') { email.clone() } else { sender.display };
+                entry.insert(SenderInfo {
+                    email,
+                    display,
+                    ..sender
+                });
+            }
+        }
+    }
+
+    let mut senders: Vec<SenderInfo> = merged.into_values().collect();
+    for sender in &mut senders {
+        sender.addresses.sort_unstable();
+    }
+    sort_by_count(&mut senders);
+    senders
+}
+
+fn sorted_senders(sender_map: &HashMap<String, SenderInfo>) -> Vec<SenderInfo> {
+    let mut senders: Vec<SenderInfo> = sender_map.values().cloned().collect();
+    sort_by_count(&mut senders);
+    senders
+}
+
+/// Largest senders first. Ties fall back to the key, since the maps these
+/// come from iterate in random order and the donut colours slices by rank.
+pub fn sort_by_count(senders: &mut [SenderInfo]) {
+    senders.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.email.cmp(&b.email)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap::ops::mock::MockSession;
+    use futures::executor::block_on;
+
+    #[test]
+    fn scan_fetches_preserve_unread_state() {
+        let every_header = ScanSettings {
+            headers: SenderHeaders {
+                from: true,
+                sender: true,
+                list_id: true,
+            },
+            own_address: Some("me@x.com".to_string()),
+            ..ScanSettings::default()
+        };
+        let mut session = MockSession::new(&[]).with_messages("news@acme.com", 3);
+        session.set_flag(2, "\\Seen");
+        let before = session.flags.clone();
+
+        for settings in [ScanSettings::default(), every_header] {
+            block_on(fetch_senders(&mut session, &[1, 2, 3], &settings)).unwrap();
+        }
+        block_on(tally_recipients(&mut session)).unwrap();
+        assert_eq!(session.flags, before);
+    }
+
+    #[test]
+    fn sender_is_taken_from_the_address() {
+        let from = SenderHeaders::default();
+        for header in [
+            "From: Acme News <news@acme.com>\r\n",
+            "from:news@acme.com\r\n",
+            "From: news@acme.com (Acme News)\r\n",
+            "From: Acme News\r\n <news@acme.com>\r\n",
+            "From: \"Acme <News>\" <news@acme.com>\r\n",
+            "From: \"a@b.com via \\\"x\\\"\" <news@acme.com>\r\n",
+        ] {
+            assert_eq!(parse_sender(header.as_bytes(), from), "news@acme.com", "{header:?}");
+        }
+        assert_eq!(parse_sender(b"From: News@Acme.com\r\n", from), "News@Acme.com");
+    }
+
+    #[test]
+    fn sender_without_an_address_falls_back() {
+        let from = SenderHeaders::default();
+        assert_eq!(parse_sender(b"From: Acme News\r\n", from), "Acme News");
+        assert_eq!(parse_sender(b"From: \"Acme News\"\r\n", from), "Acme News");
+        for garbage in [
+            &b""[..],
+            b"\r\n",
+            b"From:\r\n",
+            b"From: <>\r\n",
+            b"Subject: From: nobody\r\n",
+            b"\xff\xfe\x00",
+        ] {
+            assert_eq!(parse_sender(garbage, from), "unknown", "{garbage:?}");
+        }
+    }
+
+    #[test]
+    fn sender_headers_are_tried_in_order() {
+        let relayed = b"From: relay@esp.com\r\nList-Id: Acme News <news.acme.com>\r\n";
+        assert_eq!(parse_sender(relayed, SenderHeaders::default()), "relay@esp.com");
+        let lists = SenderHeaders {
+            from: false,
+            sender: true,
+            list_id: true,
+        };
+        assert_eq!(parse_sender(relayed, lists), "news.acme.com");
+        assert_eq!(parse_sender(b"Sender: bounce@esp.com\r\n", lists), "bounce@esp.com");
+        assert_eq!(parse_sender(b"From: relay@esp.com\r\n", lists), "unknown");
+        let settings = ScanSettings {
+            headers: lists,
+            ..ScanSettings::default()
+        };
+        assert_eq!(
+            settings.fetch_query(),
+            "(INTERNALDATE RFC822.SIZE BODY.PEEK[HEADER.FIELDS (SENDER LIST-ID)])"
+        );
+    }
+
+    #[test]
+    fn equal_counts_sort_by_address() {
+        let sender = |email: &str, count| SenderInfo {
+            email: email.to_string(),
+            display: email.to_string(),
+            addresses: vec![email.to_string()],
+            count,
+            last_seen: None,
+            sent_to: 0,
+            bytes: 0,
+        };
+        let map: HashMap<String, SenderInfo> = ["c@x.com", "a@x.com", "d@x.com", "b@x.com"]
+            .into_iter()
+            .zip([2, 2, 5, 2])
+            .map(|(email, count)| (email.to_string(), sender(email, count)))
+            .collect();
+        let order: Vec<String> = sorted_senders(&map).into_iter().map(|s| s.email).collect();
+        assert_eq!(order, ["d@x.com", "a@x.com", "b@x.com", "c@x.com"]);
+    }
+
+    #[test]
+    fn messages_are_split_by_where_the_account_is_addressed() {
+        let own = normalize_gmail_address("me@gmail.com");
+        for (header, expected) in [
+            ("To: Me <m.e+shop@gmail.com>\r\nCc: a@x.com\r\n", Recipient::To),
+            ("To: team@x.com\r\nCc: a@x.com,\r\n me@gmail.com\r\n", Recipient::Cc),
+            ("To: me@gmail.com\r\nCc: me@gmail.com\r\n", Recipient::To),
+            ("To: list@lists.x.com\r\n", Recipient::Neither),
+            ("Reply-To: me@gmail.com\r\n", Recipient::Neither),
+        ] {
+            assert_eq!(classify_recipient(header.as_bytes(), &own), expected, "{header}");
+        }
+    }
+
+    #[test]
+    fn fetched_batches_tally_per_sender() {
+        let mut session = MockSession::new(&[])
+            .with_messages("News <News@Acme.com>", 3)
+            .with_messages("friend@x.com", 1);
+        session
+            .messages
+            .insert(5, b"Subject: no sender\r\n\r\n".to_vec());
+
+        let settings = ScanSettings {
+            retain_uids: true,
+            raw_samples: true,
+            ..ScanSettings::default()
+        };
+        let mut tally = Tally::new(&settings);
+        let mut diagnostics = ScanDiagnostics::default();
+        for batch in [&[1, 2][..], &[3, 4, 5]] {
+            let scanned = block_on(fetch_senders(&mut session, batch, &settings)).unwrap();
+            diagnostics.record_unparsed(scanned.unparsed);
+            tally.add(scanned.messages);
+        }
+
+        let news = &tally.senders["news@acme.com"];
+        assert_eq!((news.count, news.display.as_str()), (3, "News@Acme.com"));
+        assert_eq!(news.bytes, 3 * "From: News <News@Acme.com>\r\n\r\n".len() as u64);
+        assert_eq!(tally.senders["friend@x.com"].count, 1);
+        assert_eq!(tally.senders.len(), 2);
+        assert_eq!(tally.uids.unwrap()["news@acme.com"], [1, 2, 3]);
+        let raw_headers = tally.raw_headers.unwrap();
+        assert_eq!(raw_headers["friend@x.com"], ["From: friend@x.com"]);
+        assert_eq!(raw_headers["news@acme.com"].len(), MAX_RAW_SAMPLES);
+        assert!(tally.recipients.is_none());
+        assert_eq!(session.commands("UID FETCH"), 2);
+        assert_eq!(diagnostics.unparsed, 1);
+        assert_eq!(diagnostics.unparsed_samples, ["Subject: no sender"]);
+    }
+
+    #[test]
+    fn subdomains_share_their_registrable_domain() {
+        assert_eq!(registrable_domain_key("news@newsletter.shop.com"), "*@shop.com");
+        assert_eq!(registrable_domain_key("offers@offers.shop.co.uk"), "*@shop.co.uk");
+        assert_eq!(registrable_domain_key("deals@shop.co.uk"), "*@shop.co.uk");
+        assert_eq!(registrable_domain_key("root@localhost"), "root@localhost");
+    }
+
+    #[test]
+    fn gmail_aliases_normalize_to_one_mailbox() {
+        for alias in [
+            "john.doe+news@gmail.com",
+            "johndoe@googlemail.com",
+            "j.o.h.n.doe@gmail.com",
+        ] {
+            assert_eq!(normalize_gmail_address(alias), "johndoe@gmail.com");
+        }
+        assert_eq!(normalize_gmail_address("john.doe+x@example.com"), "john.doe+x@example.com");
+    }
+
+    #[test]
+    fn rotating_addresses_share_a_key() {
+        let pattern = Regex::new(DEFAULT_ROTATION_PATTERN).unwrap();
+        for address in ["bounce-123@mail.acme.com", "bounce-a8f3e9@mail.acme.com"] {
+            assert_eq!(rotation_key(address, &pattern), "bounce-*@mail.acme.com");
+        }
+        for address in ["newsletter@acme.com", "bounce-news@acme.com", "no-at-sign"] {
+            assert_eq!(rotation_key(address, &pattern), address);
+        }
+    }
+
+    #[test]
+    fn sent_folder_check_gives_up_on_timeout_or_cancel() {
+        let never = || futures::future::pending::<Result<(), AppError>>();
+        let cancel = CancellationToken::new();
+        let result = block_on(bounded(Duration::from_millis(10), &cancel, never()));
+        assert!(matches!(result, Err(AppError::Imap(reason)) if reason.contains("timed out")));
+
+        cancel.cancel();
+        let result = block_on(bounded(Duration::from_secs(90), &cancel, never()));
+        assert!(matches!(result, Err(AppError::Cancelled)));
+    }
+
+    #[test]
+    fn non_peek_fetches_mark_the_mock_read() {
+        let non_peek = ScanSettings::default().fetch_query().replace("BODY.PEEK[", "BODY[");
+        for query in [non_peek.as_str(), "(UID RFC822)", "RFC822.TEXT"] {
+            let mut session = MockSession::new(&[]).with_messages("news@acme.com", 1);
+            block_on(session.uid_fetch("1", query)).unwrap();
+            assert!(session.flags[&1].contains("\\Seen"), "{query}");
+        }
+        let mut session = MockSession::new(&[]).with_messages("news@acme.com", 1);
+        block_on(session.uid_fetch("1", "(FLAGS RFC822.HEADER RFC822.SIZE)")).unwrap();
+        assert!(session.flags.is_empty());
+    }
 }
@@ -0,0 +1,278 @@
+//! Minimal `ManageSieve` (RFC 5804) client for installing a server-side rule
+//! that discards future mail from blocked senders.
+
+use crate::error::AppError;
+use crate::oauth;
+use crate::state::ConnectionOptions;
+use async_std::net::TcpStream;
+use base64::Engine;
+use futures::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use std::fmt::Write as _;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use super::provider::ImapProvider;
+use super::{open_tcp, tls_connector, Credentials, CONNECT_TIMEOUT};
+
+/// IANA port for `ManageSieve`. The service runs in plain text and upgrades
+/// with `STARTTLS` before authenticating.
+const MANAGESIEVE_PORT: u16 = 4190;
+
+/// Name of the script this app owns. Other scripts are never modified.
+const SCRIPT_NAME: &str = "email-assassin";
+
+/// Upper bound for one whole session, on top of the connect timeout.
+const SESSION_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// What [`block_senders`] changed on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SieveOutcome {
+    /// Addresses that weren't in the blocklist before.
+    pub added: usize,
+    /// Size of the blocklist after the upload.
+    pub total: usize,
+    /// Whether our script is the active one. When another script is active
+    /// it is left alone and the user has to include ours themselves.
+    pub active: bool,
+}
+
+/// Adds `addresses` to this app's Sieve script on the account's server,
+/// creating the script if needed and activating it if nothing else is.
+pub async fn block_senders(
+    credentials: &Credentials,
+    addresses: &[String],
+    options: &ConnectionOptions,
+) -> Result<SieveOutcome, AppError> {
+    let host = ImapProvider::from_email(&credentials.email).host;
+    let session = async {
+        let tcp = async_std::future::timeout(
+            CONNECT_TIMEOUT,
+            open_tcp(host, MANAGESIEVE_PORT, options.proxy.as_ref()),
+        )
+        .await
+        .map_err(|_| unavailable(host))?
+        .map_err(|_| unavailable(host))?;
+        run_session(credentials, host, tcp, addresses, options).await
+    };
+    async_std::future::timeout(CONNECT_TIMEOUT + SESSION_TIMEOUT, session)
+        .await
+        .map_err(|_| AppError::Sieve(format!("{host} stopped responding")))?
+}
+
+fn unavailable(host: &str) -> AppError {
+    AppError::Sieve(format!(
+        "ManageSieve isn't available on {host}:{MANAGESIEVE_PORT}"
+    ))
+}
+
+async fn run_session(
+    credentials: &Credentials,
+    host: &str,
+    tcp: TcpStream,
+    addresses: &[String],
+    options: &ConnectionOptions,
+) -> Result<SieveOutcome, AppError> {
+    let mut plain = SieveStream::new(tcp);
+    let greeting = plain.read_reply().await?.into_ok()?;
+    if !greeting.iter().any(|line| line.eq_ignore_ascii_case("\"STARTTLS\"")) {
+        // Never send the password in the clear.
+        return Err(AppError::Sieve(format!("{host} doesn't offer STARTTLS")));
+    }
+    plain.command("STARTTLS").await?.into_ok()?;
+
+    let tls = tls_connector(&options.tls)?
+        .connect(host, plain.into_inner())
+        .await
+        .map_err(AppError::from)?;
+    let mut conn = SieveStream::new(tls);
+    // Capabilities are sent again once TLS is up.
+    conn.read_reply().await?.into_ok()?;
+
+    // Every copy of the password or token is wiped, like the original.
+    let (mechanism, auth) = match &credentials.oauth {
+        Some(oauth) => {
+            let token = oauth.access_token(&credentials.email, options).await?;
+            ("XOAUTH2", oauth::xoauth2_response(&credentials.email, &token))
+        }
+        None => (
+            "PLAIN",
+            Zeroizing::new(format!("\0{}\0{}", credentials.email, credentials.password.as_str())),
+        ),
+    };
+    let auth = Zeroizing::new(base64::engine::general_purpose::STANDARD.encode(auth.as_bytes()));
+    let login = Zeroizing::new(format!("AUTHENTICATE \"{mechanism}\" \"{}\"", auth.as_str()));
+    conn.command(&login)
+        .await?
+        .into_ok()
+        .map_err(|e| AppError::Sieve(format!("login refused: {e}")))?;
+
+    let scripts = conn.command("LISTSCRIPTS").await?.into_ok()?;
+    let ours = format!("\"{SCRIPT_NAME}\"");
+    let mut blocklist = Vec::new();
+    if scripts.iter().any(|line| line.starts_with(&ours)) {
+        let existing = conn.command(&format!("GETSCRIPT {ours}")).await?.into_ok()?;
+        blocklist = parse_blocklist(&existing.concat());
+    }
+    let before = blocklist.len();
+    for address in addresses {
+        let address = address.to_lowercase();
+        if !blocklist.contains(&address) {
+            blocklist.push(address);
+        }
+    }
+
+    let script = render_script(&blocklist);
+    conn.command(&format!("PUTSCRIPT {ours} {{{}+}}\r\n{script}", script.len()))
+        .await?
+        .into_ok()?;
+
+    let other_active = scripts
+        .iter()
+        .any(|line| line.ends_with(" ACTIVE") && !line.starts_with(&ours));
+    if !other_active {
+        conn.command(&format!("SETACTIVE {ours}")).await?.into_ok()?;
+    }
+
+    if let Err(e) = conn.command("LOGOUT").await {
+        tracing::warn!(error = %e, "ManageSieve logout failed");
+    }
+    Ok(SieveOutcome {
+        added: blocklist.len() - before,
+        total: blocklist.len(),
+        active: !other_active,
+    })
+}
+
+/// The script body. Addresses go one per line so [`parse_blocklist`] can
+/// read them back without a Sieve parser.
+fn render_script(addresses: &[String]) -> String {
+    let mut script = String::from("# Managed by Email Assassin; changes here are overwritten.\r\n");
+    if addresses.is_empty() {
+        return script;
+    }
+    script.push_str("if address :is \"from\" [\r\n");
+    for (i, address) in addresses.iter().enumerate() {
+        let separator = if i + 1 < addresses.len() { "," } else { "" };
+        let _ = write!(script, "    \"{}\"{separator}\r\n", sieve_escape(address));
+    }
+    script.push_str("] {\r\n    discard;\r\n    stop;\r\n}\r\n");
+    script
+}
+
+/// Addresses listed in a script written by [`render_script`].
+fn parse_blocklist(script: &str) -> Vec<String> {
+    script
+        .lines()
+        .map(str::trim)
+        .filter_map(|line| line.trim_end_matches(',').strip_prefix('"')?.strip_suffix('"'))
+        .map(|address| address.replace("\\\"", "\"").replace("\\\\", "\\"))
+        .collect()
+}
+
+fn sieve_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A finished server response: the data lines before the status line,
+/// with literals inlined, and the status itself.
+struct Reply {
+    ok: bool,
+    status: String,
+    data: Vec<String>,
+}
+
+impl Reply {
+    fn into_ok(self) -> Result<Vec<String>, AppError> {
+        if self.ok {
+            Ok(self.data)
+        } else {
+            Err(AppError::Sieve(self.status))
+        }
+    }
+}
+
+struct SieveStream<S> {
+    inner: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SieveStream<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            inner: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    async fn command(&mut self, line: &str) -> Result<Reply, AppError> {
+        let stream = self.inner.get_mut();
+        // Written in two parts rather than formatted, so a login line isn't
+        // copied into a buffer that escapes zeroizing.
+        for part in [line.as_bytes(), b"\r\n"] {
+            stream
+                .write_all(part)
+                .await
+                .map_err(|e| AppError::Sieve(e.to_string()))?;
+        }
+        stream.flush().await.map_err(|e| AppError::Sieve(e.to_string()))?;
+        self.read_reply().await
+    }
+
+    async fn read_reply(&mut self) -> Result<Reply, AppError> {
+        let mut data = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = self
+                .inner
+                .read_line(&mut line)
+                .await
+                .map_err(|e| AppError::Sieve(e.to_string()))?;
+            if read == 0 {
+                return Err(AppError::Sieve("connection closed".to_string()));
+            }
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+
+            if let Some(len) = literal_length(&line) {
+                let mut literal = vec![0; len];
+                self.inner
+                    .read_exact(&mut literal)
+                    .await
+                    .map_err(|e| AppError::Sieve(e.to_string()))?;
+                data.push(String::from_utf8_lossy(&literal).into_owned());
+                continue;
+            }
+            let keyword = line.split(' ').next().unwrap_or("").to_uppercase();
+            match keyword.as_str() {
+                "OK" => return Ok(Reply { ok: true, status: line, data }),
+                "NO" | "BYE" => return Ok(Reply { ok: false, status: line, data }),
+                _ => data.push(line),
+            }
+        }
+    }
+}
+
+/// Length of a `{n}` or `{n+}` literal announced on its own line.
+fn literal_length(line: &str) -> Option<usize> {
+    line.strip_prefix('{')?
+        .strip_suffix('}')?
+        .trim_end_matches('+')
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocklist_survives_a_script_round_trip() {
+        let addresses = vec!["news@acme.com".to_string(), "odd\"name@x.com".to_string()];
+        let script = render_script(&addresses);
+        assert!(script.contains("discard;"));
+        assert_eq!(parse_blocklist(&script), addresses);
+        assert!(parse_blocklist(&render_script(&[])).is_empty());
+        assert_eq!(literal_length("{42+}"), Some(42));
+    }
+}
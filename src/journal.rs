@@ -0,0 +1,176 @@
+use crate::bridge::DeleteRequest;
+use crate::error::AppError;
+use crate::imap::deleter::DeleteTarget;
+use crate::state::{DeleteMode, SenderInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const JOURNAL_FILE: &str = "delete_journal.json";
+
+/// On-disk record of a delete run, rewritten after every sender so a run cut
+/// short by a crash can be picked up again. Holds no credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteJournal {
+    pub email: String,
+    pub folder: String,
+    pub mode: DeleteMode,
+    pub trash_folder: String,
+    /// Missing from journals written before the option existed.
+    #[serde(default)]
+    pub keep_newest: usize,
+    /// Senders not yet attempted, in run order.
+    pub remaining: Vec<JournalEntry>,
+    /// `UIDVALIDITY` the targets' UIDs were recorded under.
+    #[serde(default)]
+    pub uid_validity: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub email: String,
+    pub display: String,
+    pub target: DeleteTarget,
+}
+
+impl DeleteJournal {
+    pub fn from_request(request: &DeleteRequest) -> Self {
+        Self {
+            email: request.credentials.email.clone(),
+            folder: request.folder.clone(),
+            mode: request.mode,
+            trash_folder: request.trash_folder.clone(),
+            keep_newest: request.keep_newest,
+            remaining: request
+                .senders
+                .iter()
+                .zip(&request.targets)
+                .map(|(sender, target)| JournalEntry {
+                    email: sender.email.clone(),
+                    display: sender.display.clone(),
+                    target: target.clone(),
+                })
+                .collect(),
+            uid_validity: request.uid_validity,
+        }
+    }
+
+    /// Senders and targets to hand back to a new delete request. Scan
+    /// figures weren't journaled, so counts start at zero.
+    pub fn senders_and_targets(&self) -> (Vec<SenderInfo>, Vec<DeleteTarget>) {
+        self.remaining
+            .iter()
+            .map(|entry| {
+                let sender = SenderInfo {
+                    email: entry.email.clone(),
+                    display: entry.display.clone(),
+                    addresses: vec![entry.email.clone()],
+                    count: 0,
+                    last_seen: None,
+                    sent_to: 0,
+                    bytes: 0,
+                };
+                (sender, entry.target.clone())
+            })
+            .unzip()
+    }
+
+    /// Drops `email` from the remaining senders.
+    pub fn complete(&mut self, email: &str) {
+        self.remaining.retain(|entry| entry.email != email);
+    }
+
+    /// The interrupted run left by a previous session, if any.
+    pub fn load() -> Option<Self> {
+        let path = journal_path()?;
+        match Self::load_from(&path) {
+            Ok(journal) => journal,
+            Err(e) => {
+                tracing::warn!(error = %e, "ignoring unreadable delete journal");
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        let path = journal_path()
+            .ok_or_else(|| AppError::Journal("no storage directory".to_string()))?;
+        self.save_to(&path)
+    }
+
+    pub fn clear() {
+        let Some(path) = journal_path() else {
+            return;
+        };
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(error = %e, "failed to remove delete journal");
+            }
+        }
+    }
+
+    fn load_from(path: &Path) -> Result<Option<Self>, AppError> {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| AppError::Journal(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(AppError::Journal(e.to_string())),
+        }
+    }
+
+    /// Writes to a temporary file first so a crash mid-write can't leave a
+    /// truncated journal behind.
+    fn save_to(&self, path: &Path) -> Result<(), AppError> {
+        let json = serde_json::to_vec(self).map_err(|e| AppError::Journal(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| AppError::Journal(e.to_string()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(|e| AppError::Journal(e.to_string()))?;
+        std::fs::rename(&tmp, path).map_err(|e| AppError::Journal(e.to_string()))
+    }
+}
+
+fn journal_path() -> Option<PathBuf> {
+    eframe::storage_dir(crate::APP_NAME).map(|dir| dir.join(JOURNAL_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_round_trips_and_shrinks_as_senders_finish() {
+        let mut journal = DeleteJournal {
+            email: "me@gmail.com".to_string(),
+            folder: "INBOX".to_string(),
+            mode: DeleteMode::Trash,
+            trash_folder: "[Gmail]/Trash".to_string(),
+            keep_newest: 3,
+            remaining: vec![
+                JournalEntry {
+                    email: "a@x.com".to_string(),
+                    display: "A@x.com".to_string(),
+                    target: DeleteTarget::Addresses(vec!["a@x.com".to_string()]),
+                },
+                JournalEntry {
+                    email: "b@x.com".to_string(),
+                    display: "b@x.com".to_string(),
+                    target: DeleteTarget::Uids(vec![4, 8, 15]),
+                },
+            ],
+            uid_validity: Some(1_700_000_000),
+        };
+        let path = std::env::temp_dir()
+            .join(format!("email-assassin-journal-{}", std::process::id()))
+            .join(JOURNAL_FILE);
+
+        journal.complete("a@x.com");
+        journal.save_to(&path).unwrap();
+        let loaded = DeleteJournal::load_from(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded.as_ref(), Some(&journal));
+        assert_eq!(loaded.unwrap().remaining[0].email, "b@x.com");
+    }
+}
@@ -19,15 +19,29 @@ use mimalloc::MiMalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+/// Window title, and the name eframe keys its storage directory by.
+pub const APP_NAME: &str = "Email Assassin";
+
+/// Starts with deleting hidden and locked off, whatever was saved.
+const ANALYZE_ONLY_FLAG: &str = "--analyze-only";
+
+mod analysis;
 mod app;
+mod blocklist;
 mod bridge;
 mod error;
+mod export;
+mod format;
 mod imap;
+mod journal;
+mod notification;
+mod oauth;
 mod state;
 mod ui;
 
 fn main() -> eframe::Result<()> {
     tracing_subscriber::fmt::init();
+    let analyze_only = std::env::args().skip(1).any(|arg| arg == ANALYZE_ONLY_FLAG);
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -37,8 +51,8 @@ fn main() -> eframe::Result<()> {
     };
 
     eframe::run_native(
-        "Email Assassin",
+        APP_NAME,
         options,
-        Box::new(|cc| Ok(Box::new(app::EmailAssassinApp::new(cc)))),
+        Box::new(move |cc| Ok(Box::new(app::EmailAssassinApp::new(cc, analyze_only)))),
     )
 }
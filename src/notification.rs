@@ -0,0 +1,26 @@
+//! Desktop notifications for scans and deletes that finish while the window
+//! is in the background.
+
+use crate::APP_NAME;
+
+/// Sound played with the notification when asked for: a freedesktop sound
+/// theme name on Linux, the system default sound elsewhere.
+#[cfg(all(unix, not(target_os = "macos")))]
+const SOUND: &str = "complete";
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+const SOUND: &str = "Default";
+
+/// Shows a notification from a short-lived thread, since reaching the
+/// notification service can block for a moment. Failures are only logged.
+pub fn show(summary: &str, body: &str, sound: bool) {
+    let mut notification = notify_rust::Notification::new();
+    notification.appname(APP_NAME).summary(summary).body(body);
+    if sound {
+        notification.sound_name(SOUND);
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            tracing::warn!(error = %e, "failed to show notification");
+        }
+    });
+}
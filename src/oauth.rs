@@ -0,0 +1,472 @@
+//! OAuth sign-in for Gmail and Outlook, which are retiring app passwords.
+//! Consent runs once in the browser; the refresh token it yields is kept in
+//! the OS keyring and traded for short-lived access tokens at login.
+
+use crate::error::AppError;
+use crate::imap::provider::{ImapProvider, ProviderKind};
+use crate::imap::{open_tcp, tls_connector, CONNECT_TIMEOUT};
+use crate::state::ConnectionOptions;
+use async_std::net::TcpListener;
+use base64::Engine;
+use futures::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Keyring service the refresh tokens are filed under, one entry per
+/// address.
+const KEYRING_SERVICE: &str = "email-assassin";
+
+/// How long the user gets to finish the consent page.
+const CONSENT_TIMEOUT: Duration = Duration::from_mins(5);
+
+/// Upper bound for one token endpoint request, on top of the connect
+/// timeout.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Access tokens are renewed this long before they expire, so one can't
+/// lapse between being handed out and the login that uses it.
+const EXPIRY_MARGIN: Duration = Duration::from_mins(1);
+
+/// Where a provider's consent page and token endpoint live.
+#[derive(Debug, Clone, Copy)]
+struct Endpoints {
+    authorize: &'static str,
+    token: &'static str,
+    scope: &'static str,
+}
+
+fn endpoints(kind: ProviderKind) -> Option<Endpoints> {
+    match kind {
+        ProviderKind::Gmail => Some(Endpoints {
+            authorize: "https://accounts.google.com/o/oauth2/v2/auth",
+            token: "https://oauth2.googleapis.com/token",
+            scope: "https://mail.google.com/",
+        }),
+        ProviderKind::Outlook => Some(Endpoints {
+            authorize: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+            token: "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+            // offline_access is what gets Microsoft to issue a refresh token.
+            scope: "https://outlook.office.com/IMAP.AccessAsUser.All offline_access",
+        }),
+        ProviderKind::Yahoo | ProviderKind::ICloud => None,
+    }
+}
+
+/// Whether OAuth sign-in is available for `email`'s provider.
+pub fn is_supported(email: &str) -> bool {
+    !ImapProvider::is_fallback(email) && endpoints(ImapProvider::from_email(email).kind).is_some()
+}
+
+fn endpoints_for(email: &str) -> Result<Endpoints, AppError> {
+    endpoints(ImapProvider::from_email(email).kind)
+        .filter(|_| !ImapProvider::is_fallback(email))
+        .ok_or_else(|| AppError::OAuth(format!("not available for {email}")))
+}
+
+/// The app registration the user created with their provider. Desktop apps
+/// can't keep a secret, so Google's installed-app secret is stored as
+/// plainly as the ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthClient {
+    pub client_id: String,
+    /// Required by Google, left empty for Microsoft.
+    pub client_secret: String,
+}
+
+impl OAuthClient {
+    /// Form fields identifying the app to the token endpoint.
+    fn fields(&self) -> Vec<(&'static str, &str)> {
+        let mut fields = vec![("client_id", self.client_id.trim())];
+        if !self.client_secret.trim().is_empty() {
+            fields.push(("client_secret", self.client_secret.trim()));
+        }
+        fields
+    }
+}
+
+/// Runs the authorization code flow for `email` in the browser and saves
+/// the resulting refresh token to the keyring.
+pub async fn connect_account(
+    email: &str,
+    client: &OAuthClient,
+    options: &ConnectionOptions,
+) -> Result<(), AppError> {
+    let endpoints = endpoints_for(email)?;
+    if client.client_id.trim().is_empty() {
+        return Err(AppError::OAuth("no client ID configured".to_string()));
+    }
+
+    // Loopback redirect with a port picked by the OS, which both providers
+    // accept for desktop apps.
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(|e| AppError::OAuth(format!("cannot listen for the redirect: {e}")))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| AppError::OAuth(e.to_string()))?
+        .port();
+    let redirect_uri = format!("http://127.0.0.1:{port}");
+
+    let verifier = random_token();
+    let state = random_token();
+    let consent_url = Url::parse_with_params(
+        endpoints.authorize,
+        [
+            ("response_type", "code"),
+            ("client_id", client.client_id.trim()),
+            ("redirect_uri", &redirect_uri),
+            ("scope", endpoints.scope),
+            ("state", &state),
+            ("code_challenge", &pkce_challenge(&verifier)),
+            ("code_challenge_method", "S256"),
+            ("login_hint", email),
+            // Google only issues a refresh token with these two.
+            ("access_type", "offline"),
+            ("prompt", "consent"),
+        ],
+    )
+    .map_err(|e| AppError::OAuth(e.to_string()))?;
+    webbrowser::open(consent_url.as_str())
+        .map_err(|e| AppError::OAuth(format!("cannot open a browser: {e}")))?;
+
+    let code = async_std::future::timeout(CONSENT_TIMEOUT, wait_for_code(&listener, &state))
+        .await
+        .map_err(|_| AppError::OAuth("timed out waiting for the consent page".to_string()))??;
+
+    let mut form = client.fields();
+    form.extend([
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("code_verifier", verifier.as_str()),
+    ]);
+    let tokens = request_tokens(endpoints.token, &form, options).await?;
+    let refresh_token = tokens
+        .refresh_token
+        .ok_or_else(|| AppError::OAuth("the provider didn't issue a refresh token".to_string()))?;
+    keyring_entry(email)?
+        .set_password(&refresh_token)
+        .map_err(|e| AppError::OAuth(format!("cannot save to the keyring: {e}")))
+}
+
+/// Accepts connections on `listener` until the browser is redirected back
+/// with an authorization code for `state`.
+async fn wait_for_code(listener: &TcpListener, state: &str) -> Result<Zeroizing<String>, AppError> {
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| AppError::OAuth(e.to_string()))?;
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await.is_err() {
+            continue;
+        }
+        // GET /?code=...&state=... HTTP/1.1
+        let target = request_line.split(' ').nth(1).unwrap_or("/");
+        let outcome = parse_redirect(target, state);
+
+        let page = match &outcome {
+            Some(Ok(_)) => "Signed in. You can close this tab and return to Email Assassin.",
+            Some(Err(_)) => "Sign-in failed. Return to Email Assassin for details.",
+            // Favicon requests and the like.
+            None => "Not found",
+        };
+        let status = if outcome.is_some() { "200 OK" } else { "404 Not Found" };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{page}",
+            page.len()
+        );
+        let mut stream = reader.into_inner();
+        if let Err(e) = stream.write_all(response.as_bytes()).await {
+            tracing::debug!(error = %e, "failed to answer the OAuth redirect");
+        }
+        if let Some(outcome) = outcome {
+            return outcome;
+        }
+    }
+}
+
+/// The code in a redirect to `target`, an error if the provider reported
+/// one or the state doesn't match, or `None` for unrelated requests.
+fn parse_redirect(target: &str, state: &str) -> Option<Result<Zeroizing<String>, AppError>> {
+    let url = Url::parse("http://127.0.0.1").ok()?.join(target).ok()?;
+    let mut code = None;
+    let mut returned_state = None;
+    let mut error = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "code" => code = Some(Zeroizing::new(value.into_owned())),
+            "state" => returned_state = Some(value.into_owned()),
+            "error" => error = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+    if let Some(error) = error {
+        return Some(Err(AppError::OAuth(format!("consent refused: {error}"))));
+    }
+    let code = code?;
+    if returned_state.as_deref() != Some(state) {
+        return Some(Err(AppError::OAuth("redirect didn't match this sign-in".to_string())));
+    }
+    Some(Ok(code))
+}
+
+/// Short-lived access tokens for one account, renewed from the stored
+/// refresh token. Shared by every connection a job opens, so a scan's
+/// workers trigger one refresh between them rather than one each.
+#[derive(Debug)]
+pub struct OAuthLogin {
+    client: OAuthClient,
+    cached: tokio::sync::Mutex<Option<AccessToken>>,
+}
+
+struct AccessToken {
+    token: Zeroizing<String>,
+    renew_at: Instant,
+}
+
+impl std::fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("token", &"<redacted>")
+            .field("renew_at", &self.renew_at)
+            .finish()
+    }
+}
+
+impl OAuthLogin {
+    pub fn new(client: OAuthClient) -> Self {
+        Self {
+            client,
+            cached: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// A current access token for `email`, refreshed if the cached one is
+    /// missing or about to expire.
+    pub async fn access_token(
+        &self,
+        email: &str,
+        options: &ConnectionOptions,
+    ) -> Result<Zeroizing<String>, AppError> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref().filter(|t| t.renew_at > Instant::now()) {
+            return Ok(token.token.clone());
+        }
+
+        let endpoints = endpoints_for(email)?;
+        let entry = keyring_entry(email)?;
+        let refresh_token = Zeroizing::new(entry.get_password().map_err(|e| match e {
+            keyring::Error::NoEntry => {
+                AppError::OAuth(format!("{email} isn't connected; use Connect account first"))
+            }
+            e => AppError::OAuth(format!("cannot read the keyring: {e}")),
+        })?);
+
+        let mut form = self.client.fields();
+        form.extend([
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+        ]);
+        let tokens = request_tokens(endpoints.token, &form, options).await?;
+
+        // Microsoft rotates refresh tokens; the old one keeps working for a
+        // while, so failing to store the new one isn't fatal.
+        if let Some(rotated) = tokens.refresh_token.filter(|t| **t != *refresh_token) {
+            if let Err(e) = entry.set_password(&rotated) {
+                tracing::warn!(error = %e, "failed to store rotated refresh token");
+            }
+        }
+
+        let lifetime = Duration::from_secs(tokens.expires_in.unwrap_or(0));
+        let token = AccessToken {
+            token: tokens.access_token,
+            renew_at: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN),
+        };
+        Ok(cached.insert(token).token.clone())
+    }
+}
+
+/// Removes the stored refresh token for `email`, if there is one.
+pub fn disconnect_account(email: &str) -> Result<(), AppError> {
+    match keyring_entry(email)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(AppError::OAuth(format!("cannot update the keyring: {e}"))),
+    }
+}
+
+fn keyring_entry(email: &str) -> Result<keyring::Entry, AppError> {
+    keyring::Entry::new(KEYRING_SERVICE, &email.trim().to_lowercase())
+        .map_err(|e| AppError::OAuth(format!("keyring unavailable: {e}")))
+}
+
+/// The `XOAUTH2` initial client response, before base64.
+pub fn xoauth2_response(email: &str, access_token: &str) -> Zeroizing<String> {
+    Zeroizing::new(format!("user={email}\x01auth=Bearer {access_token}\x01\x01"))
+}
+
+/// `XOAUTH2` for async-imap's `authenticate`. Servers answer a rejected
+/// token with a JSON challenge, which gets an empty reply so they send the
+/// final `NO`.
+pub struct XOAuth2 {
+    response: Option<Zeroizing<String>>,
+}
+
+impl XOAuth2 {
+    pub fn new(email: &str, access_token: &str) -> Self {
+        Self {
+            response: Some(xoauth2_response(email, access_token)),
+        }
+    }
+}
+
+impl std::fmt::Debug for XOAuth2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("XOAuth2").finish_non_exhaustive()
+    }
+}
+
+impl async_imap::Authenticator for XOAuth2 {
+    type Response = Zeroizing<String>;
+
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        self.response.take().unwrap_or_default()
+    }
+}
+
+/// Fields of a token endpoint reply that this app uses.
+#[derive(Deserialize)]
+struct TokenReply {
+    access_token: Zeroizing<String>,
+    expires_in: Option<u64>,
+    refresh_token: Option<Zeroizing<String>>,
+}
+
+/// An OAuth error reply (RFC 6749 section 5.2).
+#[derive(Deserialize)]
+struct ErrorReply {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// POSTs `form` to the token endpoint `url`, through the proxy and TLS
+/// settings used for IMAP.
+async fn request_tokens(
+    url: &str,
+    form: &[(&str, &str)],
+    options: &ConnectionOptions,
+) -> Result<TokenReply, AppError> {
+    let url = Url::parse(url).map_err(|e| AppError::OAuth(e.to_string()))?;
+    let host = url.host_str().unwrap_or_default();
+    let body = Zeroizing::new(
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish(),
+    );
+    // HTTP/1.0 so the reply can't be chunked and simply ends at EOF.
+    let request = Zeroizing::new(format!(
+        "POST {} HTTP/1.0\r\nHost: {host}\r\nAccept: application/json\r\n\
+         Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        url.path(),
+        body.len(),
+        body.as_str()
+    ));
+
+    let exchange = async {
+        let tcp = open_tcp(host, 443, options.proxy.as_ref());
+        let tcp = async_std::future::timeout(CONNECT_TIMEOUT, tcp)
+            .await
+            .map_err(|_| AppError::Connection(format!("{host} timed out")))??;
+        let mut tls = tls_connector(&options.tls)?
+            .connect(host, tcp)
+            .await
+            .map_err(AppError::from)?;
+        tls.write_all(request.as_bytes())
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?;
+        let mut reply = Zeroizing::new(Vec::new());
+        tls.read_to_end(&mut reply)
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?;
+        Ok::<_, AppError>(reply)
+    };
+    let reply = async_std::future::timeout(CONNECT_TIMEOUT + TOKEN_TIMEOUT, exchange)
+        .await
+        .map_err(|_| AppError::OAuth(format!("{host} stopped responding")))??;
+    parse_token_reply(&reply)
+}
+
+fn parse_token_reply(reply: &[u8]) -> Result<TokenReply, AppError> {
+    let split = reply
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| AppError::OAuth("malformed reply from the token endpoint".to_string()))?;
+    let body = &reply[split + 4..];
+    if let Ok(tokens) = serde_json::from_slice::<TokenReply>(body) {
+        return Ok(tokens);
+    }
+    Err(match serde_json::from_slice::<ErrorReply>(body) {
+        Ok(ErrorReply {
+            error,
+            error_description: Some(description),
+        }) => AppError::OAuth(format!("{error}: {description}")),
+        Ok(ErrorReply { error, .. }) => AppError::OAuth(error),
+        Err(_) => {
+            let status = String::from_utf8_lossy(&reply[..split]);
+            let status = status.lines().next().unwrap_or_default().to_string();
+            AppError::OAuth(format!("unexpected reply from the token endpoint: {status}"))
+        }
+    })
+}
+
+/// 32 random bytes, base64url-encoded: a PKCE verifier or a state value.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 PKCE challenge for `verifier` (RFC 7636).
+fn pkce_challenge(verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_yields_code_only_for_matching_state() {
+        let code = parse_redirect("/?code=abc%2F1&state=s1", "s1").unwrap().unwrap();
+        assert_eq!(code.as_str(), "abc/1");
+        assert!(parse_redirect("/?code=abc&state=other", "s1").unwrap().is_err());
+        assert!(parse_redirect("/?error=access_denied&state=s1", "s1").unwrap().is_err());
+        assert!(parse_redirect("/favicon.ico", "s1").is_none());
+    }
+
+    #[test]
+    fn token_replies_and_errors_parse() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            {\"access_token\":\"at\",\"expires_in\":3599,\"token_type\":\"Bearer\"}";
+        let tokens = parse_token_reply(ok).unwrap();
+        assert_eq!((tokens.access_token.as_str(), tokens.expires_in), ("at", Some(3599)));
+        assert!(tokens.refresh_token.is_none());
+
+        let denied = b"HTTP/1.0 400 Bad Request\r\n\r\n\
+            {\"error\":\"invalid_grant\",\"error_description\":\"Token has been revoked.\"}";
+        let error = parse_token_reply(denied).err().unwrap().to_string();
+        assert!(error.contains("invalid_grant: Token has been revoked."), "{error}");
+
+        // RFC 7636 appendix B.
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
@@ -1,4 +1,116 @@
-use std::collections::HashMap;
+use crate::analysis::ReplyBalance;
+use crate::blocklist::{Blocklist, BlocklistSettings};
+use crate::bridge::{RescanRequest, ScanRequest, SkippedSenders};
+use crate::imap::deleter::DeleteTarget;
+use crate::imap::diagnose::DiagnosticStep;
+use crate::imap::provider::ImapProvider;
+use crate::export::ScanSnapshot;
+use crate::imap::scanner::{
+    normalize_gmail_address, sort_by_count, RecipientCounts, ScanDiagnostics, ScanSettings,
+    SenderGrouping, SenderHeaders, DEFAULT_ROTATION_PATTERN,
+};
+use crate::imap::Credentials;
+use crate::journal::DeleteJournal;
+use crate::oauth::{self, OAuthClient, OAuthLogin};
+use crate::ui::chart_export::ChartExport;
+use crate::ui::donut::DonutCache;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// How fast the scan bar closes on the reported progress, per second.
+const PROGRESS_EASING_RATE: f32 = 12.0;
+
+/// Local parts of the `no-reply@` addresses automated mail comes from.
+pub const NO_REPLY_PATTERN: &str = r"(?i)no-?reply";
+
+/// Starting point for the editable "Select matching" preset.
+const DEFAULT_AUTOMATED_PATTERN: &str = r"(?i)^(notifications?|alerts?|newsletters?|updates?)$";
+
+/// Domains protected out of the box: banks, payment services, tax and
+/// government mail. Subdomains match too, so `gov` covers `irs.gov`.
+const DEFAULT_PROTECTED_DOMAINS: [&str; 16] = [
+    "gov",
+    "mil",
+    "gov.uk",
+    "gc.ca",
+    "gov.au",
+    "europa.eu",
+    "chase.com",
+    "bankofamerica.com",
+    "wellsfargo.com",
+    "citi.com",
+    "capitalone.com",
+    "americanexpress.com",
+    "paypal.com",
+    "schwab.com",
+    "fidelity.com",
+    "vanguard.com",
+];
+
+/// Domains whose senders are all protected without adding them one by
+/// one. Persisted across runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtectedDomains {
+    pub domains: BTreeSet<String>,
+    /// Also protect the account's own domain, when it is a custom one
+    /// rather than a mail provider's.
+    pub own_domain: bool,
+}
+
+impl Default for ProtectedDomains {
+    fn default() -> Self {
+        Self {
+            domains: DEFAULT_PROTECTED_DOMAINS.iter().map(ToString::to_string).collect(),
+            own_domain: true,
+        }
+    }
+}
+
+/// How much detail the dashboard shows. Persisted across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayLimits {
+    /// Senders drawn as their own donut slice.
+    pub donut_slices: usize,
+    /// Senders the kill list shows at first, and how many more each
+    /// "Show more" adds.
+    pub kill_list_rows: usize,
+}
+
+impl Default for DisplayLimits {
+    fn default() -> Self {
+        Self {
+            donut_slices: 20,
+            kill_list_rows: 100,
+        }
+    }
+}
+
+/// Desktop notifications for long jobs. Persisted across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    /// Notify when a scan or delete finishes while the window is in the
+    /// background.
+    pub on_finish: bool,
+    pub sound: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            on_finish: true,
+            sound: false,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AppPhase {
@@ -8,42 +120,417 @@ pub enum AppPhase {
     Deleting,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// How the app logs in to the account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignIn {
+    #[default]
+    AppPassword,
+    /// `XOAUTH2` with a refresh token saved by "Connect account".
+    OAuth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeleteMode {
     Trash,
     Permanent,
+    /// Gmail only: drop the `\Inbox` label and keep the message in All Mail.
+    Archive,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl DeleteMode {
+    /// Label for the button that carries out the action.
+    pub fn action_label(self) -> &'static str {
+        match self {
+            DeleteMode::Trash => "Move to Trash",
+            DeleteMode::Permanent => "Delete permanently",
+            DeleteMode::Archive => "Remove from Inbox",
+        }
+    }
+
+    /// Past tense used in the completion status.
+    pub fn done_verb(self) -> &'static str {
+        match self {
+            DeleteMode::Trash | DeleteMode::Permanent => "Removed",
+            DeleteMode::Archive => "Archived",
+        }
+    }
+}
+
+/// Which of a sender's messages a delete removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteScope {
+    /// Everything from the sender currently in the folder.
+    WholeFolder,
+    /// Only the messages the last scan actually looked at.
+    ScannedOnly,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub fn visuals(self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+}
+
+/// Which end of the folder a depth-limited scan samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanDirection {
+    Newest,
+    Oldest,
+}
+
+/// Which list the reply-balance table shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceView {
+    Unanswered,
+    Ignored,
+}
+
+/// Column the raw data table is ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Sender,
+    Count,
+    LastSeen,
+}
+
+impl SortKey {
+    /// Direction used when a column is first clicked: A-Z for names,
+    /// biggest/newest first for numbers and dates.
+    pub fn default_ascending(self) -> bool {
+        matches!(self, SortKey::Sender)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SenderInfo {
+    /// Aggregation key; also what the selection map is keyed by.
     pub email: String,
+    /// Original-cased address from the first message seen, for display only.
+    pub display: String,
+    /// Every raw address folded into this entry. Deletion searches for all of
+    /// them, since the key may be a normalised form the server never saw.
+    pub addresses: Vec<String>,
     pub count: usize,
+    /// Most recent INTERNALDATE seen for this sender, if the server sent one.
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Messages in the Sent folder addressed to this sender.
+    pub sent_to: usize,
+    /// Total `RFC822.SIZE` of the scanned messages. Zero for scans saved
+    /// before sizes were fetched.
+    #[serde(default)]
+    pub bytes: u64,
 }
 
+impl SenderInfo {
+    /// A two-way contact: someone the user has also written to.
+    pub fn is_correspondent(&self) -> bool {
+        self.sent_to > 0
+    }
+}
+
+/// Senders waiting on the delete confirmation dialog.
 #[derive(Debug, Clone)]
+pub struct DeletePreview {
+    pub senders: Vec<SenderInfo>,
+    /// Per-sender counts from a fresh server search, `None` while pending.
+    pub live_counts: Option<Result<Vec<usize>, String>>,
+    /// Trash folder the server doesn't have; Trash mode would fail.
+    pub missing_trash: Option<String>,
+    /// The user ticked that a permanent delete may reach messages a
+    /// partial scan never counted.
+    pub scope_acknowledged: bool,
+}
+
+/// Progress of a server-side block request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockStatus {
+    Uploading,
+    Done(String),
+    Failed(String),
+}
+
+/// Progress of a check that the special folders exist on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FolderCheck {
+    Checking,
+    /// The folders `LIST` didn't return; empty when all were found.
+    Done(Vec<String>),
+    Failed(String),
+}
+
+/// Progress of a "Diagnose connection" run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnosis {
+    Running,
+    Done(Vec<DiagnosticStep>),
+}
+
+/// Progress of an OAuth "Connect account" request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectStatus {
+    /// The consent page is open in the browser.
+    Waiting,
+    Connected(String),
+    Failed(String),
+}
+
+/// Whether the IMAP server was reachable the last time the app talked to
+/// it, shown as the sidebar's status dot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// Nothing has connected yet.
+    #[default]
+    Unknown,
+    Online,
+    /// The last attempt couldn't reach the server or lost the connection.
+    Offline(String),
+}
+
+/// SOCKS5 proxy used to reach the IMAP server. Empty credentials mean the
+/// proxy is used without authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 1080,
+            username: String::new(),
+            password: String::new(),
+        }
+    }
+}
+
+/// Extra trust settings for the TLS handshake. The default performs full
+/// validation against the system trust store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM file with an additional CA certificate to trust. Empty means none.
+    pub ca_cert_path: String,
+    /// Skip certificate validation entirely. Only meant for testing.
+    pub accept_invalid_certs: bool,
+    /// Client certificate for servers that require one: a PKCS#12 bundle,
+    /// or a PEM certificate when `client_key_path` is set. Empty means none.
+    pub client_cert_path: String,
+    /// PKCS#8 PEM private key for a PEM client certificate.
+    pub client_key_path: String,
+    /// Password of a PKCS#12 bundle.
+    pub client_cert_password: String,
+}
+
+/// Everything that shapes how a connection is established, independent of
+/// the account being logged into.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    pub proxy: Option<ProxyConfig>,
+    pub tls: TlsConfig,
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)] // one per settings checkbox
 pub struct AppState {
     // Credentials
     pub email: String,
-    pub password: String,
+    /// Wiped on drop. Pre-sized so typing doesn't reallocate and strand
+    /// unwiped copies of the partial password on the heap.
+    pub password: Zeroizing<String>,
+    pub sign_in: SignIn,
+    /// App registration used for OAuth. Persisted across runs.
+    pub oauth_client: OAuthClient,
+    pub connect_status: Option<ConnectStatus>,
+    pub link_status: LinkStatus,
     pub folder: String,
+    /// Folder last scanned, keyed by lowercased address. Persisted across
+    /// runs and prefilled when the address is entered again.
+    pub last_folders: BTreeMap<String, String>,
 
     // Scan settings
     pub scan_depth: u32,
+    pub scan_direction: ScanDirection,
+    pub search_query: String,
+    pub check_correspondents: bool,
+    pub merge_gmail_aliases: bool,
+    /// Collapse rotating ESP addresses matching `rotation_pattern`.
+    pub collapse_rotating: bool,
+    pub rotation_pattern: String,
+    /// Count every subdomain of a company's domain as one sender.
+    pub group_by_domain: bool,
+    /// Header fields tried, in order, for each message's sender.
+    pub sender_headers: SenderHeaders,
+    /// Remember which UIDs each sender had. Costs memory on huge folders.
+    pub retain_uids: bool,
+    /// Debug aid: keep the raw sender headers of a few messages per sender,
+    /// shown by clicking a row in the raw table.
+    pub keep_raw_headers: bool,
+    /// Also fetch To and Cc and count where the account was addressed.
+    pub recipient_breakdown: bool,
+    /// Scan again once a delete finishes so counts come from the server
+    /// rather than the optimistic update.
+    pub rescan_after_delete: bool,
+    /// Hide every way to delete, for exploring without risk. Persisted.
+    pub analyze_only: bool,
+    /// Set by `--analyze-only`; the sidebar toggle can't turn it off.
+    pub analyze_only_locked: bool,
+    pub search_timeout_secs: u64,
+
+    // Network
+    pub proxy_enabled: bool,
+    pub proxy: ProxyConfig,
+    pub tls: TlsConfig,
+
+    // Appearance
+    pub theme: Theme,
+    pub display_limits: DisplayLimits,
+    pub notifications: Notifications,
 
     // State
     pub phase: AppPhase,
+    /// Never persisted, so every session starts on Trash.
     pub delete_mode: DeleteMode,
+    /// Permanent mode was confirmed this session and can be picked freely.
+    pub permanent_unlocked: bool,
+    /// Permanent mode was clicked and its confirmation is showing.
+    pub confirm_permanent: bool,
+    pub delete_scope: DeleteScope,
+    /// Overrides the provider's trash folder when non-empty, for localized
+    /// or custom mailbox names.
+    pub trash_folder: String,
+    /// Same for the Sent folder the correspondent check reads and the spam
+    /// folder the "Scan Spam folder" preset opens.
+    pub sent_folder: String,
+    pub junk_folder: String,
+    /// Outcome of the last check of the special folders against `LIST`.
+    pub folder_check: Option<FolderCheck>,
+    /// Report from the last connection diagnosis.
+    pub diagnosis: Option<Diagnosis>,
+    /// Newest messages per sender a delete leaves in place; 0 for none.
+    /// Set in the confirmation dialog.
+    pub keep_newest: usize,
+    /// Milliseconds to wait before each command that changes the folder.
+    pub delete_pause_ms: u64,
+    /// Messages per delete command; 0 uses the provider's default.
+    pub delete_chunk_size: usize,
+    /// Delete all selected senders with one search instead of one each.
+    pub combine_delete: bool,
 
     // Progress
+    /// Furthest the scan has reported. Batches finish out of order, so
+    /// this only ever moves forward during a scan.
     pub scan_progress: f32,
+    /// What the scan bar shows: eased toward `scan_progress` each frame so
+    /// it moves steadily instead of jumping.
+    pub shown_scan_progress: f32,
     pub scan_status: String,
     pub delete_progress: f32,
     pub delete_status: String,
+    /// Progress through the sender being purged, for big single senders.
+    pub delete_sender_progress: Option<f32>,
+    /// Worker and batch counters from the current or last scan.
+    pub scan_diagnostics: Option<ScanDiagnostics>,
 
     // Results
+    /// File name the results were loaded from instead of scanned. Deleting
+    /// needs credentials for the account first.
+    pub loaded_scan: Option<String>,
     pub total_emails: usize,
+    /// Set when the scan covered only part of the folder.
+    pub limited_scope: Option<String>,
+    /// The server opened the scanned folder read-only, so nothing in it
+    /// can be deleted.
+    pub folder_read_only: bool,
+    /// The scanned folder's `UIDVALIDITY`, which `sender_uids` are only
+    /// good under.
+    pub uid_validity: Option<u32>,
+    /// Message count of a folder the last scan refused as too large.
+    pub large_folder: Option<u32>,
+    /// Scan batches that failed; non-zero means the counts are partial.
+    pub failed_batches: usize,
     pub senders: Vec<SenderInfo>,
+    /// Headers `senders` were keyed on. Anything but plain `From:` can only
+    /// be deleted by UID.
+    pub scanned_headers: SenderHeaders,
+    /// UIDs seen by the last scan, keyed by lowercased raw address.
+    /// `None` when the scan didn't retain them.
+    pub sender_uids: Option<HashMap<String, Vec<u32>>>,
+    /// Raw header samples from the last scan, keyed like `sender_uids`.
+    pub raw_headers: Option<HashMap<String, Vec<String>>>,
+    /// To/Cc breakdown from the last scan, keyed like `sender_uids`.
+    pub recipients: Option<HashMap<String, RecipientCounts>>,
+    /// Sender whose raw header samples are shown under the raw table.
+    pub inspected_sender: Option<String>,
     pub sender_selected: HashMap<String, bool>,
+    pub donut_cache: DonutCache,
+    /// The "Export chart" window, while it is open.
+    pub chart_export: Option<ChartExport>,
+    /// Lowercased addresses that can never be selected for deletion.
+    /// Persisted across runs.
+    pub protected: BTreeSet<String>,
+    pub protected_domains: ProtectedDomains,
+    /// Free-text notes on senders, keyed by lowercased address. Persisted
+    /// across runs, so they reattach to a sender on every scan.
+    pub sender_notes: BTreeMap<String, String>,
+    pub blocklist: BlocklistSettings,
+    /// Senders matching the blocklist when it was last applied.
+    pub blocklisted: HashSet<String>,
+    /// Text box for adding to `protected_domains`.
+    pub domain_input: String,
+    /// How many senders the kill list renders. Selection helpers always
+    /// cover the full list.
+    pub kill_list_limit: usize,
+    /// Threshold for "Uncheck below": senders with fewer messages are
+    /// deselected.
+    pub min_selected_count: usize,
+    /// Local-part regex behind the "Select matching" preset.
+    pub automated_pattern: String,
+    /// Merged sender whose underlying addresses are listed under the kill list.
+    pub expanded_sender: Option<String>,
+    /// Kill list row picked with the arrow keys, which Space toggles.
+    pub kill_list_focus: Option<usize>,
+    /// Kill list shows one collapsible row per domain.
+    pub group_kill_list: bool,
+    /// Domains whose senders are listed under their group row.
+    pub expanded_domains: HashSet<String>,
+    /// Senders with a live recount still running.
+    pub rescanning: HashSet<String>,
+    /// Sent vs received comparison, present when the Sent folder was checked.
+    pub reply_balance: Option<ReplyBalance>,
+    /// Why the last scan's correspondent check didn't finish, if it was
+    /// asked for. Nobody is protected as a correspondent then.
+    pub correspondents_unchecked: Option<String>,
+    pub balance_view: BalanceView,
+    pub delete_preview: Option<DeletePreview>,
+    /// Senders the last delete run could not purge, with the error for each.
+    pub delete_failures: Vec<(String, String)>,
+    /// Shared with the running delete, if any, so unchecking a sender it
+    /// hasn't reached yet skips it.
+    pub delete_skipped: Option<SkippedSenders>,
+    /// Outcome of the last "Block on server" request.
+    pub block_status: Option<BlockStatus>,
+    /// A delete from an earlier session that never finished.
+    pub interrupted_delete: Option<DeleteJournal>,
+
+    // Raw table
+    pub sort_key: SortKey,
+    pub sort_ascending: bool,
+    /// Find box above the raw table and which of its matches is current.
+    pub raw_search: String,
+    pub raw_match: usize,
 
     // Errors
     pub error_message: Option<String>,
@@ -53,31 +540,603 @@ impl Default for AppState {
     fn default() -> Self {
         Self {
             email: String::new(),
-            password: String::new(),
+            password: Zeroizing::new(String::with_capacity(128)),
+            sign_in: SignIn::default(),
+            oauth_client: OAuthClient::default(),
+            connect_status: None,
+            link_status: LinkStatus::Unknown,
             folder: "INBOX".to_string(),
+            last_folders: BTreeMap::new(),
             scan_depth: 0,
+            scan_direction: ScanDirection::Newest,
+            search_query: "ALL".to_string(),
+            check_correspondents: true,
+            merge_gmail_aliases: false,
+            collapse_rotating: false,
+            rotation_pattern: DEFAULT_ROTATION_PATTERN.to_string(),
+            group_by_domain: false,
+            sender_headers: SenderHeaders::default(),
+            retain_uids: true,
+            keep_raw_headers: false,
+            recipient_breakdown: false,
+            rescan_after_delete: false,
+            analyze_only: false,
+            analyze_only_locked: false,
+            search_timeout_secs: 120,
+            proxy_enabled: false,
+            proxy: ProxyConfig::default(),
+            tls: TlsConfig::default(),
+            theme: Theme::default(),
+            display_limits: DisplayLimits::default(),
+            notifications: Notifications::default(),
             phase: AppPhase::Idle,
             delete_mode: DeleteMode::Trash,
+            permanent_unlocked: false,
+            confirm_permanent: false,
+            delete_scope: DeleteScope::WholeFolder,
+            keep_newest: 0,
+            delete_pause_ms: 200,
+            delete_chunk_size: 0,
+            combine_delete: false,
+            trash_folder: String::new(),
+            sent_folder: String::new(),
+            junk_folder: String::new(),
+            folder_check: None,
+            diagnosis: None,
             scan_progress: 0.0,
+            shown_scan_progress: 0.0,
             scan_status: String::new(),
             delete_progress: 0.0,
             delete_status: String::new(),
+            delete_sender_progress: None,
+            scan_diagnostics: None,
+            loaded_scan: None,
             total_emails: 0,
+            limited_scope: None,
+            folder_read_only: false,
+            uid_validity: None,
+            large_folder: None,
+            failed_batches: 0,
             senders: Vec::new(),
+            scanned_headers: SenderHeaders::default(),
+            sender_uids: None,
+            raw_headers: None,
+            recipients: None,
+            inspected_sender: None,
             sender_selected: HashMap::new(),
+            donut_cache: DonutCache::default(),
+            chart_export: None,
+            protected: BTreeSet::new(),
+            protected_domains: ProtectedDomains::default(),
+            sender_notes: BTreeMap::new(),
+            blocklist: BlocklistSettings::default(),
+            blocklisted: HashSet::new(),
+            domain_input: String::new(),
+            kill_list_limit: DisplayLimits::default().kill_list_rows,
+            min_selected_count: 10,
+            automated_pattern: DEFAULT_AUTOMATED_PATTERN.to_string(),
+            expanded_sender: None,
+            kill_list_focus: None,
+            group_kill_list: false,
+            expanded_domains: HashSet::new(),
+            rescanning: HashSet::new(),
+            reply_balance: None,
+            correspondents_unchecked: None,
+            balance_view: BalanceView::Unanswered,
+            delete_preview: None,
+            delete_failures: Vec::new(),
+            delete_skipped: None,
+            block_status: None,
+            interrupted_delete: None,
+            sort_key: SortKey::Count,
+            sort_ascending: false,
+            raw_search: String::new(),
+            raw_match: 0,
             error_message: None,
         }
     }
 }
 
 impl AppState {
+    /// Snapshot of the login fields for a background job.
+    pub fn credentials(&self) -> Arc<Credentials> {
+        Arc::new(Credentials {
+            email: self.email.clone(),
+            password: self.password.clone(),
+            oauth: self
+                .uses_oauth()
+                .then(|| Arc::new(OAuthLogin::new(self.oauth_client.clone()))),
+        })
+    }
+
+    /// OAuth was picked and the provider supports it; otherwise the app
+    /// password is used.
+    pub fn uses_oauth(&self) -> bool {
+        self.sign_in == SignIn::OAuth && oauth::is_supported(&self.email)
+    }
+
+    /// Moves the scan bar a frame's worth toward the reported progress,
+    /// covering most of the gap in a quarter of a second. Returns whether
+    /// it still has ground to make up.
+    pub fn ease_scan_progress(&mut self, dt: f32) -> bool {
+        let target = self.scan_progress;
+        let gap = target - self.shown_scan_progress;
+        if gap <= 0.001 {
+            self.shown_scan_progress = target;
+            return false;
+        }
+        self.shown_scan_progress += gap * (1.0 - (-dt * PROGRESS_EASING_RATE).exp());
+        true
+    }
+
+    /// Resets results for a fresh scan and builds the request for it.
+    pub fn start_scan(&mut self, allow_large_folder: bool) -> ScanRequest {
+        self.phase = AppPhase::Scanning;
+        self.scan_progress = 0.0;
+        self.shown_scan_progress = 0.0;
+        self.scan_status = "Starting...".to_string();
+        self.error_message = None;
+        self.scan_diagnostics = None;
+        self.large_folder = None;
+        self.limited_scope = None;
+        self.folder_read_only = false;
+        self.uid_validity = None;
+        self.senders.clear();
+        self.sender_uids = None;
+        self.raw_headers = None;
+        self.recipients = None;
+        self.inspected_sender = None;
+        self.sender_selected.clear();
+        self.kill_list_focus = None;
+        self.delete_failures.clear();
+        self.reply_balance = None;
+        self.correspondents_unchecked = None;
+        self.loaded_scan = None;
+        self.scanned_headers = self.sender_headers;
+        let by_from = self.sender_headers.is_from_only();
+        if !by_from {
+            self.delete_scope = DeleteScope::ScannedOnly;
+        }
+        self.last_folders
+            .insert(self.email.trim().to_lowercase(), self.folder.clone());
+
+        ScanRequest {
+            credentials: self.credentials(),
+            folder: self.folder.clone(),
+            scan_depth: self.scan_depth,
+            scan_direction: self.scan_direction,
+            search_query: self.search_query.trim().to_string(),
+            check_correspondents: self.check_correspondents,
+            sent_folder: self.sent_folder(),
+            grouping: SenderGrouping {
+                gmail_aliases: self.merge_gmail_aliases,
+                rotation: self.rotation_regex().and_then(Result::ok),
+                registrable_domains: self.group_by_domain,
+            },
+            settings: ScanSettings {
+                headers: self.sender_headers,
+                // A From search can't find senders keyed on other headers.
+                retain_uids: self.retain_uids || !by_from,
+                raw_samples: self.keep_raw_headers,
+                own_address: self
+                    .recipient_breakdown
+                    .then(|| normalize_gmail_address(&self.email.trim().to_lowercase())),
+            },
+            search_timeout: Duration::from_secs(self.search_timeout_secs),
+            allow_large_folder,
+            connection: self.connection_options(),
+        }
+    }
+
+    /// Scans again after a delete, keeping the senders that failed and
+    /// their selection so they can still be retried.
+    pub fn start_rescan(&mut self) -> ScanRequest {
+        let failures = std::mem::take(&mut self.delete_failures);
+        // The folder was scanned before, so its size was already accepted.
+        let request = self.start_scan(true);
+        for (sender, _) in &failures {
+            self.sender_selected.insert(sender.clone(), true);
+        }
+        self.delete_failures = failures;
+        request
+    }
+
+    /// Shows a scan saved earlier in place of live results. Its account and
+    /// folder are filled in so a delete, once credentials are entered,
+    /// targets the same mailbox.
+    pub fn load_scan(&mut self, file_name: String, snapshot: ScanSnapshot) {
+        self.senders = snapshot.senders;
+        self.total_emails = snapshot.total_emails;
+        self.scanned_headers = snapshot.headers;
+        self.email = snapshot.email;
+        self.folder = snapshot.folder;
+        self.loaded_scan = Some(file_name);
+        self.phase = AppPhase::ScanComplete;
+        self.error_message = None;
+        self.scan_diagnostics = None;
+        self.failed_batches = 0;
+        self.limited_scope = None;
+        self.folder_read_only = false;
+        self.uid_validity = None;
+        self.reply_balance = None;
+        self.correspondents_unchecked = None;
+        self.sender_uids = None;
+        self.raw_headers = None;
+        self.recipients = None;
+        self.inspected_sender = None;
+        self.delete_scope = if self.scanned_headers.is_from_only() {
+            DeleteScope::WholeFolder
+        } else {
+            DeleteScope::ScannedOnly
+        };
+        self.sender_selected.clear();
+        self.delete_failures.clear();
+        self.apply_blocklist();
+    }
+
+    /// Rereads the blocklist file and flags the senders it lists, checking
+    /// them too when that is turned on. Protected senders and
+    /// correspondents are flagged but never checked. A file that can't be
+    /// read is reported and flags nothing.
+    pub fn apply_blocklist(&mut self) {
+        self.blocklisted.clear();
+        let path = self.blocklist.path.trim();
+        if path.is_empty() {
+            return;
+        }
+        let list = match Blocklist::load(Path::new(path)) {
+            Ok(list) => list,
+            Err(e) => {
+                self.error_message = Some(e.to_string());
+                return;
+            }
+        };
+        for sender in &self.senders {
+            let listed = std::iter::once(&sender.email)
+                .chain(&sender.addresses)
+                .any(|address| list.matches(address));
+            if !listed {
+                continue;
+            }
+            self.blocklisted.insert(sender.email.clone());
+            if self.blocklist.auto_select && self.bulk_selectable(sender) {
+                self.sender_selected.insert(sender.email.clone(), true);
+            }
+        }
+    }
+
+    /// Enough to log in: an address and either an app password or OAuth.
+    pub fn has_credentials(&self) -> bool {
+        self.email.contains('@') && (self.uses_oauth() || !self.password.is_empty())
+    }
+
+    /// Switches to the folder last scanned with the current address, if
+    /// there is one.
+    pub fn restore_folder(&mut self) {
+        if let Some(folder) = self.last_folders.get(&self.email.trim().to_lowercase()) {
+            self.folder.clone_from(folder);
+        }
+    }
+
+    /// Trash folder for Trash mode: the override if set, else the
+    /// provider's default.
+    pub fn trash_folder(&self) -> String {
+        override_or(&self.trash_folder, ImapProvider::from_email(&self.email).trash_folder)
+    }
+
+    /// Sent folder for the correspondent check, resolved the same way.
+    pub fn sent_folder(&self) -> String {
+        override_or(&self.sent_folder, ImapProvider::from_email(&self.email).sent_folder)
+    }
+
+    /// Spam folder for the "Scan Spam folder" preset, resolved the same way.
+    pub fn junk_folder(&self) -> String {
+        override_or(&self.junk_folder, ImapProvider::from_email(&self.email).junk_folder)
+    }
+
+    /// Trash, Sent and spam folders, as they'd be used right now.
+    pub fn special_folders(&self) -> Vec<String> {
+        vec![self.trash_folder(), self.sent_folder(), self.junk_folder()]
+    }
+
+    /// Messages per delete command: the override if set, else the
+    /// provider's default.
+    pub fn delete_chunk_size(&self) -> usize {
+        match self.delete_chunk_size {
+            0 => ImapProvider::from_email(&self.email).delete_chunk_size,
+            size => size,
+        }
+    }
+
+    /// The compiled rotation pattern, or `None` when collapsing is off.
+    pub fn rotation_regex(&self) -> Option<Result<Regex, regex::Error>> {
+        self.collapse_rotating
+            .then(|| Regex::new(self.rotation_pattern.trim()))
+    }
+
+    /// Connection settings to hand to the background worker. The proxy is
+    /// only included when it is enabled and has a host.
+    pub fn connection_options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            proxy: (self.proxy_enabled && !self.proxy.host.trim().is_empty())
+                .then(|| self.proxy.clone()),
+            tls: self.tls.clone(),
+        }
+    }
+
+    /// What deleting `sender` should touch under the current scope. Without
+    /// retained UIDs a scan-scoped delete matches nothing.
+    pub fn delete_target(&self, sender: &SenderInfo) -> DeleteTarget {
+        match (self.delete_scope, &self.sender_uids) {
+            (DeleteScope::WholeFolder, _) => DeleteTarget::Addresses(sender.addresses.clone()),
+            (DeleteScope::ScannedOnly, None) => DeleteTarget::Uids(Vec::new()),
+            (DeleteScope::ScannedOnly, Some(uids)) => DeleteTarget::Uids(
+                sender
+                    .addresses
+                    .iter()
+                    .filter_map(|address| uids.get(address))
+                    .flatten()
+                    .copied()
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Checked senders, minus any on the allowlist even if a stale
+    /// selection still marks them.
     pub fn selected_senders(&self) -> Vec<&SenderInfo> {
         self.senders
             .iter()
             .filter(|s| self.sender_selected.get(&s.email).copied().unwrap_or(false))
+            .filter(|s| !self.is_protected(s))
+            .collect()
+    }
+
+    /// Checks every sender except two-way correspondents and protected
+    /// senders, which have to be selected by hand or not at all.
+    pub fn select_all(&mut self) {
+        for sender in &self.senders {
+            self.sender_selected.insert(sender.email.clone(), self.bulk_selectable(sender));
+        }
+    }
+
+    /// A combined delete is running. It can't skip senders, so the kill
+    /// list's checkboxes stay as they were when it started.
+    pub fn selection_locked(&self) -> bool {
+        self.phase == AppPhase::Deleting && self.delete_skipped.is_none()
+    }
+
+    /// Whether selections covering many senders at once may check
+    /// `sender`. Protected senders and people the user has written to are
+    /// only ever checked one at a time.
+    pub fn bulk_selectable(&self, sender: &SenderInfo) -> bool {
+        !sender.is_correspondent() && !self.is_protected(sender)
+    }
+
+    /// Keys of the senders at `members`, indices into `senders`, that a
+    /// domain's group checkbox checks and unchecks.
+    pub fn group_selectable(&self, members: &[usize]) -> Vec<String> {
+        members
+            .iter()
+            .map(|&i| &self.senders[i])
+            .filter(|sender| self.bulk_selectable(sender))
+            .map(|sender| sender.email.clone())
             .collect()
     }
 
+    /// On the allowlist under its key or any address folded into it, or
+    /// sending from a protected domain.
+    pub fn is_protected(&self, sender: &SenderInfo) -> bool {
+        self.protected.contains(&sender.email)
+            || sender.addresses.iter().any(|a| self.protected.contains(a))
+            || self.protecting_domain(sender).is_some()
+    }
+
+    /// The note on `sender`'s key, or else on the first address folded
+    /// into it that has one.
+    pub fn note_for(&self, sender: &SenderInfo) -> Option<&str> {
+        std::iter::once(&sender.email)
+            .chain(&sender.addresses)
+            .find_map(|address| self.sender_notes.get(address))
+            .map(String::as_str)
+    }
+
+    /// Replaces the note on `email`. A blank note removes it.
+    pub fn set_note(&mut self, email: &str, note: String) {
+        let email = email.to_lowercase();
+        if note.trim().is_empty() {
+            self.sender_notes.remove(&email);
+        } else {
+            self.sender_notes.insert(email, note);
+        }
+    }
+
+    /// The protected domain, or parent domain, one of `sender`'s addresses
+    /// is under.
+    pub fn protecting_domain(&self, sender: &SenderInfo) -> Option<String> {
+        let own = self.own_domain();
+        sender.addresses.iter().find_map(|address| {
+            let (_, mut domain) = address.rsplit_once('@')?;
+            loop {
+                let listed = self.protected_domains.domains.contains(domain);
+                if listed || own.as_deref() == Some(domain) {
+                    return Some(domain.to_string());
+                }
+                domain = domain.split_once('.')?.1;
+            }
+        })
+    }
+
+    /// The account's domain if it should be protected. Provider domains
+    /// like `gmail.com` never are, since that would cover strangers too.
+    fn own_domain(&self) -> Option<String> {
+        let email = self.email.trim();
+        if !self.protected_domains.own_domain || !ImapProvider::is_fallback(email) {
+            return None;
+        }
+        let (_, domain) = email.rsplit_once('@')?;
+        (!domain.is_empty()).then(|| domain.to_lowercase())
+    }
+
+    /// Adds the domain typed into `domain_input`, without any leading `@`.
+    pub fn add_protected_domain(&mut self) {
+        let domain = self.domain_input.trim().trim_start_matches('@').to_lowercase();
+        if !domain.is_empty() {
+            self.protected_domains.domains.insert(domain);
+        }
+        self.domain_input.clear();
+    }
+
+    /// Adds `sender` to the allowlist and drops it from the selection.
+    pub fn protect(&mut self, sender: &SenderInfo) {
+        self.protected.insert(sender.email.clone());
+        self.sender_selected.remove(&sender.email);
+    }
+
+    pub fn unprotect(&mut self, sender: &SenderInfo) {
+        self.protected.remove(&sender.email);
+        for address in &sender.addresses {
+            self.protected.remove(address);
+        }
+    }
+
+    /// Optimistic update after a delete: purged senders leave the list, or
+    /// are trimmed to what was kept, and are deselected.
+    pub fn forget_deleted(&mut self, removed: &[String], keep_newest: usize) {
+        if keep_newest == 0 {
+            self.senders.retain(|s| !removed.contains(&s.email));
+        } else {
+            for sender in &mut self.senders {
+                if removed.contains(&sender.email) && sender.count > keep_newest {
+                    sender.bytes = sender.bytes * keep_newest as u64 / sender.count as u64;
+                    sender.count = keep_newest;
+                }
+            }
+        }
+        for sender in removed {
+            self.sender_selected.remove(sender);
+        }
+    }
+
+    /// The live count request for `sender`, or `None` when the scan keyed
+    /// senders on `Sender:` or `List-Id:`, which the From search a rescan
+    /// runs can't match.
+    pub fn rescan_request(&self, sender: &SenderInfo) -> Option<RescanRequest> {
+        self.scanned_headers.is_from_only().then(|| RescanRequest {
+            credentials: self.credentials(),
+            folder: self.folder.clone(),
+            email: sender.email.clone(),
+            addresses: sender.addresses.clone(),
+            connection: self.connection_options(),
+        })
+    }
+
+    /// Replaces a sender's scanned count with a live one. The size is
+    /// scaled to match, and a sender with nothing left is dropped. The
+    /// folder total stays: it came from the whole folder, which a depth
+    /// limit or search never narrowed, so the live count is already in it.
+    pub fn apply_rescan(&mut self, email: &str, count: usize) {
+        self.rescanning.remove(email);
+        let Some(index) = self.senders.iter().position(|s| s.email == email) else {
+            return;
+        };
+        let sender = &mut self.senders[index];
+        if count == 0 {
+            self.senders.remove(index);
+            self.sender_selected.remove(email);
+            return;
+        }
+        if sender.count > 0 {
+            sender.bytes = sender.bytes * count as u64 / sender.count as u64;
+        }
+        sender.count = count;
+        sort_by_count(&mut self.senders);
+    }
+
+    /// Rough space freed by removing `total_removed` messages from the
+    /// `removed` senders, from their average scanned size. `None` when the
+    /// scan has no sizes for them.
+    pub fn estimate_freed(&self, removed: &[String], total_removed: usize) -> Option<u64> {
+        let (count, bytes) = self
+            .senders
+            .iter()
+            .filter(|s| removed.contains(&s.email))
+            .fold((0, 0), |(count, bytes), s| (count + s.count, bytes + s.bytes));
+        let per_message = bytes as f64 / count.max(1) as f64;
+        (bytes > 0).then_some((per_message * total_removed as f64) as u64)
+    }
+
+    /// Header click handler: re-clicking the active column flips direction.
+    pub fn toggle_sort(&mut self, key: SortKey) {
+        if self.sort_key == key {
+            self.sort_ascending = !self.sort_ascending;
+        } else {
+            self.sort_key = key;
+            self.sort_ascending = key.default_ascending();
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.sender_selected.clear();
+    }
+
+    /// Unchecks every sender with fewer than `min_count` messages, including
+    /// those past the kill list's display limit.
+    pub fn deselect_below(&mut self, min_count: usize) {
+        for sender in self.senders.iter().filter(|s| s.count < min_count) {
+            if let Some(selected) = self.sender_selected.get_mut(&sender.email) {
+                *selected = false;
+            }
+        }
+    }
+
+    /// Checks every sender with an address whose local part matches
+    /// `pattern`. Protected senders and correspondents are skipped, as in
+    /// [`Self::select_all`].
+    pub fn select_matching(&mut self, pattern: &Regex) {
+        for sender in &self.senders {
+            if !self.bulk_selectable(sender) {
+                continue;
+            }
+            let matches = sender.addresses.iter().any(|address| {
+                let local = address.rsplit_once('@').map_or(address.as_str(), |(local, _)| local);
+                pattern.is_match(local)
+            });
+            if matches {
+                self.sender_selected.insert(sender.email.clone(), true);
+            }
+        }
+    }
+
+    /// Where the account was addressed on `sender`'s mail, summed over
+    /// every address merged into it. `None` without a breakdown.
+    pub fn recipient_counts(&self, sender: &SenderInfo) -> Option<RecipientCounts> {
+        let recipients = self.recipients.as_ref()?;
+        let mut counts = RecipientCounts::default();
+        for address in &sender.addresses {
+            if let Some(found) = recipients.get(address) {
+                counts += *found;
+            }
+        }
+        Some(counts)
+    }
+
+    /// Checks every sender whose mail mostly had the account on `Cc:`.
+    /// Only [`Self::bulk_selectable`] senders are checked.
+    pub fn select_mostly_cc(&mut self) {
+        for sender in &self.senders {
+            if !self.bulk_selectable(sender) {
+                continue;
+            }
+            if self
+                .recipient_counts(sender)
+                .is_some_and(|counts| counts.cc * 2 > counts.total())
+            {
+                self.sender_selected.insert(sender.email.clone(), true);
+            }
+        }
+    }
+
     pub fn selected_email_count(&self) -> usize {
         self.selected_senders().iter().map(|s| s.count).sum()
     }
@@ -90,3 +1149,88 @@ impl AppState {
         self.senders.iter().map(|s| s.count).sum()
     }
 }
+
+/// A folder setting's trimmed value, or `default` when it is left empty.
+fn override_or(setting: &str, default: &str) -> String {
+    match setting.trim() {
+        "" => default.to_string(),
+        name => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender(email: &str, sent_to: usize) -> SenderInfo {
+        SenderInfo {
+            email: email.to_string(),
+            display: email.to_string(),
+            addresses: vec![email.to_string()],
+            count: 10,
+            last_seen: None,
+            sent_to,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn group_toggle_leaves_correspondents_and_protected_unchecked() {
+        let mut state = AppState {
+            senders: vec![
+                sender("deals@shop.example", 0),
+                sender("owner@shop.example", 2),
+                sender("receipts@shop.example", 0),
+            ],
+            ..AppState::default()
+        };
+        state.protected.insert("receipts@shop.example".to_string());
+
+        for email in state.group_selectable(&[0, 1, 2]) {
+            state.sender_selected.insert(email, true);
+        }
+        let checked = |state: &AppState, email: &str| {
+            state.sender_selected.get(email).copied().unwrap_or(false)
+        };
+        assert!(checked(&state, "deals@shop.example"));
+        assert!(!checked(&state, "owner@shop.example"));
+        assert!(!checked(&state, "receipts@shop.example"));
+    }
+
+    #[test]
+    fn rescan_needs_senders_keyed_on_from() {
+        let mut state = AppState::default();
+        let news = sender("news@lists.example", 0);
+        assert!(state.rescan_request(&news).is_some());
+
+        state.scanned_headers = SenderHeaders {
+            from: false,
+            sender: false,
+            list_id: true,
+        };
+        let list = SenderInfo {
+            addresses: vec!["news.lists.example".to_string()],
+            ..sender("news.lists.example", 0)
+        };
+        assert!(state.rescan_request(&list).is_none());
+    }
+
+    #[test]
+    fn rescan_after_depth_limited_scan_keeps_folder_total() {
+        // The newest 20 of 100 messages were scanned; the sender has 25
+        // in the whole folder, which the total already counts.
+        let mut state = AppState {
+            senders: vec![sender("deals@shop.example", 0), sender("news@shop.example", 0)],
+            total_emails: 100,
+            ..AppState::default()
+        };
+        state.apply_rescan("deals@shop.example", 25);
+        assert_eq!(state.total_emails, 100);
+        assert_eq!(state.senders[0].email, "deals@shop.example");
+        assert_eq!(state.senders[0].count, 25);
+
+        state.apply_rescan("news@shop.example", 0);
+        assert_eq!(state.total_emails, 100);
+        assert_eq!(state.senders.len(), 1);
+    }
+}
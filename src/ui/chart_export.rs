@@ -0,0 +1,135 @@
+//! Saving the inbox composition donut as a PNG. The chart is painted
+//! rather than rendered to an image, so it is laid out with its title and
+//! legend in a window and that part of a screenshot is saved.
+
+use crate::export;
+use crate::format::thousands;
+use crate::state::AppState;
+use crate::ui::donut::{self, DonutCache};
+use egui::{Context, Rect};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Chart widths offered, in points. The PNG gets as many pixels per point
+/// as the display it was captured on.
+const SIZES: [(&str, f32); 3] = [("Small", 240.0), ("Medium", 360.0), ("Large", 480.0)];
+
+/// Marks the screenshot this module asked for among any others.
+const SCREENSHOT_TAG: &str = "chart_export";
+
+/// The open export window.
+#[derive(Debug, Clone)]
+pub struct ChartExport {
+    size: f32,
+    /// Kept apart from the dashboard's, which is sized differently.
+    cache: DonutCache,
+    /// Where the title, chart and legend were drawn last frame.
+    content: Option<Rect>,
+}
+
+impl Default for ChartExport {
+    fn default() -> Self {
+        Self {
+            size: SIZES[1].1,
+            cache: DonutCache::default(),
+            content: None,
+        }
+    }
+}
+
+pub fn draw_chart_export(ctx: &Context, state: &mut AppState) {
+    let Some(export) = &mut state.chart_export else {
+        return;
+    };
+    if let Some(screenshot) = take_screenshot(ctx) {
+        if let Some(content) = export.content {
+            save_capture(ctx, &screenshot, content, &mut state.error_message);
+        }
+        return;
+    }
+
+    let mut open = true;
+    let mut capture = false;
+    egui::Window::new("Export chart")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (label, size) in SIZES {
+                    ui.selectable_value(&mut export.size, size, label);
+                }
+                ui.separator();
+                capture = ui.button("Save PNG...").clicked();
+            });
+            ui.separator();
+
+            let content = ui.vertical(|ui| {
+                ui.set_width(export.size);
+                ui.heading("Inbox Composition");
+                ui.weak(format!(
+                    "{} · {}: {} emails",
+                    state.email,
+                    state.folder,
+                    thousands(state.total_emails)
+                ));
+                ui.add_space(4.0);
+                let limit = state.display_limits.donut_slices;
+                // No selection outlines: the image is about the inbox.
+                let selected = HashMap::new();
+                let cache = &mut export.cache;
+                donut::draw_donut(ui, &state.senders, limit, &selected, cache, export.size);
+                donut::draw_legend(ui, &state.senders, limit);
+            });
+            export.content = Some(content.response.rect);
+        });
+
+    if !open {
+        state.chart_export = None;
+    } else if capture {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::new(
+            SCREENSHOT_TAG,
+        )));
+    }
+}
+
+/// The screenshot this module asked for, once it arrives.
+fn take_screenshot(ctx: &Context) -> Option<Arc<egui::ColorImage>> {
+    ctx.input(|input| {
+        input.events.iter().find_map(|event| match event {
+            egui::Event::Screenshot {
+                user_data, image, ..
+            } => {
+                let tag = user_data.data.as_ref()?.downcast_ref::<&str>()?;
+                (*tag == SCREENSHOT_TAG).then(|| Arc::clone(image))
+            }
+            _ => None,
+        })
+    })
+}
+
+/// Crops `screenshot` to `content` and asks where to save it.
+fn save_capture(
+    ctx: &Context,
+    screenshot: &egui::ColorImage,
+    content: Rect,
+    error: &mut Option<String>,
+) {
+    let pixels_per_point = ctx.pixels_per_point();
+    let [width, height] = screenshot.size;
+    let screen = Rect::from_min_size(
+        egui::Pos2::ZERO,
+        egui::vec2(width as f32, height as f32) / pixels_per_point,
+    );
+    // Parts of the window dragged off screen weren't captured.
+    let region = content.intersect(screen);
+    if !region.is_positive() {
+        *error = Some("The chart is off screen; move the export window into view".to_string());
+        return;
+    }
+    match export::save_png(&screenshot.region(&region, Some(pixels_per_point))) {
+        Ok(Some(path)) => tracing::info!(path = %path.display(), "chart exported"),
+        Ok(None) => {}
+        Err(e) => *error = Some(e.to_string()),
+    }
+}
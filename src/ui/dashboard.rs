@@ -1,37 +1,61 @@
-use crate::bridge::UiCommand;
-use crate::state::{AppPhase, AppState};
-use crate::ui::donut;
+use crate::bridge::{BlockRequest, DeleteRequest, SkippedSenders, UiCommand};
+use crate::export::{self, FilterFormat};
+use crate::format::thousands;
+use crate::journal::DeleteJournal;
+use crate::state::{
+    AppPhase, AppState, BlockStatus, DeleteMode, DeletePreview, DeleteScope, ScanDirection,
+    SenderInfo, SortKey, NO_REPLY_PATTERN,
+};
+use crate::ui::chart_export::{self, ChartExport};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+use crate::ui::{diagnostics, donut, recipients, reply_balance};
 use egui::Ui;
 use tokio::sync::mpsc::UnboundedSender;
 
-/// Maximum senders shown in the donut chart before grouping the rest.
-const DONUT_MAX_SLICES: usize = 20;
+/// Depth offered instead of a full scan when a folder is very large.
+const LARGE_FOLDER_SCAN_DEPTH: u32 = 50_000;
 
-/// Maximum senders shown in the interactive kill list.
-const KILL_LIST_LIMIT: usize = 100;
+/// Shown wherever a partial scan's counts could be mistaken for what a
+/// delete will remove.
+const SCOPE_WARNING: &str =
+    "Deleting searches the whole folder, so it can remove more than the counts shown.";
 
 pub fn draw_dashboard(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>) {
     let busy = state.phase == AppPhase::Scanning || state.phase == AppPhase::Deleting;
 
+    draw_delete_confirmation(ui.ctx(), state, cmd_tx);
+    chart_export::draw_chart_export(ui.ctx(), state);
+
     // Error display
     if let Some(err) = &state.error_message {
         ui.colored_label(egui::Color32::RED, format!("Error: {err}"));
         ui.add_space(4.0);
     }
 
-    // Progress display
-    match state.phase {
-        AppPhase::Scanning => {
-            ui.heading("Scanning...");
-            ui.add(egui::ProgressBar::new(state.scan_progress).text(&state.scan_status));
-            ui.add_space(8.0);
-        }
-        AppPhase::Deleting => {
-            ui.heading("Deleting...");
-            ui.add(egui::ProgressBar::new(state.delete_progress).text(&state.delete_status));
-            ui.add_space(8.0);
-        }
-        _ => {}
+    if state.interrupted_delete.is_some() && !state.analyze_only {
+        draw_resume_prompt(ui, state, cmd_tx, busy);
+        ui.add_space(4.0);
+    }
+
+    if let Some(messages) = state.large_folder {
+        draw_large_folder_prompt(ui, state, cmd_tx, messages);
+        ui.add_space(4.0);
+    }
+
+    if !state.delete_failures.is_empty() && state.phase != AppPhase::Deleting {
+        draw_delete_failures(ui, state, cmd_tx, busy);
+        ui.add_space(4.0);
+    }
+
+    draw_progress(ui, state);
+
+    if let Some(scan_diagnostics) = &state.scan_diagnostics {
+        diagnostics::draw_diagnostics(ui, scan_diagnostics);
+        ui.add_space(4.0);
     }
 
     if state.senders.is_empty() && state.phase == AppPhase::Idle {
@@ -45,7 +69,82 @@ pub fn draw_dashboard(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSende
         return;
     }
 
-    // Metrics row
+    if let Some(file) = &state.loaded_scan {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!("Loaded from {file} (read-only until you connect)"),
+        );
+    }
+
+    if state.failed_batches > 0 && state.phase != AppPhase::Scanning {
+        ui.colored_label(
+            egui::Color32::ORANGE,
+            format!(
+                "Partial scan: {} batches failed, so counts may be low.",
+                state.failed_batches
+            ),
+        );
+        ui.add_space(4.0);
+    }
+
+    if let Some(reason) = &state.correspondents_unchecked {
+        ui.colored_label(
+            egui::Color32::ORANGE,
+            format!("Correspondents weren't checked ({reason}), so nobody is protected as one."),
+        );
+        ui.add_space(4.0);
+    }
+
+    draw_metrics(ui, state);
+
+    ui.add_space(8.0);
+    ui.separator();
+    ui.add_space(4.0);
+
+    // Two-column layout: donut + kill list
+    ui.columns(2, |columns| {
+        // Left: Donut chart
+        columns[0].horizontal(|ui| {
+            ui.heading("Inbox Composition");
+            if ui
+                .add_enabled(state.chart_export.is_none(), egui::Button::new("Export PNG"))
+                .on_hover_text("Save the chart with a legend as an image")
+                .clicked()
+            {
+                state.chart_export = Some(ChartExport::default());
+            }
+        });
+        columns[0].add_space(4.0);
+        donut::draw_donut(
+            &mut columns[0],
+            &state.senders,
+            state.display_limits.donut_slices,
+            &state.sender_selected,
+            &mut state.donut_cache,
+            300.0,
+        );
+
+        // Right: Kill list
+        columns[1].heading("Kill List");
+        columns[1].add_space(4.0);
+        draw_kill_list(&mut columns[1], state, cmd_tx, busy);
+    });
+
+    ui.add_space(8.0);
+    ui.separator();
+    ui.add_space(4.0);
+
+    // Raw data table
+    draw_raw_table(ui, state);
+
+    reply_balance::draw_reply_balance(ui, state);
+    recipients::draw_recipients(ui, state);
+}
+
+/// Spinner and progress bar for a running scan or delete. The spinner keeps
+/// requesting repaints, so it animates even while no progress events arrive
+/// (e.g. during the initial UID search).
+fn draw_metrics(ui: &mut Ui, state: &AppState) {
     ui.horizontal(|ui| {
         let frame = egui::Frame::default()
             .inner_margin(8.0)
@@ -59,6 +158,22 @@ pub fn draw_dashboard(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSende
             });
         });
 
+        let unparsed = state.scan_diagnostics.as_ref().map_or(0, |d| d.unparsed);
+        if unparsed > 0 {
+            frame
+                .show(ui, |ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Unparsed");
+                        ui.heading(unparsed.to_string());
+                    });
+                })
+                .response
+                .on_hover_text(
+                    "Messages left out because their From header couldn't be read. \
+                     Sample headers are listed under Diagnostics.",
+                );
+        }
+
         frame.show(ui, |ui| {
             ui.vertical(|ui| {
                 ui.label("Unique Senders");
@@ -73,30 +188,68 @@ pub fn draw_dashboard(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSende
             });
         });
     });
+}
 
-    ui.add_space(8.0);
-    ui.separator();
-    ui.add_space(4.0);
+fn draw_progress(ui: &mut Ui, state: &AppState) {
+    match state.phase {
+        AppPhase::Scanning => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.heading("Scanning...");
+            });
+            ui.add(egui::ProgressBar::new(state.shown_scan_progress).text(&state.scan_status));
+            ui.add_space(8.0);
+        }
+        AppPhase::Deleting => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.heading("Deleting...");
+            });
+            ui.add(egui::ProgressBar::new(state.delete_progress).text(&state.delete_status));
+            if let Some(sender_progress) = state.delete_sender_progress {
+                ui.add(egui::ProgressBar::new(sender_progress).desired_height(4.0));
+            }
+            ui.weak(if state.delete_skipped.is_some() {
+                "Uncheck a sender in the kill list to skip it if it hasn't been reached yet."
+            } else {
+                "Senders can't be skipped while they are deleted in one combined search."
+            });
+            ui.add_space(8.0);
+        }
+        _ => {}
+    }
+}
 
-    // Two-column layout: donut + kill list
-    ui.columns(2, |columns| {
-        // Left: Donut chart
-        columns[0].heading("Inbox Composition");
-        columns[0].add_space(4.0);
-        donut::draw_donut(&mut columns[0], &state.senders, DONUT_MAX_SLICES);
+/// Selection summary, bulk selection buttons and presets above the kill
+/// list.
+fn draw_selection_controls(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    draw_selection_header(ui, state);
+    if let Some(scope) = state.limited_scope.as_ref().filter(|_| searches_whole_folder(state)) {
+        ui.colored_label(egui::Color32::ORANGE, format!("⚠ {scope}"))
+            .on_hover_text(SCOPE_WARNING);
+    }
 
-        // Right: Kill list
-        columns[1].heading("Kill List");
-        columns[1].add_space(4.0);
-        draw_kill_list(&mut columns[1], state, cmd_tx, busy);
+    ui.horizontal(|ui| {
+        if ui.add_enabled(!busy, egui::Button::new("Select all")).clicked() {
+            state.select_all();
+        }
+        if ui.add_enabled(!busy, egui::Button::new("Clear")).clicked() {
+            state.clear_selection();
+        }
+        ui.separator();
+        if ui
+            .add_enabled(!busy, egui::Button::new("Uncheck below"))
+            .on_hover_text("Deselects every sender with fewer emails than this")
+            .clicked()
+        {
+            state.deselect_below(state.min_selected_count);
+        }
+        ui.add(egui::DragValue::new(&mut state.min_selected_count).range(1..=100_000));
+        ui.separator();
+        ui.checkbox(&mut state.group_kill_list, "Group by domain")
+            .on_hover_text("One row per domain; expand a row to pick single senders");
     });
-
-    ui.add_space(8.0);
-    ui.separator();
-    ui.add_space(4.0);
-
-    // Raw data table
-    draw_raw_table(ui, state);
+    draw_preset_selectors(ui, state, busy);
 }
 
 fn draw_kill_list(
@@ -105,89 +258,1142 @@ fn draw_kill_list(
     cmd_tx: &UnboundedSender<UiCommand>,
     busy: bool,
 ) {
-    let top_senders: Vec<(String, usize)> = state
-        .senders
-        .iter()
-        .take(KILL_LIST_LIMIT)
-        .map(|s| (s.email.clone(), s.count))
-        .collect();
+    draw_selection_controls(ui, state, busy);
 
+    // Applied after the loop, which holds a borrow of the sender list.
+    let mut action = None;
+    let rows = kill_list_rows(state);
+    // Only the visible rows are laid out, so a high cap stays cheap.
+    let shown = rows.len().min(state.kill_list_limit);
+    let mut focus = state.kill_list_focus;
+    let (moved, toggle) = kill_list_keys(ui, &mut focus, shown);
+    let row_height = ui.spacing().interact_size.y;
+    let pitch = row_height + ui.spacing().item_spacing.y;
     egui::ScrollArea::vertical()
         .max_height(250.0)
-        .show(ui, |ui| {
-            for (email, count) in &top_senders {
-                let checked = state.sender_selected.entry(email.clone()).or_insert(false);
-                ui.horizontal(|ui| {
-                    ui.checkbox(checked, "");
-                    ui.label(format!("{email} ({count})"));
+        .show_rows(ui, row_height, shown, |ui, range| {
+            if let Some(focus) = focus.filter(|_| moved) {
+                // The focused row may be outside the laid-out range, so
+                // work out where it would be.
+                let offset = (focus as f32 - range.start as f32) * pitch;
+                let top_left = ui.cursor().left_top() + egui::vec2(0.0, offset);
+                let size = egui::vec2(ui.available_width(), row_height);
+                ui.scroll_to_rect(egui::Rect::from_min_size(top_left, size), None);
+            }
+            for (i, row) in range.clone().zip(&rows[range]) {
+                let focused = focus == Some(i);
+                let toggle = focused && toggle;
+                let drawn = ui.scope(|ui| match row {
+                    KillRow::Group { domain, members } => {
+                        draw_group_row(ui, state, domain, members, toggle)
+                    }
+                    KillRow::Sender { index, nested } => {
+                        draw_sender_row(ui, state, &state.senders[*index], *nested, busy, toggle)
+                    }
                 });
+                if focused {
+                    let stroke = ui.visuals().selection.stroke;
+                    let rect = drawn.response.rect;
+                    ui.painter().rect_stroke(rect, 2.0, stroke, egui::StrokeKind::Outside);
+                }
+                if drawn.inner.is_some() {
+                    action = drawn.inner;
+                }
             }
         });
+    let total_rows = rows.len();
+    state.kill_list_focus = focus;
+    if let Some(action) = action {
+        apply_row_action(state, cmd_tx, action);
+    }
+    draw_expanded_addresses(ui, state);
 
-    let selected_count = state.selected_email_count();
-    if selected_count > 0 {
+    if shown < total_rows {
+        ui.horizontal(|ui| {
+            let noun = if state.group_kill_list { "rows" } else { "senders" };
+            ui.label(format!("Showing {shown} of {total_rows} {noun}"));
+            if ui.button("Show more").clicked() {
+                state.kill_list_limit += state.display_limits.kill_list_rows;
+            }
+        });
+    }
+
+    if state.selected_email_count() > 0 {
         ui.add_space(4.0);
-        ui.colored_label(
-            egui::Color32::YELLOW,
-            format!("~{selected_count} emails selected for removal"),
+        draw_selection_actions(ui, state, cmd_tx, busy);
+    }
+}
+
+/// Arrow keys move the kill list's focus and Space asks for the focused
+/// row to be toggled. Only read while no other widget has keyboard focus,
+/// so typing and tabbing elsewhere are unaffected. Returns whether the
+/// focus moved and whether to toggle.
+fn kill_list_keys(ui: &Ui, focus: &mut Option<usize>, rows: usize) -> (bool, bool) {
+    if rows == 0 {
+        *focus = None;
+        return (false, false);
+    }
+    if ui.memory(|memory| memory.focused().is_some()) {
+        return (false, false);
+    }
+    let (down, up, space) = ui.input_mut(|input| {
+        (
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::Space),
+        )
+    });
+    let before = *focus;
+    let current = focus.map(|row| row.min(rows - 1));
+    *focus = match (down, up, current) {
+        (true, false, Some(row)) => Some((row + 1).min(rows - 1)),
+        (false, true, Some(row)) => Some(row.saturating_sub(1)),
+        (true, false, None) | (false, true, None) => Some(0),
+        _ => current,
+    };
+    (*focus != before, space && focus.is_some())
+}
+
+/// Why Rescan is off: it counts with a From search.
+const RESCAN_NEEDS_FROM: &str =
+    "Senders were identified by Sender or List-Id, which a From search can't count";
+
+/// Hover text on kill-list checkboxes frozen by a combined delete.
+const COMBINED_DELETE_LOCK: &str = "A combined delete is running and can't skip senders";
+
+/// A line in the kill list: a sender, or the header of a domain with
+/// several senders when the list is grouped.
+enum KillRow<'a> {
+    Group {
+        domain: &'a str,
+        /// Indices into `state.senders`.
+        members: Vec<usize>,
+    },
+    Sender {
+        index: usize,
+        /// Listed under an expanded domain header.
+        nested: bool,
+    },
+}
+
+/// The kill list's lines, in sender order. Grouped, a domain's senders sit
+/// under its header, placed where its biggest sender would be, and only
+/// show once it is expanded.
+fn kill_list_rows(state: &AppState) -> Vec<KillRow<'_>> {
+    if !state.group_kill_list {
+        return (0..state.senders.len())
+            .map(|index| KillRow::Sender { index, nested: false })
+            .collect();
+    }
+    let mut groups: Vec<(&str, Vec<usize>)> = Vec::new();
+    let mut by_domain: HashMap<&str, usize> = HashMap::new();
+    for (index, sender) in state.senders.iter().enumerate() {
+        let domain = sender.email.rsplit_once('@').map_or(sender.email.as_str(), |(_, d)| d);
+        let group = *by_domain.entry(domain).or_insert_with(|| {
+            groups.push((domain, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(index);
+    }
+
+    let mut rows = Vec::with_capacity(state.senders.len());
+    for (domain, members) in groups {
+        if let [index] = members[..] {
+            rows.push(KillRow::Sender { index, nested: false });
+            continue;
+        }
+        let expanded = state.expanded_domains.contains(domain);
+        let shown = if expanded { members.clone() } else { Vec::new() };
+        rows.push(KillRow::Group { domain, members });
+        rows.extend(shown.into_iter().map(|index| KillRow::Sender { index, nested: true }));
+    }
+    rows
+}
+
+/// Header for a domain's senders. The checkbox is worked out from its
+/// members every frame: checked when every selectable one is, mixed when
+/// only some are.
+fn draw_group_row(
+    ui: &mut Ui,
+    state: &AppState,
+    domain: &str,
+    members: &[usize],
+    toggle: bool,
+) -> Option<RowAction> {
+    let senders: Vec<&SenderInfo> = members.iter().map(|&i| &state.senders[i]).collect();
+    let selectable = state.group_selectable(members);
+    let selected = selectable
+        .iter()
+        .filter(|email| state.sender_selected.get(*email).copied().unwrap_or(false))
+        .count();
+    let mut all = !selectable.is_empty() && selected == selectable.len();
+    let partial = selected > 0 && !all;
+    let emails: usize = senders.iter().map(|sender| sender.count).sum();
+    let expanded = state.expanded_domains.contains(domain);
+
+    let locked = state.selection_locked();
+    let mut action = None;
+    if toggle && !locked && !selectable.is_empty() {
+        action = Some(RowAction::ToggleGroup(selectable.clone(), !all));
+    }
+    ui.horizontal(|ui| {
+        let checkbox = egui::Checkbox::without_text(&mut all).indeterminate(partial);
+        let hint = if locked {
+            COMBINED_DELETE_LOCK
+        } else {
+            "Every sender here is protected or someone you've emailed"
+        };
+        if ui
+            .add_enabled(!locked && !selectable.is_empty(), checkbox)
+            .on_disabled_hover_text(hint)
+            .changed()
+        {
+            action = Some(RowAction::ToggleGroup(selectable.clone(), all));
+        }
+        let arrow = if expanded { "▾" } else { "▸" };
+        let label = format!("{arrow} {domain}: {} senders ({emails})", senders.len());
+        if ui.add(egui::Button::new(label).frame(false)).clicked() {
+            action = Some(RowAction::ExpandDomain(domain.to_string()));
+        }
+    });
+    action
+}
+
+/// One sender's checkbox, warnings and name, with a context menu for
+/// rescanning and protecting it.
+fn draw_sender_row(
+    ui: &mut Ui,
+    state: &AppState,
+    sender: &SenderInfo,
+    nested: bool,
+    busy: bool,
+    toggle: bool,
+) -> Option<RowAction> {
+    let protected = state.is_protected(sender);
+    let domain = state.protecting_domain(sender);
+    let mut checked = state.sender_selected.get(&sender.email).copied().unwrap_or(false);
+    let mut action = None;
+    let locked = state.selection_locked();
+    if toggle && !protected && !locked {
+        action = Some(RowAction::Toggled(sender.email.clone(), !checked));
+    }
+    ui.horizontal(|ui| {
+        if nested {
+            ui.add_space(ui.spacing().indent);
+        }
+        if let Some(domain) = &domain {
+            ui.add_enabled(false, egui::Checkbox::without_text(&mut false));
+            ui.label("🛡").on_hover_text(format!(
+                "Protected domain {domain}. Edit the list under Protected domains."
+            ));
+        } else if protected {
+            ui.add_enabled(false, egui::Checkbox::without_text(&mut false));
+            ui.label("🔒").on_hover_text("Protected. Right-click to unprotect.");
+        } else if ui
+            .add_enabled(!locked, egui::Checkbox::without_text(&mut checked))
+            .on_disabled_hover_text(COMBINED_DELETE_LOCK)
+            .changed()
+        {
+            action = Some(RowAction::Toggled(sender.email.clone(), checked));
+        }
+        if sender.sent_to > 0 {
+            ui.colored_label(egui::Color32::ORANGE, "⚠").on_hover_text(format!(
+                "You've sent {} emails to this address. \
+                 Excluded from Select all.",
+                sender.sent_to
+            ));
+        }
+        if state.blocklisted.contains(&sender.email) {
+            ui.colored_label(egui::Color32::RED, "⛔").on_hover_text("On your blocklist");
+        }
+        if let Some(note) = state.note_for(sender) {
+            ui.label("📝").on_hover_text(note);
+        }
+        if sender.addresses.len() > 1 {
+            let expanded = state.expanded_sender.as_ref() == Some(&sender.email);
+            let arrow = if expanded { "▾" } else { "▸" };
+            if ui
+                .small_button(format!("{arrow} {}", sender.addresses.len()))
+                .on_hover_text("Show the addresses merged into this sender")
+                .clicked()
+            {
+                action = Some(RowAction::ExpandSender((!expanded).then(|| sender.email.clone())));
+            }
+        }
+        let rescanning = state.rescanning.contains(&sender.email);
+        let by_from = state.scanned_headers.is_from_only();
+        ui.label(format!("{} ({})", sender.display, sender.count))
+            .context_menu(|ui| {
+                let rescan = ui
+                    .add_enabled(!busy && !rescanning && by_from, egui::Button::new("Rescan"))
+                    .on_hover_text("Count this sender's messages in the folder again");
+                let rescan = if by_from {
+                    rescan
+                } else {
+                    rescan.on_disabled_hover_text(RESCAN_NEEDS_FROM)
+                };
+                if rescan.clicked() {
+                    action = Some(RowAction::Rescan(sender.clone()));
+                    ui.close_menu();
+                }
+                ui.separator();
+                if let Some(domain) = &domain {
+                    ui.label(format!("Protected by domain {domain}"));
+                    return;
+                }
+                let (label, protect) = if protected {
+                    ("Unprotect sender", RowAction::Unprotect(sender.clone()))
+                } else {
+                    ("Protect sender", RowAction::Protect(sender.clone()))
+                };
+                if ui.button(label).clicked() {
+                    action = Some(protect);
+                    ui.close_menu();
+                }
+            });
+        if rescanning {
+            ui.spinner();
+        }
+    });
+    action
+}
+
+fn apply_row_action(
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    action: RowAction,
+) {
+    match action {
+        RowAction::Toggled(email, checked) => {
+            if let Some(skipped) = &state.delete_skipped {
+                skip_during_delete(skipped, email.clone(), checked);
+            }
+            state.sender_selected.insert(email, checked);
+        }
+        RowAction::ToggleGroup(emails, checked) => {
+            for email in emails {
+                if let Some(skipped) = &state.delete_skipped {
+                    skip_during_delete(skipped, email.clone(), checked);
+                }
+                state.sender_selected.insert(email, checked);
+            }
+        }
+        RowAction::ExpandSender(email) => state.expanded_sender = email,
+        RowAction::ExpandDomain(domain) => {
+            if !state.expanded_domains.remove(&domain) {
+                state.expanded_domains.insert(domain);
+            }
+        }
+        RowAction::Protect(sender) => state.protect(&sender),
+        RowAction::Unprotect(sender) => state.unprotect(&sender),
+        RowAction::Rescan(sender) => rescan_sender(state, cmd_tx, &sender),
+    }
+}
+
+/// One-click selections of senders that look automated, by local part.
+fn draw_preset_selectors(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(!busy, egui::Button::new("Select no-reply"))
+            .on_hover_text("Checks no-reply@ and noreply@ senders. Protected senders are skipped")
+            .clicked()
+        {
+            if let Ok(pattern) = Regex::new(NO_REPLY_PATTERN) {
+                state.select_matching(&pattern);
+            }
+        }
+        let pattern = Regex::new(state.automated_pattern.trim());
+        if ui
+            .add_enabled(!busy && pattern.is_ok(), egui::Button::new("Select matching"))
+            .on_hover_text("Checks senders whose local part matches the pattern")
+            .clicked()
+        {
+            if let Ok(pattern) = &pattern {
+                state.select_matching(pattern);
+            }
+        }
+        ui.add_enabled(
+            !busy,
+            egui::TextEdit::singleline(&mut state.automated_pattern)
+                .hint_text("local-part regex")
+                .font(egui::TextStyle::Monospace),
         );
+        if let Err(e) = pattern {
+            ui.colored_label(egui::Color32::RED, "⚠")
+                .on_hover_text(format!("Invalid pattern: {e}"));
+        }
+    });
+}
 
+/// Export, block and delete buttons for the current selection.
+fn draw_selection_actions(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    // Results loaded from a file can be reviewed, but acting on them needs
+    // a login.
+    let online = state.loaded_scan.is_none() || state.has_credentials();
+    ui.horizontal(|ui| {
+        ui.menu_button("Export filters", |ui| {
+            for format in FilterFormat::ALL {
+                if ui.button(format.label()).clicked() {
+                    ui.close_menu();
+                    export_filters(state, format);
+                }
+            }
+        });
+        let uploading = state.block_status == Some(BlockStatus::Uploading);
+        let can_block = !uploading && online && !state.analyze_only;
         if ui
-            .add_enabled(!busy, egui::Button::new("EXECUTE"))
+            .add_enabled(can_block, egui::Button::new("Block on server"))
+            .on_hover_text(
+                "Optional: adds a Sieve rule that discards future mail from these senders. \
+                 Needs a server with ManageSieve.",
+            )
             .clicked()
         {
-            let selected: Vec<String> = state
-                .senders
-                .iter()
-                .filter(|s| state.sender_selected.get(&s.email).copied().unwrap_or(false))
-                .map(|s| s.email.clone())
-                .collect();
+            block_on_server(state, cmd_tx);
+        }
+    });
+    draw_block_status(ui, state);
+
+    if state.folder_read_only {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!(
+                "{} is read-only on the server, so nothing can be deleted from it. \
+                 Pick another folder or check the account's permissions.",
+                state.folder
+            ),
+        );
+    }
+    if state.analyze_only {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            "Analyze-only mode: turn it off in the sidebar to delete or block.",
+        );
+        return;
+    }
+    if !online {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            "Enter credentials for this account to delete or block.",
+        );
+    }
+    if ui
+        .add_enabled(
+            !busy && online && !state.folder_read_only,
+            egui::Button::new("EXECUTE"),
+        )
+        .clicked()
+    {
+        let selected = state.selected_senders().into_iter().cloned().collect();
+        preview_delete(state, cmd_tx, selected);
+    }
+}
+
+/// Lists the raw addresses behind the expanded merged sender. Deleting the
+/// sender searches for all of them.
+fn draw_expanded_addresses(ui: &mut Ui, state: &AppState) {
+    let Some(sender) = state
+        .expanded_sender
+        .as_ref()
+        .and_then(|email| state.senders.iter().find(|s| &s.email == email))
+    else {
+        return;
+    };
+    egui::Frame::default()
+        .inner_margin(4.0)
+        .fill(ui.visuals().faint_bg_color)
+        .show(ui, |ui| {
+            ui.label(format!("{} covers:", sender.display));
+            egui::ScrollArea::vertical()
+                .id_salt("expanded_addresses")
+                .max_height(120.0)
+                .show(ui, |ui| {
+                    for address in &sender.addresses {
+                        ui.monospace(address);
+                    }
+                });
+        });
+}
+
+/// What a click in the kill list asks for, applied once the list is drawn.
+enum RowAction {
+    /// A sender's checkbox changed to the given state.
+    Toggled(String, bool),
+    /// A domain header's checkbox changed; covers its selectable senders.
+    ToggleGroup(Vec<String>, bool),
+    /// Show the addresses merged into this sender, or `None` to hide them.
+    ExpandSender(Option<String>),
+    /// Show or hide a domain's senders.
+    ExpandDomain(String),
+    Protect(SenderInfo),
+    Unprotect(SenderInfo),
+    Rescan(SenderInfo),
+}
+
+/// Running total of what the current selection would remove, kept above the
+/// scrolling list so it stays visible.
+fn draw_selection_header(ui: &mut Ui, state: &AppState) {
+    let selected_count = state.selected_email_count();
+    let senders = state.selected_senders().len();
+    let total = state.total_emails.max(state.total_scanned());
+    let percent = if total == 0 {
+        0.0
+    } else {
+        selected_count as f64 / total as f64 * 100.0
+    };
+    let color = if selected_count > 0 {
+        ui.visuals().warn_fg_color
+    } else {
+        ui.visuals().weak_text_color()
+    };
+    ui.colored_label(
+        color,
+        format!(
+            "~{selected_count} emails from {senders} senders selected \
+             ({percent:.1}% of folder)"
+        ),
+    );
+}
+
+/// Passes a checkbox change on to the running delete, so a sender unchecked
+/// before the delete reaches it is left alone.
+fn skip_during_delete(skipped: &SkippedSenders, email: String, checked: bool) {
+    let Ok(mut skipped) = skipped.lock() else {
+        return;
+    };
+    if checked {
+        skipped.remove(&email);
+    } else {
+        skipped.insert(email);
+    }
+}
+
+/// Asks for a live count of one sender's messages in the folder.
+fn rescan_sender(state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>, sender: &SenderInfo) {
+    let Some(request) = state.rescan_request(sender) else {
+        return;
+    };
+    if let Err(e) = cmd_tx.send(UiCommand::RescanSender(request)) {
+        tracing::warn!(error = %e, "failed to send rescan command");
+        return;
+    }
+    state.rescanning.insert(sender.email.clone());
+}
+
+/// Sends the selected senders' addresses to the server-side blocklist.
+fn block_on_server(state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>) {
+    let addresses = state
+        .selected_senders()
+        .iter()
+        .flat_map(|s| s.addresses.iter().cloned())
+        .collect();
+    let request = BlockRequest {
+        credentials: state.credentials(),
+        addresses,
+        connection: state.connection_options(),
+    };
+    if let Err(e) = cmd_tx.send(UiCommand::BlockSenders(request)) {
+        tracing::warn!(error = %e, "failed to send block command");
+        return;
+    }
+    state.block_status = Some(BlockStatus::Uploading);
+}
+
+fn draw_block_status(ui: &mut Ui, state: &AppState) {
+    match &state.block_status {
+        None => {}
+        Some(BlockStatus::Uploading) => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Updating server filter...");
+            });
+        }
+        Some(BlockStatus::Done(message)) => {
+            ui.label(message);
+        }
+        Some(BlockStatus::Failed(err)) => {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+    }
+}
+
+fn export_filters(state: &mut AppState, format: FilterFormat) {
+    let senders: Vec<&str> = state
+        .selected_senders()
+        .iter()
+        .map(|s| s.email.as_str())
+        .collect();
+    match export::save_filters(format, &senders) {
+        Ok(Some(path)) => {
+            tracing::info!(path = %path.display(), count = senders.len(), "exported filters");
+        }
+        Ok(None) => {}
+        Err(e) => state.error_message = Some(e.to_string()),
+    }
+}
+
+/// Whether a delete can reach messages the scan never counted.
+fn searches_whole_folder(state: &AppState) -> bool {
+    state.delete_scope == DeleteScope::WholeFolder
+}
+
+fn delete_request(state: &AppState, senders: Vec<SenderInfo>) -> DeleteRequest {
+    DeleteRequest {
+        credentials: state.credentials(),
+        folder: state.folder.clone(),
+        targets: senders.iter().map(|sender| state.delete_target(sender)).collect(),
+        senders,
+        mode: state.delete_mode,
+        trash_folder: state.trash_folder(),
+        keep_newest: state.keep_newest,
+        pause: Duration::from_millis(state.delete_pause_ms),
+        chunk_size: state.delete_chunk_size(),
+        skipped: SkippedSenders::default(),
+        combined: state.combine_delete && state.keep_newest == 0,
+        uid_validity: state.uid_validity,
+        connection: state.connection_options(),
+    }
+}
+
+/// Opens the confirmation dialog and asks the server how many messages
+/// each sender currently has.
+fn preview_delete(
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    senders: Vec<SenderInfo>,
+) {
+    if state.analyze_only {
+        return;
+    }
+    if let Err(e) = cmd_tx.send(UiCommand::PreviewDelete(delete_request(state, senders.clone()))) {
+        tracing::warn!(error = %e, "failed to send delete preview command");
+    }
+    state.delete_preview = Some(DeletePreview {
+        senders,
+        live_counts: None,
+        missing_trash: None,
+        scope_acknowledged: false,
+    });
+}
+
+/// Switches to the deleting phase and hands `senders` to the background worker.
+fn start_delete(
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    senders: Vec<SenderInfo>,
+) {
+    let request = delete_request(state, senders);
+    send_delete(state, cmd_tx, request);
+}
+
+/// Every delete goes through here, so analyze-only mode is enforced once.
+fn send_delete(state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>, request: DeleteRequest) {
+    if state.analyze_only {
+        tracing::warn!("delete refused in analyze-only mode");
+        return;
+    }
+    state.phase = AppPhase::Deleting;
+    state.delete_progress = 0.0;
+    state.delete_status = "Starting deletion...".to_string();
+    state.delete_sender_progress = None;
+    state.error_message = None;
+    state.delete_failures.clear();
+    // A combined run searches for everyone at once and can't skip anyone.
+    state.delete_skipped = (!request.combined).then(|| request.skipped.clone());
+
+    if let Err(e) = cmd_tx.send(UiCommand::StartDelete(request)) {
+        tracing::warn!(error = %e, "failed to send delete command");
+    }
+}
+
+/// Offers to finish a delete that an earlier session didn't complete. The
+/// journal has no password, so resuming waits for the same account to be
+/// entered in the sidebar.
+fn draw_resume_prompt(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    let Some(journal) = &state.interrupted_delete else {
+        return;
+    };
+    ui.colored_label(
+        egui::Color32::ORANGE,
+        format!(
+            "A previous delete was interrupted: {} senders left in {} ({}).",
+            journal.remaining.len(),
+            journal.folder,
+            journal.email
+        ),
+    );
+    let same_account = journal.email.eq_ignore_ascii_case(state.email.trim());
+    let can_resume = !busy && same_account && !state.password.is_empty();
+    let mut resume = false;
+    let mut discard = false;
+    ui.horizontal(|ui| {
+        resume = ui
+            .add_enabled(can_resume, egui::Button::new("Resume"))
+            .on_disabled_hover_text("Enter the password for this account first")
+            .clicked();
+        discard = ui.add_enabled(!busy, egui::Button::new("Discard")).clicked();
+    });
+
+    if resume {
+        if let Some(journal) = state.interrupted_delete.take() {
+            let (senders, targets) = journal.senders_and_targets();
+            let request = DeleteRequest {
+                credentials: state.credentials(),
+                folder: journal.folder,
+                senders,
+                targets,
+                mode: journal.mode,
+                trash_folder: journal.trash_folder,
+                keep_newest: journal.keep_newest,
+                pause: Duration::from_millis(state.delete_pause_ms),
+                chunk_size: state.delete_chunk_size(),
+                skipped: SkippedSenders::default(),
+                combined: false,
+                uid_validity: journal.uid_validity,
+                connection: state.connection_options(),
+            };
+            send_delete(state, cmd_tx, request);
+        }
+    } else if discard {
+        state.interrupted_delete = None;
+        DeleteJournal::clear();
+    }
+}
 
-            state.phase = AppPhase::Deleting;
-            state.delete_progress = 0.0;
-            state.delete_status = "Starting deletion...".to_string();
-            state.error_message = None;
+fn draw_delete_confirmation(
+    ctx: &egui::Context,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+) {
+    let Some(preview) = &state.delete_preview else {
+        return;
+    };
+    let mut confirmed = false;
+    let mut cancelled = false;
+    let mut keep = state.keep_newest;
+    let mut acknowledged = preview.scope_acknowledged;
+    let permanent = state.delete_mode == DeleteMode::Permanent;
+    let unseen_scope = state.limited_scope.as_ref().filter(|_| searches_whole_folder(state));
 
-            if let Err(e) = cmd_tx.send(UiCommand::StartDelete {
-                email: state.email.clone(),
-                password: state.password.clone(),
-                folder: state.folder.clone(),
-                senders: selected,
-                mode: state.delete_mode.clone(),
-            }) {
-                tracing::warn!(error = %e, "failed to send delete command");
+    let modal = egui::Modal::new(egui::Id::new("delete_confirmation")).show(ctx, |ui| {
+        ui.heading("Confirm deletion");
+        ui.add_space(4.0);
+        match unseen_scope {
+            Some(scope) if permanent => draw_scope_interlock(ui, scope, &mut acknowledged),
+            Some(scope) => {
+                ui.colored_label(egui::Color32::ORANGE, format!("{scope} {SCOPE_WARNING}"));
+                ui.add_space(4.0);
             }
+            None => {}
+        }
+        if permanent {
+            ui.colored_label(egui::Color32::RED, "Permanent deletion cannot be undone.");
+        }
+        let live_counts = preview
+            .live_counts
+            .as_ref()
+            .and_then(|r| r.as_ref().ok())
+            .map(Vec::as_slice);
+        draw_sender_summary(ui, &preview.senders, live_counts);
+        ui.horizontal(|ui| {
+            ui.label("Keep the newest");
+            ui.add(egui::DragValue::new(&mut keep).range(0..=10_000));
+            ui.label("per sender");
+        })
+        .response
+        .on_hover_text("0 removes everything. Otherwise the most recent messages by date stay.");
+        match &preview.live_counts {
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Counting matching messages on the server...");
+                });
+            }
+            Some(Err(err)) => {
+                ui.colored_label(egui::Color32::RED, format!("Couldn't get live counts: {err}"));
+                let scanned: usize = preview.senders.iter().map(|s| s.count).sum();
+                ui.label(format!("The scan found ~{} emails.", thousands(scanned)));
+            }
+            Some(Ok(counts)) => {
+                let total: usize = counts.iter().map(|count| count.saturating_sub(keep)).sum();
+                let kept = if keep > 0 {
+                    format!(", keeping the newest {keep} from each sender")
+                } else {
+                    String::new()
+                };
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} emails will be removed{kept}.", thousands(total)),
+                );
+            }
+        }
+        if let Some(trash) = &preview.missing_trash {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                format!("⚠ Trash folder '{trash}' not found. Check the Trash folder setting."),
+            );
         }
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            let action = state.delete_mode.action_label();
+            let ready = preview.live_counts.is_some()
+                && (acknowledged || !permanent || unseen_scope.is_none());
+            if ui.add_enabled(ready, egui::Button::new(action)).clicked() {
+                confirmed = true;
+            }
+            if ui.button("Cancel").clicked() {
+                cancelled = true;
+            }
+            if ui.button("Copy list").clicked() {
+                ui.ctx().copy_text(sender_list_text(&preview.senders, live_counts));
+            }
+        });
+    });
+    state.keep_newest = keep;
+    if let Some(preview) = &mut state.delete_preview {
+        preview.scope_acknowledged = acknowledged;
+    }
+
+    if confirmed {
+        if let Some(preview) = state.delete_preview.take() {
+            start_delete(state, cmd_tx, preview.senders);
+        }
+    } else if cancelled || modal.should_close() {
+        state.delete_preview = None;
+    }
+}
+
+/// The loudest warning the app has: a permanent, folder-wide delete after a
+/// scan that only covered part of the folder. Nothing it removes can be
+/// recovered, and the counts shown may be far below what it matches.
+fn draw_scope_interlock(ui: &mut Ui, scope: &str, acknowledged: &mut bool) {
+    egui::Frame::new()
+        .fill(egui::Color32::from_rgb(90, 0, 0))
+        .stroke(egui::Stroke::new(2.0, egui::Color32::RED))
+        .inner_margin(8.0)
+        .corner_radius(4.0)
+        .show(ui, |ui| {
+            ui.label(
+                egui::RichText::new("⚠ Partial scan + permanent delete")
+                    .strong()
+                    .size(16.0)
+                    .color(egui::Color32::WHITE),
+            );
+            ui.label(
+                egui::RichText::new(format!(
+                    "{scope} This delete searches the whole folder by sender and expunges \
+                     every match, including messages the scan never counted. They cannot be \
+                     recovered."
+                ))
+                .color(egui::Color32::WHITE),
+            );
+            ui.checkbox(
+                acknowledged,
+                egui::RichText::new("I understand this may permanently delete more than shown")
+                    .color(egui::Color32::WHITE),
+            );
+        });
+    ui.add_space(4.0);
+}
+
+/// Every sender about to be purged, with the scan count and, once known,
+/// the live count from the server.
+fn draw_sender_summary(ui: &mut Ui, senders: &[SenderInfo], live: Option<&[usize]>) {
+    ui.label(format!("{} senders:", senders.len()));
+    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+        egui::Grid::new("live_counts").striped(true).show(ui, |ui| {
+            ui.strong("Sender");
+            ui.strong("Scanned");
+            ui.strong("Now");
+            ui.end_row();
+            for (i, sender) in senders.iter().enumerate() {
+                ui.label(&sender.display);
+                ui.label(thousands(sender.count));
+                match live.and_then(|counts| counts.get(i)) {
+                    Some(count) => ui.label(thousands(*count)),
+                    None => ui.weak("…"),
+                };
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Tab-separated sender list for the clipboard, using live counts when
+/// they have arrived.
+fn sender_list_text(senders: &[SenderInfo], live: Option<&[usize]>) -> String {
+    let mut text = String::new();
+    for (i, sender) in senders.iter().enumerate() {
+        let count = live.and_then(|counts| counts.get(i)).unwrap_or(&sender.count);
+        let _ = writeln!(text, "{}\t{count}", sender.display);
     }
+    text
+}
+
+fn draw_large_folder_prompt(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    messages: u32,
+) {
+    ui.colored_label(
+        ui.visuals().warn_fg_color,
+        format!(
+            "{} holds {} messages. A full scan may take a long time.",
+            state.folder,
+            thousands(messages as usize)
+        ),
+    );
+    ui.horizontal(|ui| {
+        let newest = format!("Scan newest {}", thousands(LARGE_FOLDER_SCAN_DEPTH as usize));
+        if ui.button(newest).clicked() {
+            state.scan_depth = LARGE_FOLDER_SCAN_DEPTH;
+            state.scan_direction = ScanDirection::Newest;
+            let _ = cmd_tx.send(UiCommand::StartScan(state.start_scan(false)));
+        }
+        if ui.button("Scan everything").clicked() {
+            let _ = cmd_tx.send(UiCommand::StartScan(state.start_scan(true)));
+        }
+    });
+}
+
+fn draw_delete_failures(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    let failed = state.delete_failures.len();
+    let noun = if failed == 1 { "sender" } else { "senders" };
+    ui.horizontal(|ui| {
+        ui.colored_label(egui::Color32::RED, format!("{failed} {noun} failed to delete"));
+        let can_retry = !busy && !state.analyze_only;
+        if ui.add_enabled(can_retry, egui::Button::new("Retry failed")).clicked() {
+            let retry = state
+                .senders
+                .iter()
+                .filter(|s| state.delete_failures.iter().any(|(failed, _)| *failed == s.email))
+                .cloned()
+                .collect();
+            start_delete(state, cmd_tx, retry);
+        }
+    });
+    egui::CollapsingHeader::new("Details")
+        .id_salt("delete_failures")
+        .show(ui, |ui| {
+            for (sender, error) in &state.delete_failures {
+                ui.label(format!("{sender}: {error}"));
+            }
+        });
 }
 
-fn draw_raw_table(ui: &mut Ui, state: &AppState) {
+fn draw_raw_table(ui: &mut Ui, state: &mut AppState) {
     ui.collapsing("Raw Data", |ui| {
-        egui_extras::TableBuilder::new(ui)
+        let rows = sorted_senders(&state.senders, state.sort_key, state.sort_ascending);
+        let (matches, scroll_to) =
+            draw_raw_search(ui, &mut state.raw_search, &mut state.raw_match, &rows);
+        let mut clicked_key = None;
+        let mut clicked_sender = None;
+        let mut edited_note = None;
+
+        let mut table = egui_extras::TableBuilder::new(ui);
+        if let Some(row) = scroll_to {
+            table = table.scroll_to_row(row, Some(egui::Align::Center));
+        }
+        table
             .striped(true)
             .resizable(true)
             .column(egui_extras::Column::remainder().at_least(200.0))
             .column(egui_extras::Column::initial(80.0))
+            .column(egui_extras::Column::initial(100.0))
+            .column(egui_extras::Column::initial(200.0))
             .header(20.0, |mut header| {
+                for (key, title) in [
+                    (SortKey::Sender, "Sender"),
+                    (SortKey::Count, "Count"),
+                    (SortKey::LastSeen, "Last seen"),
+                ] {
+                    header.col(|ui| {
+                        let label = if state.sort_key == key {
+                            let arrow = if state.sort_ascending { "⬆" } else { "⬇" };
+                            format!("{title} {arrow}")
+                        } else {
+                            title.to_string()
+                        };
+                        if ui
+                            .add(egui::Button::new(egui::RichText::new(label).strong()).frame(false))
+                            .clicked()
+                        {
+                            clicked_key = Some(key);
+                        }
+                    });
+                }
                 header.col(|ui| {
-                    ui.strong("Sender");
-                });
-                header.col(|ui| {
-                    ui.strong("Count");
+                    ui.strong("Note");
                 });
             })
             .body(|body| {
-                body.rows(18.0, state.senders.len(), |mut row| {
+                body.rows(18.0, rows.len(), |mut row| {
                     let idx = row.index();
-                    if let Some(sender) = state.senders.get(idx) {
+                    if let Some(sender) = rows.get(idx) {
+                        row.set_selected(matches.binary_search(&idx).is_ok());
                         row.col(|ui| {
-                            ui.label(&sender.email);
+                            let inspected = state.inspected_sender.as_ref() == Some(&sender.email);
+                            if state.raw_headers.is_some() {
+                                if ui.selectable_label(inspected, &sender.display).clicked() {
+                                    clicked_sender = Some(sender.email.clone());
+                                }
+                            } else {
+                                ui.label(&sender.display);
+                            }
                         });
                         row.col(|ui| {
                             ui.label(sender.count.to_string());
                         });
+                        row.col(|ui| {
+                            let last_seen = sender
+                                .last_seen
+                                .map_or_else(|| "—".to_string(), |d| d.format("%Y-%m-%d").to_string());
+                            ui.label(last_seen);
+                        });
+                        row.col(|ui| {
+                            let mut note = state.note_for(sender).unwrap_or_default().to_string();
+                            let edit = egui::TextEdit::singleline(&mut note)
+                                .hint_text("add a note")
+                                .desired_width(f32::INFINITY);
+                            if ui.add(edit).changed() {
+                                edited_note = Some((sender.email.clone(), note));
+                            }
+                        });
                     }
                 });
             });
+
+        if let Some(key) = clicked_key {
+            state.toggle_sort(key);
+        }
+        if let Some((email, note)) = edited_note {
+            state.set_note(&email, note);
+        }
+        if let Some(email) = clicked_sender {
+            let reopen = state.inspected_sender.as_ref() != Some(&email);
+            state.inspected_sender = reopen.then_some(email);
+        }
+        draw_raw_headers(ui, state);
+    });
+}
+
+/// Find box above the raw table. Searches every sender, not just the rows
+/// on screen, and returns the matching row indices along with the row to
+/// scroll to when the query or the current match changed this frame.
+fn draw_raw_search(
+    ui: &mut Ui,
+    search: &mut String,
+    current: &mut usize,
+    rows: &[&SenderInfo],
+) -> (Vec<usize>, Option<usize>) {
+    let mut jump = false;
+    let mut matches = Vec::new();
+    ui.horizontal(|ui| {
+        ui.label("Find:");
+        let edit = egui::TextEdit::singleline(search)
+            .hint_text("address or name")
+            .desired_width(200.0);
+        if ui.add(edit).changed() {
+            *current = 0;
+            jump = true;
+        }
+
+        let query = search.trim().to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+        matches = rows
+            .iter()
+            .enumerate()
+            .filter(|(_, sender)| {
+                sender.display.to_lowercase().contains(&query)
+                    || sender.addresses.iter().any(|a| a.contains(&query))
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        if matches.is_empty() {
+            ui.weak("No matches");
+            return;
+        }
+
+        *current %= matches.len();
+        if ui.small_button("⏶").on_hover_text("Previous match").clicked() {
+            *current = current.checked_sub(1).unwrap_or(matches.len() - 1);
+            jump = true;
+        }
+        if ui.small_button("⏷").on_hover_text("Next match").clicked() {
+            *current = (*current + 1) % matches.len();
+            jump = true;
+        }
+        ui.label(format!("{} of {} matches", *current + 1, matches.len()));
+    });
+    let scroll_to = matches.get(*current).copied().filter(|_| jump);
+    (matches, scroll_to)
+}
+
+/// Raw header samples the scan kept for the sender clicked in the raw
+/// table, across every address merged into it.
+fn draw_raw_headers(ui: &mut Ui, state: &AppState) {
+    let (Some(raw_headers), Some(email)) = (&state.raw_headers, &state.inspected_sender) else {
+        return;
+    };
+    let Some(sender) = state.senders.iter().find(|s| &s.email == email) else {
+        return;
+    };
+    ui.add_space(4.0);
+    ui.label(egui::RichText::new(format!("Raw headers for {}", sender.display)).strong());
+    let mut shown = false;
+    for address in &sender.addresses {
+        for header in raw_headers.get(address).into_iter().flatten() {
+            ui.monospace(header);
+            shown = true;
+        }
+    }
+    if !shown {
+        ui.weak("No samples kept for this sender.");
+    }
+}
+
+/// Orders senders for the raw table without touching `state.senders`, which
+/// the donut and kill list rely on being sorted by count. Senders without a
+/// date always sort last when ordering by recency.
+fn sorted_senders(senders: &[SenderInfo], key: SortKey, ascending: bool) -> Vec<&SenderInfo> {
+    let mut rows: Vec<&SenderInfo> = senders.iter().collect();
+    rows.sort_by(|a, b| match key {
+        SortKey::Sender => {
+            let ord = a.email.cmp(&b.email);
+            if ascending { ord } else { ord.reverse() }
+        }
+        SortKey::Count => {
+            let ord = a.count.cmp(&b.count);
+            if ascending { ord } else { ord.reverse() }
+        }
+        SortKey::LastSeen => match (a.last_seen, b.last_seen) {
+            (Some(x), Some(y)) => {
+                let ord = x.cmp(&y);
+                if ascending { ord } else { ord.reverse() }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
     });
+    rows
 }
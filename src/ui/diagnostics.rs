@@ -0,0 +1,45 @@
+use crate::imap::scanner::ScanDiagnostics;
+use egui::Ui;
+
+pub fn draw_diagnostics(ui: &mut Ui, diagnostics: &ScanDiagnostics) {
+    ui.collapsing("Diagnostics", |ui| {
+        egui::Grid::new("scan_diagnostics").show(ui, |ui| {
+            ui.label("Active workers");
+            ui.label(format!("{} / {}", diagnostics.active_workers, diagnostics.workers));
+            ui.end_row();
+            ui.label("Batches queued");
+            ui.label(diagnostics.batches_queued().to_string());
+            ui.end_row();
+            ui.label("Batches completed");
+            ui.label(format!(
+                "{} / {}",
+                diagnostics.batches_completed, diagnostics.batches_total
+            ));
+            ui.end_row();
+            ui.label("Batches failed");
+            ui.label(diagnostics.batches_failed.to_string());
+            ui.end_row();
+        });
+
+        if !diagnostics.unparsed_samples.is_empty() {
+            ui.add_space(4.0);
+            ui.label(format!(
+                "Unparsed From headers ({} messages)",
+                diagnostics.unparsed
+            ));
+            for header in &diagnostics.unparsed_samples {
+                ui.monospace(header);
+            }
+        }
+
+        if diagnostics.errors.is_empty() {
+            ui.weak("No connection errors recorded.");
+            return;
+        }
+        ui.add_space(4.0);
+        ui.label("Recent errors");
+        for error in &diagnostics.errors {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    });
+}
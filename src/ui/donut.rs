@@ -1,6 +1,14 @@
 use crate::state::SenderInfo;
-use egui::{Color32, Pos2, Sense, Shape, Stroke, Vec2};
+use egui::{Color32, Mesh, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use std::collections::HashMap;
 use std::f32::consts::TAU;
+use std::sync::Arc;
+
+/// Outline drawn around slices whose sender is checked in the kill list.
+const SELECTED_STROKE: Stroke = Stroke {
+    width: 2.5,
+    color: Color32::RED,
+};
 
 const PALETTE: &[Color32] = &[
     Color32::from_rgb(239, 71, 111),
@@ -25,16 +33,115 @@ const PALETTE: &[Color32] = &[
     Color32::from_rgb(247, 127, 0),
 ];
 
-pub fn draw_donut(ui: &mut egui::Ui, senders: &[SenderInfo], max_slices: usize) {
+/// Slice geometry kept between frames. Tessellating every slice allocates
+/// on each repaint, which adds up with many thin slices, so it is only
+/// rebuilt when the counts or the chart rect change.
+#[derive(Debug, Clone, Default)]
+pub struct DonutCache {
+    counts: Vec<usize>,
+    rect: Option<Rect>,
+    slices: Vec<Slice>,
+    /// Fill for every slice at once.
+    mesh: Arc<Mesh>,
+}
+
+#[derive(Debug, Clone)]
+struct Slice {
+    start: f32,
+    sweep: f32,
+    color: Color32,
+    /// Outer arc clockwise, then the inner arc back.
+    outline: Vec<Pos2>,
+}
+
+impl DonutCache {
+    /// Rebuilds the geometry if `counts` or `rect` differ from the last
+    /// call. Returns whether it did.
+    fn update(&mut self, counts: &[usize], rect: Rect) -> bool {
+        if self.rect == Some(rect) && self.counts == counts {
+            return false;
+        }
+        let center = rect.center();
+        let outer_r = rect.width() * 0.45;
+        let inner_r = rect.width() * 0.25;
+        let total: usize = counts.iter().sum();
+
+        let mut mesh = Mesh::default();
+        let mut start_angle: f32 = -TAU / 4.0; // Start from top
+        self.slices.clear();
+        for (i, &count) in counts.iter().enumerate() {
+            let sweep = count as f32 / total as f32 * TAU;
+            let color = PALETTE[i % PALETTE.len()];
+
+            // Build arc polygon
+            let segments = (sweep / 0.05).max(2.0) as usize;
+            let mut outline = Vec::with_capacity(segments * 2 + 2);
+
+            // Outer arc
+            for j in 0..=segments {
+                let angle = start_angle + sweep * (j as f32 / segments as f32);
+                outline.push(center + outer_r * Vec2::angled(angle));
+            }
+            // Inner arc (reversed)
+            for j in (0..=segments).rev() {
+                let angle = start_angle + sweep * (j as f32 / segments as f32);
+                outline.push(center + inner_r * Vec2::angled(angle));
+            }
+
+            add_ring_segment(&mut mesh, &outline, color);
+            self.slices.push(Slice {
+                start: start_angle,
+                sweep,
+                color,
+                outline,
+            });
+            start_angle += sweep;
+        }
+
+        self.counts = counts.to_vec();
+        self.rect = Some(rect);
+        self.mesh = Arc::new(mesh);
+        true
+    }
+}
+
+/// Triangulates a slice outline built by [`DonutCache::update`]: point `j`
+/// on the outer arc pairs with point `len - 1 - j` on the inner one.
+fn add_ring_segment(mesh: &mut Mesh, outline: &[Pos2], color: Color32) {
+    let base = mesh.vertices.len() as u32;
+    let last = outline.len() as u32 - 1;
+    let segments = last / 2;
+    mesh.reserve_vertices(outline.len());
+    mesh.reserve_triangles(segments as usize * 2);
+    for &point in outline {
+        mesh.colored_vertex(point, color);
+    }
+    for j in 0..segments {
+        let (outer, outer_next) = (base + j, base + j + 1);
+        let (inner, inner_next) = (base + last - j, base + last - j - 1);
+        mesh.add_triangle(outer, outer_next, inner_next);
+        mesh.add_triangle(outer, inner_next, inner);
+    }
+}
+
+/// Draws the chart as large as fits, up to `max_size` across.
+pub fn draw_donut(
+    ui: &mut egui::Ui,
+    senders: &[SenderInfo],
+    max_slices: usize,
+    selected: &HashMap<String, bool>,
+    cache: &mut DonutCache,
+    max_size: f32,
+) {
     let available = ui.available_size();
-    let size = available.x.min(available.y).min(300.0);
+    let size = available.x.min(available.y).min(max_size);
     let (response, painter) = ui.allocate_painter(Vec2::splat(size), Sense::hover());
     let rect = response.rect;
     let center = rect.center();
     let outer_r = size * 0.45;
     let inner_r = size * 0.25;
 
-    let top_senders: Vec<&SenderInfo> = senders.iter().take(max_slices).collect();
+    let top_senders = &senders[..senders.len().min(max_slices)];
     let total: usize = top_senders.iter().map(|s| s.count).sum();
     if total == 0 {
         painter.text(
@@ -47,78 +154,119 @@ pub fn draw_donut(ui: &mut egui::Ui, senders: &[SenderInfo], max_slices: usize)
         return;
     }
 
-    let mouse_pos = response.hover_pos();
-    let mut start_angle: f32 = -TAU / 4.0; // Start from top
-    let mut hovered_sender: Option<(&str, usize)> = None;
+    // Compared without collecting so an unchanged frame allocates nothing.
+    if !top_senders
+        .iter()
+        .map(|s| s.count)
+        .eq(cache.counts.iter().copied())
+        || cache.rect != Some(rect)
+    {
+        let counts: Vec<usize> = top_senders.iter().map(|s| s.count).collect();
+        cache.update(&counts, rect);
+    }
+    painter.add(Shape::Mesh(Arc::clone(&cache.mesh)));
 
-    for (i, sender) in top_senders.iter().enumerate() {
-        let fraction = sender.count as f32 / total as f32;
-        let sweep = fraction * TAU;
-        let color = PALETTE[i % PALETTE.len()];
-
-        // Build arc polygon
-        let segments = (sweep / 0.05).max(2.0) as usize;
-        let mut points = Vec::with_capacity(segments * 2 + 2);
-
-        // Outer arc
-        for j in 0..=segments {
-            let angle = start_angle + sweep * (j as f32 / segments as f32);
-            points.push(Pos2::new(
-                center.x + outer_r * angle.cos(),
-                center.y + outer_r * angle.sin(),
-            ));
-        }
-        // Inner arc (reversed)
-        for j in (0..=segments).rev() {
-            let angle = start_angle + sweep * (j as f32 / segments as f32);
-            points.push(Pos2::new(
-                center.x + inner_r * angle.cos(),
-                center.y + inner_r * angle.sin(),
-            ));
-        }
+    // Borders: one spoke per slice plus the two rims.
+    let border = Stroke::new(1.0, Color32::from_gray(30));
+    for slice in &cache.slices {
+        let direction = Vec2::angled(slice.start);
+        painter.line_segment(
+            [center + inner_r * direction, center + outer_r * direction],
+            border,
+        );
+    }
+    painter.circle_stroke(center, outer_r, border);
+    painter.circle_stroke(center, inner_r, border);
 
-        // Hit test for hover
-        let mut is_hovered = false;
-        if let Some(mp) = mouse_pos {
-            let dx = mp.x - center.x;
-            let dy = mp.y - center.y;
-            let dist = (dx * dx + dy * dy).sqrt();
-            if dist >= inner_r && dist <= outer_r {
-                let mut angle = dy.atan2(dx);
-                // Normalize to same range as start_angle
-                if angle < start_angle {
-                    angle += TAU;
-                }
-                let end_angle = start_angle + sweep;
-                if angle >= start_angle && angle <= end_angle
-                    || angle + TAU >= start_angle && angle + TAU <= end_angle
-                {
-                    is_hovered = true;
-                    hovered_sender = Some((&sender.email, sender.count));
-                }
+    let mut hovered_sender: Option<(&str, usize)> = None;
+    if let Some(mp) = response.hover_pos() {
+        let offset = mp - center;
+        let dist = offset.length();
+        if dist >= inner_r && dist <= outer_r {
+            let angle = offset.angle();
+            let hit = cache
+                .slices
+                .iter()
+                .zip(top_senders)
+                .find(|(slice, _)| slice_contains(angle, slice.start, slice.sweep));
+            if let Some((slice, sender)) = hit {
+                hovered_sender = Some((&sender.display, sender.count));
+                let color = slice.color;
+                let highlight = Color32::from_rgba_premultiplied(
+                    color.r().saturating_add(40),
+                    color.g().saturating_add(40),
+                    color.b().saturating_add(40),
+                    255,
+                );
+                let mut mesh = Mesh::default();
+                add_ring_segment(&mut mesh, &slice.outline, highlight);
+                painter.add(mesh);
             }
         }
+    }
 
-        let fill = if is_hovered {
-            Color32::from_rgba_premultiplied(
-                color.r().saturating_add(40),
-                color.g().saturating_add(40),
-                color.b().saturating_add(40),
-                255,
-            )
-        } else {
-            color
-        };
+    // Outlines go on top so neighbouring slices can't paint over them.
+    let is_selected = |email: &str| selected.get(email).copied().unwrap_or(false);
+    for (slice, sender) in cache.slices.iter().zip(top_senders) {
+        if is_selected(&sender.email) {
+            painter.add(Shape::closed_line(slice.outline.clone(), SELECTED_STROKE));
+        }
+    }
+
+    let selected_total: usize = senders
+        .iter()
+        .filter(|s| is_selected(&s.email))
+        .map(|s| s.count)
+        .sum();
+    let all_total: usize = senders.iter().map(|s| s.count).sum();
+    draw_center_label(
+        &painter,
+        ui.visuals(),
+        center,
+        hovered_sender,
+        selected_total,
+        all_total,
+    );
+}
 
-        painter.add(Shape::convex_polygon(
-            points,
-            fill,
-            Stroke::new(1.0, Color32::from_gray(30)),
-        ));
+/// Key to the slices [`draw_donut`] draws for the same `senders`, with the
+/// senders past `max_slices` summed up on the last line.
+pub fn draw_legend(ui: &mut egui::Ui, senders: &[SenderInfo], max_slices: usize) {
+    let shown = senders.len().min(max_slices);
+    for (i, sender) in senders[..shown].iter().enumerate() {
+        ui.horizontal(|ui| {
+            let (swatch, _) = ui.allocate_exact_size(Vec2::splat(10.0), Sense::hover());
+            ui.painter().rect_filled(swatch, 2.0, PALETTE[i % PALETTE.len()]);
+            ui.label(format!("{} ({})", sender.display, sender.count));
+        });
+    }
+    let rest = &senders[shown..];
+    if !rest.is_empty() {
+        let emails: usize = rest.iter().map(|s| s.count).sum();
+        ui.weak(format!("Not charted: {} more senders ({emails})", rest.len()));
+    }
+}
 
-        start_angle += sweep;
+/// Whether `angle` (any range, as from `atan2`) falls in the slice that
+/// starts at `start` and runs clockwise for `sweep`. The angle is measured
+/// from the slice start in [0, TAU), so slices crossing the ±PI seam or
+/// the top need no special casing. The end is exclusive, so a point on a
+/// border belongs to exactly one slice.
+fn slice_contains(angle: f32, start: f32, sweep: f32) -> bool {
+    if sweep >= TAU {
+        return true;
     }
+    (angle - start).rem_euclid(TAU) < sweep
+}
 
+fn draw_center_label(
+    painter: &egui::Painter,
+    visuals: &egui::Visuals,
+    center: Pos2,
+    hovered_sender: Option<(&str, usize)>,
+    selected_total: usize,
+    all_total: usize,
+) {
     // Center label — truncate to fit the donut hole at 11pt proportional font.
     // 25 chars is the display threshold; 22 + "..." keeps it within bounds.
     if let Some((sender, count)) = hovered_sender {
@@ -132,14 +280,77 @@ pub fn draw_donut(ui: &mut egui::Ui, senders: &[SenderInfo], max_slices: usize)
             egui::Align2::CENTER_CENTER,
             truncated,
             egui::FontId::proportional(11.0),
-            Color32::WHITE,
+            visuals.text_color(),
         );
         painter.text(
             center + Vec2::new(0.0, 8.0),
             egui::Align2::CENTER_CENTER,
             format!("{count} emails"),
             egui::FontId::proportional(11.0),
-            Color32::LIGHT_GRAY,
+            visuals.weak_text_color(),
+        );
+    } else if selected_total > 0 {
+        let pct = selected_total as f32 / all_total as f32 * 100.0;
+        painter.text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            format!("{pct:.1}% selected"),
+            egui::FontId::proportional(11.0),
+            SELECTED_STROKE.color,
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn every_angle_lands_in_exactly_one_slice() {
+        // Uneven slices starting at the top, one of them crossing the ±PI
+        // seam on the left and the last one wrapping back to the top.
+        let sweeps = [0.1, 0.3, 0.25, 0.2, 0.15].map(|fraction| fraction * TAU);
+        for step in 0..720 {
+            let angle = -PI + step as f32 * TAU / 720.0 + 0.001;
+            let mut start = -TAU / 4.0;
+            let mut hits = 0;
+            for sweep in sweeps {
+                if slice_contains(angle, start, sweep) {
+                    hits += 1;
+                }
+                start += sweep;
+            }
+            assert_eq!(hits, 1, "angle {angle}");
+        }
+    }
+
+    #[test]
+    fn slices_are_found_on_both_sides_of_the_seam() {
+        let start = -TAU / 4.0;
+        // Top right quadrant, just clockwise of the start.
+        assert!(slice_contains(-TAU / 4.0 + 0.01, start, 0.1));
+        assert!(!slice_contains(-TAU / 4.0 - 0.01, start, 0.1));
+        // A slice from bottom-left round to top-left spans the seam.
+        let left = 3.0 * TAU / 8.0;
+        assert!(slice_contains(PI - 0.01, left, PI / 2.0));
+        assert!(slice_contains(-PI + 0.01, left, PI / 2.0));
+        assert!(!slice_contains(0.0, left, PI / 2.0));
+        // A lone sender covers the whole ring.
+        assert!(slice_contains(start, start, TAU));
+        assert!(slice_contains(start - 0.0001, start, TAU));
+    }
+
+    #[test]
+    fn geometry_is_rebuilt_only_when_counts_or_size_change() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(200.0));
+        let mut cache = DonutCache::default();
+        assert!(cache.update(&[5, 3, 1], rect));
+        assert!(!cache.update(&[5, 3, 1], rect));
+        assert!(cache.update(&[5, 3, 2], rect));
+        assert!(cache.update(&[5, 3, 2], rect.expand(10.0)));
+        assert_eq!(cache.slices.len(), 3);
+        let vertices: usize = cache.slices.iter().map(|slice| slice.outline.len()).sum();
+        assert_eq!(cache.mesh.vertices.len(), vertices);
+    }
+}
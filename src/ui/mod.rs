@@ -1,3 +1,7 @@
+pub mod chart_export;
 pub mod dashboard;
+pub mod diagnostics;
 pub mod donut;
+pub mod recipients;
+pub mod reply_balance;
 pub mod sidebar;
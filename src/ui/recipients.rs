@@ -0,0 +1,77 @@
+use crate::imap::scanner::RecipientCounts;
+use crate::state::{AppState, SenderInfo};
+use egui::Ui;
+
+/// Senders listed in the breakdown table, most Cc'd first.
+const MAX_ROWS: usize = 20;
+
+pub fn draw_recipients(ui: &mut Ui, state: &mut AppState) {
+    ui.collapsing("To / Cc Breakdown", |ui| {
+        if state.recipients.is_none() {
+            ui.label("Enable \"Break down To / Cc\" and rescan to see where you were addressed.");
+            return;
+        }
+
+        let mut rows: Vec<(&SenderInfo, RecipientCounts)> = state
+            .senders
+            .iter()
+            .filter_map(|sender| Some((sender, state.recipient_counts(sender)?)))
+            .collect();
+        let mut totals = RecipientCounts::default();
+        for (_, counts) in &rows {
+            totals += *counts;
+        }
+        ui.label(format!(
+            "On To: {}   On Cc: {}   Neither (lists, Bcc): {}",
+            totals.to, totals.cc, totals.neither
+        ));
+
+        rows.retain(|(_, counts)| counts.cc > 0);
+        rows.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.cc));
+        rows.truncate(MAX_ROWS);
+        if rows.is_empty() {
+            ui.weak("No mail had you on Cc.");
+            return;
+        }
+        draw_breakdown_table(ui, &rows);
+
+        if ui
+            .button("Select mostly-Cc senders")
+            .on_hover_text("Checks senders whose mail had you on Cc more often than not")
+            .clicked()
+        {
+            state.select_mostly_cc();
+        }
+    });
+}
+
+fn draw_breakdown_table(ui: &mut Ui, rows: &[(&SenderInfo, RecipientCounts)]) {
+    egui_extras::TableBuilder::new(ui)
+        .id_salt("recipient_breakdown")
+        .striped(true)
+        .resizable(true)
+        .column(egui_extras::Column::remainder().at_least(200.0))
+        .column(egui_extras::Column::initial(60.0))
+        .column(egui_extras::Column::initial(60.0))
+        .column(egui_extras::Column::initial(60.0))
+        .header(20.0, |mut header| {
+            for title in ["Sender", "To", "Cc", "Neither"] {
+                header.col(|ui| {
+                    ui.strong(title);
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(18.0, rows.len(), |mut row| {
+                let (sender, counts) = &rows[row.index()];
+                row.col(|ui| {
+                    ui.label(&sender.display);
+                });
+                for count in [counts.to, counts.cc, counts.neither] {
+                    row.col(|ui| {
+                        ui.label(count.to_string());
+                    });
+                }
+            });
+        });
+}
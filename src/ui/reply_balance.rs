@@ -0,0 +1,65 @@
+use crate::analysis::ContactBalance;
+use crate::state::{AppState, BalanceView};
+use egui::Ui;
+
+pub fn draw_reply_balance(ui: &mut Ui, state: &mut AppState) {
+    ui.collapsing("Reply Balance", |ui| {
+        let Some(balance) = &state.reply_balance else {
+            ui.label(
+                "Enable \"Flag people I've emailed\" and rescan to compare \
+                 against your Sent folder.",
+            );
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(
+                &mut state.balance_view,
+                BalanceView::Unanswered,
+                format!("They rarely reply ({})", balance.unanswered.len()),
+            );
+            ui.selectable_value(
+                &mut state.balance_view,
+                BalanceView::Ignored,
+                format!("I never reply ({})", balance.ignored.len()),
+            );
+        });
+
+        let rows = match state.balance_view {
+            BalanceView::Unanswered => &balance.unanswered,
+            BalanceView::Ignored => &balance.ignored,
+        };
+        draw_balance_table(ui, rows);
+    });
+}
+
+fn draw_balance_table(ui: &mut Ui, rows: &[ContactBalance]) {
+    egui_extras::TableBuilder::new(ui)
+        .id_salt("reply_balance")
+        .striped(true)
+        .resizable(true)
+        .column(egui_extras::Column::remainder().at_least(200.0))
+        .column(egui_extras::Column::initial(80.0))
+        .column(egui_extras::Column::initial(80.0))
+        .header(20.0, |mut header| {
+            for title in ["Address", "I sent", "They sent"] {
+                header.col(|ui| {
+                    ui.strong(title);
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(18.0, rows.len(), |mut row| {
+                let contact = &rows[row.index()];
+                row.col(|ui| {
+                    ui.label(&contact.address);
+                });
+                row.col(|ui| {
+                    ui.label(contact.sent.to_string());
+                });
+                row.col(|ui| {
+                    ui.label(contact.received.to_string());
+                });
+            });
+        });
+}
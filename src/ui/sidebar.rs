@@ -1,71 +1,778 @@
-use crate::bridge::UiCommand;
-use crate::state::{AppPhase, AppState, DeleteMode};
+use crate::bridge::{ConnectRequest, DiagnoseRequest, FolderCheckRequest, UiCommand};
+use crate::export::{self, ScanSnapshot};
+use crate::imap::diagnose;
+use crate::imap::provider::{ImapProvider, ProviderKind};
+use crate::imap::scanner::validate_search_query;
+use crate::oauth;
+use crate::state::{
+    AppPhase, AppState, ConnectStatus, DeleteMode, DeleteScope, Diagnosis, FolderCheck, LinkStatus,
+    ProtectedDomains, ScanDirection, SignIn, Theme,
+};
 use egui::Ui;
 use tokio::sync::mpsc::UnboundedSender;
 
 pub fn draw_sidebar(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>) {
     let busy = state.phase == AppPhase::Scanning || state.phase == AppPhase::Deleting;
 
-    ui.heading("Credentials");
+    draw_credentials(ui, state, cmd_tx, busy);
+
+    ui.add_space(8.0);
+    ui.separator();
     ui.add_space(4.0);
 
-    ui.label("Email");
-    ui.add_enabled(!busy, egui::TextEdit::singleline(&mut state.email).hint_text("you@gmail.com"));
+    let query_valid = draw_scan_settings(ui, state, busy);
+
+    ui.add_space(4.0);
+    draw_network_settings(ui, state, busy);
+    draw_special_folders(ui, state, cmd_tx, busy);
+    draw_diagnostics(ui, state, cmd_tx);
+
+    ui.add_space(8.0);
+
+    let can_scan = !busy && state.has_credentials() && query_valid;
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(can_scan, egui::Button::new("Start Scan"))
+            .clicked()
+        {
+            let _ = cmd_tx.send(UiCommand::StartScan(state.start_scan(false)));
+        }
+        let junk = state.junk_folder();
+        if ui
+            .add_enabled(can_scan, egui::Button::new("Scan Spam folder"))
+            .on_hover_text(format!("Scans {junk} to see what the spam filter is catching"))
+            .clicked()
+        {
+            state.folder = junk;
+            let _ = cmd_tx.send(UiCommand::StartScan(state.start_scan(false)));
+        }
+    });
+    draw_saved_scans(ui, state, busy);
+
+    ui.add_space(8.0);
+    ui.separator();
+    ui.add_space(4.0);
+
+    ui.add_enabled(
+        !busy && !state.analyze_only_locked,
+        egui::Checkbox::new(&mut state.analyze_only, "Analyze only"),
+    )
+    .on_hover_text("Hides everything that deletes, so exploring can't remove any mail")
+    .on_disabled_hover_text("Started with --analyze-only");
+    if state.analyze_only {
+        ui.colored_label(ui.visuals().warn_fg_color, "🔍 Analyze-only mode: deleting is off");
+    } else {
+        draw_delete_settings(ui, state, busy);
+    }
+    draw_protected_domains(ui, state);
+    draw_blocklist(ui, state, busy);
+
+    ui.add_space(8.0);
+    ui.separator();
+    ui.add_space(4.0);
+
+    ui.label("Theme");
+    ui.horizontal(|ui| {
+        let before = state.theme;
+        ui.selectable_value(&mut state.theme, Theme::Dark, "🌙 Dark");
+        ui.selectable_value(&mut state.theme, Theme::Light, "☀ Light");
+        if state.theme != before {
+            ui.ctx().set_visuals(state.theme.visuals());
+        }
+    });
+    draw_display_limits(ui, state);
+
+    let notifications = &mut state.notifications;
+    ui.checkbox(&mut notifications.on_finish, "Notify when a scan or delete finishes")
+        .on_hover_text("Only while the window is in the background");
+    ui.add_enabled(
+        notifications.on_finish,
+        egui::Checkbox::new(&mut notifications.sound, "Play a sound"),
+    );
+}
+
+/// How many senders the donut and kill list show.
+fn draw_display_limits(ui: &mut Ui, state: &mut AppState) {
+    let limits = &mut state.display_limits;
+    ui.horizontal(|ui| {
+        ui.label("Donut slices:");
+        ui.add(egui::DragValue::new(&mut limits.donut_slices).range(1..=100));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Kill list rows:");
+        let rows = ui.add(egui::DragValue::new(&mut limits.kill_list_rows).range(10..=10_000));
+        if rows.changed() {
+            state.kill_list_limit = limits.kill_list_rows;
+        }
+    });
+}
+
+/// Delete mode, trash folder, pacing and scope. Hidden in analyze-only mode.
+fn draw_delete_settings(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    ui.label("Delete Mode");
+    ui.radio_value(&mut state.delete_mode, DeleteMode::Trash, "Move to Trash");
+    draw_permanent_mode(ui, state);
+    // X-GM-LABELS is a Gmail extension; other servers would reject the store.
+    let gmail = ImapProvider::from_email(&state.email).kind == ProviderKind::Gmail
+        && !ImapProvider::is_fallback(&state.email);
+    if !gmail && state.delete_mode == DeleteMode::Archive {
+        state.delete_mode = DeleteMode::Trash;
+    }
+    ui.add_enabled_ui(gmail, |ui| {
+        ui.radio_value(&mut state.delete_mode, DeleteMode::Archive, "Archive (remove from Inbox)")
+            .on_hover_text("Keeps the emails in All Mail. Only meaningful when scanning INBOX")
+            .on_disabled_hover_text("Only available for Gmail accounts");
+    });
+
+    ui.add_space(4.0);
+    ui.add_enabled_ui(state.delete_mode == DeleteMode::Trash, |ui| {
+        ui.label("Trash folder");
+        let default_trash = ImapProvider::from_email(&state.email).trash_folder;
+        ui.add(egui::TextEdit::singleline(&mut state.trash_folder).hint_text(default_trash))
+            .on_hover_text("Leave empty for the provider default. Set it for localized names");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Pause between commands");
+        ui.add(
+            egui::DragValue::new(&mut state.delete_pause_ms)
+                .range(0..=10_000)
+                .suffix(" ms"),
+        )
+        .on_hover_text(
+            "Slows large deletes down to stay under provider abuse limits. \
+             Throttling replies add a longer backoff on top",
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Messages per command");
+        let default_chunk = ImapProvider::from_email(&state.email).delete_chunk_size;
+        ui.add(
+            egui::DragValue::new(&mut state.delete_chunk_size)
+                .range(0..=10_000)
+                .custom_formatter(|n, _| match n as usize {
+                    0 => format!("auto ({default_chunk})"),
+                    n => n.to_string(),
+                }),
+        )
+        .on_hover_text(
+            "Bigger chunks delete large senders faster; lower it if the server \
+             rejects or times out on big deletes. 0 uses the provider default",
+        );
+    });
+
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.combine_delete, "One search for all senders"),
+    )
+    .on_hover_text(
+        "Finds every selected sender's mail in a single search and removes it in one pass. \
+         Much faster for many small senders, but progress isn't shown per sender and \
+         \"Keep the newest\" turns it off",
+    );
 
     ui.add_space(4.0);
-    ui.label("App Password");
+    ui.label("Delete Scope");
+    ui.add_enabled_ui(state.scanned_headers.is_from_only(), |ui| {
+        ui.radio_value(&mut state.delete_scope, DeleteScope::WholeFolder, "Whole folder")
+            .on_disabled_hover_text(
+                "Senders were identified by Sender or List-Id, which a From search can't match",
+            );
+    });
+    ui.add_enabled_ui(state.sender_uids.is_some(), |ui| {
+        ui.radio_value(
+            &mut state.delete_scope,
+            DeleteScope::ScannedOnly,
+            "Scanned messages only",
+        )
+        .on_hover_text("Deletes only the messages the last scan counted, nothing newer")
+        .on_disabled_hover_text("Scan with \"Remember message UIDs\" on to use this");
+    });
     ui.add_enabled(
         !busy,
-        egui::TextEdit::singleline(&mut state.password)
-            .password(true)
-            .hint_text("app password"),
+        egui::Checkbox::new(&mut state.rescan_after_delete, "Rescan after deleting"),
+    )
+    .on_hover_text("Refreshes every count from the server. Takes as long as the first scan");
+}
+
+/// The "Permanently Delete" option. The first pick each session asks for
+/// confirmation, and a warning stays up for as long as it is selected.
+fn draw_permanent_mode(ui: &mut Ui, state: &mut AppState) {
+    let permanent = state.delete_mode == DeleteMode::Permanent;
+    if ui.radio(permanent, "Permanently Delete").clicked() && !permanent {
+        if state.permanent_unlocked {
+            state.delete_mode = DeleteMode::Permanent;
+        } else {
+            state.confirm_permanent = true;
+        }
+    }
+    if state.confirm_permanent {
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.colored_label(
+                egui::Color32::RED,
+                "Permanently deleted mail skips the Trash and can't be recovered.",
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Delete permanently").clicked() {
+                    state.permanent_unlocked = true;
+                    state.delete_mode = DeleteMode::Permanent;
+                    state.confirm_permanent = false;
+                }
+                if ui.button("Keep using Trash").clicked() {
+                    state.confirm_permanent = false;
+                }
+            });
+        });
+    }
+    if state.delete_mode == DeleteMode::Permanent {
+        ui.colored_label(
+            egui::Color32::RED,
+            "⚠ Permanent mode: deleted mail can't be recovered",
+        )
+        .on_hover_text("Resets to Move to Trash when the app restarts");
+    }
+}
+
+/// Editor for the domains whose senders can't be selected for deletion.
+fn draw_protected_domains(ui: &mut Ui, state: &mut AppState) {
+    ui.collapsing("Protected domains", |ui| {
+        ui.checkbox(&mut state.protected_domains.own_domain, "Protect my own domain")
+            .on_hover_text("Only applies to custom domains, not gmail.com and the like");
+        let mut removed = None;
+        for domain in &state.protected_domains.domains {
+            ui.horizontal(|ui| {
+                if ui.small_button("✖").on_hover_text("Stop protecting").clicked() {
+                    removed = Some(domain.clone());
+                }
+                ui.label(domain);
+            });
+        }
+        if let Some(domain) = removed {
+            state.protected_domains.domains.remove(&domain);
+        }
+        ui.horizontal(|ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut state.domain_input)
+                    .hint_text("example.com")
+                    .desired_width(140.0),
+            );
+            let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Add").clicked() || submitted {
+                state.add_protected_domain();
+            }
+        });
+        if ui.button("Restore defaults").clicked() {
+            let own_domain = state.protected_domains.own_domain;
+            state.protected_domains = ProtectedDomains {
+                own_domain,
+                ..ProtectedDomains::default()
+            };
+        }
+    });
+}
+
+/// The deny-list file applied to every scan, opposite the allowlist.
+fn draw_blocklist(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    ui.collapsing("Blocklist", |ui| {
+        ui.label("File with one address or domain per line; * is a wildcard");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut state.blocklist.path)
+                    .hint_text("blocklist.txt")
+                    .desired_width(160.0),
+            );
+            if ui.button("Browse...").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    state.blocklist.path = path.display().to_string();
+                }
+            }
+        });
+        ui.checkbox(&mut state.blocklist.auto_select, "Select listed senders after a scan")
+            .on_hover_text("Protected senders and people you've emailed are only flagged");
+        let has_results = !state.senders.is_empty();
+        if ui
+            .add_enabled(!busy && has_results, egui::Button::new("Apply to current results"))
+            .clicked()
+        {
+            state.error_message = None;
+            state.apply_blocklist();
+        }
+        if has_results && !state.blocklist.path.trim().is_empty() {
+            ui.weak(format!("{} senders listed", state.blocklisted.len()));
+        }
+    });
+}
+
+/// Saving the current results to a file and loading them back for review
+/// without connecting.
+fn draw_saved_scans(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    ui.horizontal(|ui| {
+        let has_results = !state.senders.is_empty();
+        if ui
+            .add_enabled(!busy && has_results, egui::Button::new("Save scan"))
+            .clicked()
+        {
+            let snapshot = ScanSnapshot {
+                email: state.email.trim().to_string(),
+                folder: state.folder.clone(),
+                total_emails: state.total_emails,
+                headers: state.scanned_headers,
+                senders: state.senders.clone(),
+            };
+            match export::save_scan(&snapshot) {
+                Ok(Some(path)) => tracing::info!(path = %path.display(), "saved scan"),
+                Ok(None) => {}
+                Err(e) => state.error_message = Some(e.to_string()),
+            }
+        }
+        if ui
+            .add_enabled(!busy, egui::Button::new("Load scan"))
+            .on_hover_text("Review a saved scan offline")
+            .clicked()
+        {
+            match export::load_scan() {
+                Ok(Some((path, snapshot))) => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    state.load_scan(name.into_owned(), snapshot);
+                }
+                Ok(None) => {}
+                Err(e) => state.error_message = Some(e.to_string()),
+            }
+        }
+    });
+}
+
+fn draw_credentials(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    ui.horizontal(|ui| {
+        ui.heading("Credentials");
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            draw_link_status(ui, &state.link_status);
+        });
+    });
+    ui.add_space(4.0);
+
+    ui.label("Email");
+    let email = ui.add_enabled(
+        !busy,
+        egui::TextEdit::singleline(&mut state.email).hint_text("you@gmail.com"),
     );
+    if email.changed() {
+        state.restore_folder();
+    }
+    draw_detected_provider(ui, &state.email);
+
+    if oauth::is_supported(&state.email) {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!busy, |ui| {
+                ui.radio_value(&mut state.sign_in, SignIn::AppPassword, "App password");
+                ui.radio_value(&mut state.sign_in, SignIn::OAuth, "OAuth");
+            });
+        });
+    }
+
+    ui.add_space(4.0);
+    if state.uses_oauth() {
+        draw_oauth_settings(ui, state, cmd_tx, busy);
+    } else {
+        ui.label("App Password");
+        ui.add_enabled(
+            !busy,
+            egui::TextEdit::singleline(&mut *state.password)
+                .password(true)
+                .hint_text("app password"),
+        );
+    }
 
     ui.add_space(4.0);
     ui.label("Folder");
     ui.add_enabled(!busy, egui::TextEdit::singleline(&mut state.folder).hint_text("INBOX"));
+}
 
-    ui.add_space(8.0);
-    ui.separator();
-    ui.add_space(4.0);
+/// Status dot for server connectivity: gray until something connects, then
+/// green or red depending on how the last attempt went.
+fn draw_link_status(ui: &mut Ui, status: &LinkStatus) {
+    let (color, label, detail) = match status {
+        LinkStatus::Unknown => (egui::Color32::GRAY, "Not connected", None),
+        LinkStatus::Online => (egui::Color32::GREEN, "Online", None),
+        LinkStatus::Offline(error) => (egui::Color32::RED, "Offline", Some(error.as_str())),
+    };
+    let response = ui
+        .horizontal(|ui| {
+            ui.label(egui::RichText::new(label).small().weak());
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(10.0, 10.0), egui::Sense::hover());
+            ui.painter().circle_filled(rect.center(), 4.0, color);
+        })
+        .response;
+    if let Some(error) = detail {
+        response.on_hover_text(error);
+    }
+}
+
+/// The OAuth app registration and the "Connect account" flow that saves a
+/// refresh token for the address.
+fn draw_oauth_settings(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    ui.collapsing("OAuth client", |ui| {
+        ui.add_enabled_ui(!busy, |ui| {
+            ui.label("Client ID");
+            ui.text_edit_singleline(&mut state.oauth_client.client_id)
+                .on_hover_text("From a desktop app registered in Google Cloud or Microsoft Entra");
+            ui.label("Client secret (Google only)");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.oauth_client.client_secret).password(true),
+            );
+        });
+    });
 
+    let waiting = state.connect_status == Some(ConnectStatus::Waiting);
+    ui.horizontal(|ui| {
+        let can_connect = !busy && !waiting && !state.oauth_client.client_id.trim().is_empty();
+        if ui
+            .add_enabled(can_connect, egui::Button::new("Connect account"))
+            .on_hover_text("Opens the provider's consent page in your browser")
+            .clicked()
+        {
+            state.connect_status = Some(ConnectStatus::Waiting);
+            let _ = cmd_tx.send(UiCommand::ConnectAccount(ConnectRequest {
+                email: state.email.trim().to_string(),
+                client: state.oauth_client.clone(),
+                connection: state.connection_options(),
+            }));
+        }
+        if ui
+            .add_enabled(!busy && !waiting, egui::Button::new("Forget"))
+            .on_hover_text("Removes the saved sign-in for this address from the keyring")
+            .clicked()
+        {
+            state.connect_status = Some(match oauth::disconnect_account(&state.email) {
+                Ok(()) => ConnectStatus::Connected("Saved sign-in removed".to_string()),
+                Err(e) => ConnectStatus::Failed(e.to_string()),
+            });
+        }
+    });
+
+    match &state.connect_status {
+        None => {}
+        Some(ConnectStatus::Waiting) => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Waiting for the browser...");
+            });
+        }
+        Some(ConnectStatus::Connected(message)) => {
+            ui.label(message);
+        }
+        Some(ConnectStatus::Failed(err)) => {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+    }
+}
+
+/// Shows which server the address maps to, so a misdetected domain is
+/// caught before a scan fails against the wrong host.
+fn draw_detected_provider(ui: &mut Ui, email: &str) {
+    let Some((local, domain)) = email.trim().rsplit_once('@') else {
+        return;
+    };
+    if local.is_empty() || !domain.contains('.') {
+        return;
+    }
+    let provider = ImapProvider::from_email(email.trim());
+    let server = format!("{}:{}", provider.host, provider.port);
+    if ImapProvider::is_fallback(email.trim()) {
+        ui.colored_label(egui::Color32::ORANGE, format!("⚠ Unknown provider, trying {server}"));
+    } else {
+        ui.weak(format!("Server: {server}"));
+    }
+}
+
+/// Draws scan scope controls. Returns whether the search query and rotation
+/// pattern are usable.
+fn draw_scan_settings(ui: &mut Ui, state: &mut AppState, busy: bool) -> bool {
     ui.label("Scan Depth (0 = all)");
     ui.add_enabled(
         !busy,
         egui::Slider::new(&mut state.scan_depth, 0..=50000),
     );
+    ui.add_enabled_ui(!busy && state.scan_depth > 0, |ui| {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut state.scan_direction, ScanDirection::Newest, "Newest");
+            ui.radio_value(&mut state.scan_direction, ScanDirection::Oldest, "Oldest");
+        });
+    });
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.check_correspondents, "Flag people I've emailed"),
+    )
+    .on_hover_text("Also scans the Sent folder so two-way contacts can't be bulk-selected");
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.merge_gmail_aliases, "Merge Gmail address variants"),
+    )
+    .on_hover_text("Counts john.doe+news@gmail.com and johndoe@gmail.com as one sender");
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.collapse_rotating, "Collapse rotating addresses"),
+    )
+    .on_hover_text("Counts bounce-123@mail.acme.com and bounce-456@mail.acme.com as one sender");
+    let mut pattern_valid = true;
+    if state.collapse_rotating {
+        ui.add_enabled(
+            !busy,
+            egui::TextEdit::singleline(&mut state.rotation_pattern)
+                .hint_text("local-part regex")
+                .font(egui::TextStyle::Monospace),
+        );
+        if let Some(Err(e)) = state.rotation_regex() {
+            ui.colored_label(egui::Color32::RED, format!("Invalid pattern: {e}"));
+            pattern_valid = false;
+        }
+    }
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.group_by_domain, "Group subdomains by company"),
+    )
+    .on_hover_text("Counts news.shop.co.uk and offers.shop.co.uk as one sender, shop.co.uk");
+    ui.add_enabled(
+        !busy && state.sender_headers.is_from_only(),
+        egui::Checkbox::new(&mut state.retain_uids, "Remember message UIDs"),
+    )
+    .on_hover_text("Needed to delete only scanned messages. Off saves memory on huge folders")
+    .on_disabled_hover_text("Always on when senders aren't identified by From alone");
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.keep_raw_headers, "Keep raw headers (debug)"),
+    )
+    .on_hover_text("Keeps a few raw headers per sender; click a row in Raw Data to see them");
+    ui.add_enabled(
+        !busy,
+        egui::Checkbox::new(&mut state.recipient_breakdown, "Break down To / Cc"),
+    )
+    .on_hover_text("Counts whether you were on To, Cc or neither, to spot bulk-Cc noise");
+    let headers_valid = draw_sender_headers(ui, state, busy);
+    ui.horizontal(|ui| {
+        ui.label("Search timeout");
+        ui.add_enabled(
+            !busy,
+            egui::DragValue::new(&mut state.search_timeout_secs)
+                .range(10..=1800)
+                .suffix(" s"),
+        );
+    });
 
-    ui.add_space(8.0);
+    ui.add_space(4.0);
+    ui.label("Messages to scan");
+    ui.add_enabled_ui(!busy, |ui| {
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("All").clicked() {
+                state.search_query = "ALL".to_string();
+            }
+            if ui.button("Unread").clicked() {
+                state.search_query = "UNSEEN".to_string();
+            }
+            if ui.button("Last 30 days").clicked() {
+                let since = chrono::Local::now().date_naive() - chrono::Days::new(30);
+                state.search_query = format!("SINCE {}", since.format("%d-%b-%Y"));
+            }
+            if ui.button("Flagged").clicked() {
+                state.search_query = "FLAGGED".to_string();
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut state.search_query).hint_text("IMAP SEARCH criteria"));
+    });
+
+    match validate_search_query(&state.search_query) {
+        Ok(()) => pattern_valid && headers_valid,
+        Err(msg) => {
+            ui.colored_label(egui::Color32::RED, msg);
+            false
+        }
+    }
+}
 
-    let can_scan = !busy && !state.email.is_empty() && state.email.contains('@') && !state.password.is_empty();
-    if ui
-        .add_enabled(can_scan, egui::Button::new("Start Scan"))
-        .clicked()
-    {
-        state.phase = AppPhase::Scanning;
-        state.scan_progress = 0.0;
-        state.scan_status = "Starting...".to_string();
-        state.error_message = None;
-        state.senders.clear();
-        state.sender_selected.clear();
-
-        let _ = cmd_tx.send(UiCommand::StartScan {
-            email: state.email.clone(),
-            password: state.password.clone(),
-            folder: state.folder.clone(),
-            scan_depth: state.scan_depth,
+/// Which header fields identify a sender. Returns false if none is picked.
+fn draw_sender_headers(ui: &mut Ui, state: &mut AppState, busy: bool) -> bool {
+    ui.label("Identify senders by")
+        .on_hover_text("Tried in this order; the first header a message has is used");
+    ui.add_enabled_ui(!busy, |ui| {
+        ui.horizontal(|ui| {
+            let headers = &mut state.sender_headers;
+            ui.checkbox(&mut headers.from, "From");
+            ui.checkbox(&mut headers.sender, "Sender");
+            ui.checkbox(&mut headers.list_id, "List-Id")
+                .on_hover_text("Uncheck From to split mail relayed through one address by list");
         });
+    });
+    if state.sender_headers.is_empty() {
+        ui.colored_label(egui::Color32::RED, "Pick at least one header");
+        return false;
     }
+    true
+}
 
-    ui.add_space(8.0);
-    ui.separator();
-    ui.add_space(4.0);
+/// Overrides for the Sent and spam folders, plus a check that those and
+/// the trash folder exist under the names the app would use.
+fn draw_special_folders(
+    ui: &mut Ui,
+    state: &mut AppState,
+    cmd_tx: &UnboundedSender<UiCommand>,
+    busy: bool,
+) {
+    ui.collapsing("Special folders", |ui| {
+        let provider = ImapProvider::from_email(&state.email);
+        ui.add_enabled_ui(!busy, |ui| {
+            ui.label("Sent folder");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.sent_folder).hint_text(provider.sent_folder),
+            );
+            ui.label("Spam folder");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.junk_folder).hint_text(provider.junk_folder),
+            );
+        })
+        .response
+        .on_hover_text("Leave empty for the provider default. Set them for localized names");
 
-    ui.label("Delete Mode");
-    ui.radio_value(&mut state.delete_mode, DeleteMode::Trash, "Move to Trash");
-    ui.radio_value(
-        &mut state.delete_mode,
-        DeleteMode::Permanent,
-        "Permanently Delete",
-    );
+        let checking = state.folder_check == Some(FolderCheck::Checking);
+        if ui
+            .add_enabled(
+                !busy && !checking && state.has_credentials(),
+                egui::Button::new("Check folders"),
+            )
+            .on_hover_text("Looks up the Trash, Sent and Spam folders on the server")
+            .clicked()
+        {
+            let request = FolderCheckRequest {
+                credentials: state.credentials(),
+                folders: state.special_folders(),
+                connection: state.connection_options(),
+            };
+            if cmd_tx.send(UiCommand::CheckFolders(request)).is_ok() {
+                state.folder_check = Some(FolderCheck::Checking);
+            }
+        }
+        match &state.folder_check {
+            None => {}
+            Some(FolderCheck::Checking) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Listing folders...");
+                });
+            }
+            Some(FolderCheck::Done(missing)) if missing.is_empty() => {
+                ui.label("✔ All special folders found");
+            }
+            Some(FolderCheck::Done(missing)) => {
+                ui.colored_label(
+                    egui::Color32::ORANGE,
+                    format!("⚠ Not on the server: {}", missing.join(", ")),
+                );
+            }
+            Some(FolderCheck::Failed(err)) => {
+                ui.colored_label(egui::Color32::RED, err);
+            }
+        }
+    });
+}
+
+/// Runs the connection one phase at a time and lists how each went, for
+/// working out where a failing sign-in breaks.
+fn draw_diagnostics(ui: &mut Ui, state: &mut AppState, cmd_tx: &UnboundedSender<UiCommand>) {
+    ui.collapsing("Connection diagnostics", |ui| {
+        let running = state.diagnosis == Some(Diagnosis::Running);
+        let button = egui::Button::new("Diagnose connection");
+        if ui
+            .add_enabled(!running && state.has_credentials(), button)
+            .on_hover_text("Checks the server lookup, TCP, TLS and login as separate steps")
+            .clicked()
+        {
+            let request = DiagnoseRequest {
+                credentials: state.credentials(),
+                connection: state.connection_options(),
+            };
+            if cmd_tx.send(UiCommand::Diagnose(request)).is_ok() {
+                state.diagnosis = Some(Diagnosis::Running);
+            }
+        }
+        match &state.diagnosis {
+            None => {}
+            Some(Diagnosis::Running) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Diagnosing...");
+                });
+            }
+            Some(Diagnosis::Done(steps)) => {
+                for step in steps {
+                    match &step.outcome {
+                        Ok(detail) => ui.label(format!("✔ {}: {detail}", step.name)),
+                        Err(reason) => ui.colored_label(
+                            egui::Color32::RED,
+                            format!("✖ {}: {reason}", step.name),
+                        ),
+                    };
+                }
+                if ui.button("Copy report").clicked() {
+                    ui.ctx().copy_text(diagnose::report(steps));
+                }
+            }
+        }
+    });
+}
+
+fn draw_network_settings(ui: &mut Ui, state: &mut AppState, busy: bool) {
+    ui.collapsing("SOCKS5 Proxy", |ui| {
+        ui.add_enabled(!busy, egui::Checkbox::new(&mut state.proxy_enabled, "Connect through proxy"));
+        ui.add_enabled_ui(!busy && state.proxy_enabled, |ui| {
+            ui.label("Host");
+            ui.text_edit_singleline(&mut state.proxy.host);
+            ui.label("Port");
+            ui.add(egui::DragValue::new(&mut state.proxy.port).range(1..=65535));
+            ui.label("Username (optional)");
+            ui.text_edit_singleline(&mut state.proxy.username);
+            ui.label("Password (optional)");
+            ui.add(egui::TextEdit::singleline(&mut state.proxy.password).password(true));
+        });
+    });
+
+    ui.collapsing("TLS", |ui| {
+        ui.add_enabled_ui(!busy, |ui| {
+            ui.label("Extra CA certificate (PEM path)");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.tls.ca_cert_path)
+                    .hint_text("/path/to/ca.pem"),
+            );
+            ui.checkbox(
+                &mut state.tls.accept_invalid_certs,
+                "Allow invalid certificates (testing only)",
+            );
+            ui.label("Client certificate (PKCS#12 or PEM path)");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.tls.client_cert_path)
+                    .hint_text("/path/to/client.p12"),
+            )
+            .on_hover_text("For servers that require mutual TLS");
+            ui.label("Client key (PEM, empty for PKCS#12)");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.tls.client_key_path)
+                    .hint_text("/path/to/client.key"),
+            );
+            ui.label("Certificate password (PKCS#12)");
+            ui.add(egui::TextEdit::singleline(&mut state.tls.client_cert_password).password(true));
+        });
+        if state.tls.accept_invalid_certs {
+            ui.colored_label(
+                egui::Color32::RED,
+                "Certificate validation is OFF. Connections can be intercepted.",
+            );
+        }
+    });
 }